@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
@@ -7,19 +7,192 @@ use crate::{
     evaluator::*,
 };
 
+// Size of the sheet, the same as Excel's. References outside it are rejected so ranges and
+// computed references can't overflow or walk billions of cells.
+pub const MAX_ROWS: usize = 1 << 20;
+pub const MAX_COLS: usize = 1 << 14;
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct CellRef {
     pub row: usize,
     pub col: usize,
 }
 
+// A1 notation
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", col_name(self.col), self.row + 1)
+    }
+}
+
+// The letters of a zero indexed column, e.g. AA for 26
+pub fn col_name(col: usize) -> String {
+    let mut letters = Vec::new();
+    let mut col = col + 1;
+    while col > 0 {
+        letters.push((b'A' + ((col - 1) % 26) as u8) as char);
+        col = (col - 1) / 26;
+    }
+
+    letters.iter().rev().collect()
+}
+
+// A rectangle of cells, start is the top left and end the bottom right
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl CellRange {
+    // Between any two opposite corners
+    pub fn new(a: CellRef, b: CellRef) -> CellRange {
+        CellRange {
+            start: CellRef {
+                row: a.row.min(b.row),
+                col: a.col.min(b.col),
+            },
+            end: CellRef {
+                row: a.row.max(b.row),
+                col: a.col.max(b.col),
+            },
+        }
+    }
+
+    // A1 notation, e.g. "A1:C3", or a single cell
+    pub fn parse(s: &str) -> Result<CellRange, LeadErr> {
+        match s.split_once(':') {
+            Some((a, b)) => Ok(CellRange::new(
+                CellRef::new(a.into())?,
+                CellRef::new(b.into())?,
+            )),
+            None => {
+                let cell = CellRef::new(s.into())?;
+                Ok(CellRange::new(cell, cell))
+            }
+        }
+    }
+
+    pub fn contains(&self, cell: CellRef) -> bool {
+        (self.start.row..=self.end.row).contains(&cell.row)
+            && (self.start.col..=self.end.col).contains(&cell.col)
+    }
+
+    // Number of cells
+    pub fn len(&self) -> usize {
+        (self.end.row - self.start.row + 1) * (self.end.col - self.start.col + 1)
+    }
+
+    // Row by row
+    pub fn cells(&self) -> impl Iterator<Item = CellRef> + use<> {
+        let CellRange { start, end } = *self;
+        (start.row..=end.row)
+            .flat_map(move |row| (start.col..=end.col).map(move |col| CellRef { row, col }))
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
+// A whole column such as C or row such as 1, zero indexed. Two of them make a range to the
+// edges of the sheet, e.g. C:C is C1:C1048576 and 1:2 is A1:XFD2.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line {
+    Col(usize),
+    Row(usize),
+}
+
+impl Line {
+    // Column letters or a row number, the $ of an absolute line such as $C is ignored
+    pub fn parse(s: &str) -> Option<Line> {
+        let s = s.strip_prefix('$').unwrap_or(s);
+        if s.is_empty() {
+            None
+        } else if s.chars().all(|c| c.is_ascii_alphabetic()) {
+            CellRef::new(format!("{s}1"))
+                .ok()
+                .map(|it| Line::Col(it.col))
+        } else if s.chars().all(|c| c.is_ascii_digit()) {
+            let row = s.parse::<usize>().ok()?;
+            (1..=MAX_ROWS).contains(&row).then(|| Line::Row(row - 1))
+        } else {
+            None
+        }
+    }
+
+    // None for a column and a row
+    pub fn range(a: Line, b: Line) -> Option<CellRange> {
+        match (a, b) {
+            (Line::Col(a), Line::Col(b)) => Some(CellRange::new(
+                CellRef { row: 0, col: a },
+                CellRef {
+                    row: MAX_ROWS - 1,
+                    col: b,
+                },
+            )),
+            (Line::Row(a), Line::Row(b)) => Some(CellRange::new(
+                CellRef { row: a, col: 0 },
+                CellRef {
+                    row: b,
+                    col: MAX_COLS - 1,
+                },
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Col(col) => write!(f, "{}", col_name(*col)),
+            Line::Row(row) => write!(f, "{}", row + 1),
+        }
+    }
+}
+
+// The array returned by a formula and where it went. The anchor cell shows the top left value
+// and the rest spill into the cells below and to the right.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spill {
+    pub array: Array,
+    // Occupied cells in the way of the array, empty when it spilled
+    pub blocked_by: Vec<CellRef>,
+}
+
+impl Spill {
+    pub fn is_blocked(&self) -> bool {
+        !self.blocked_by.is_empty()
+    }
+
+    // Shown in the anchor cell while blocked
+    pub fn err(&self) -> LeadErr {
+        LeadErr {
+            title: "Spill error.".into(),
+            desc: format!(
+                "Array of {}x{} can't spill, {} cell(s) such as {} are in the way.",
+                self.array.rows,
+                self.array.cols,
+                self.blocked_by.len(),
+                self.blocked_by[0]
+            ),
+            code: LeadErrCode::Spill,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Cell {
+    #[allow(dead_code)]
     reference: CellRef,
     eval: Eval,
     raw: String,
-    precedents: HashSet<CellRef>, // Cells that this cell reads
-    dependents: HashSet<CellRef>, // Cells that read this cell
+    formula: Option<Formula>,      // Compiled from raw when it starts with =
+    spill: Option<Spill>,          // Set on cells whose formula returned an array
+    spill_anchor: Option<CellRef>, // Set on the read-only cells an array spilled into
 }
 
 impl Cell {
@@ -27,77 +200,73 @@ impl Cell {
         Self {
             reference,
             eval,
+            formula: compile(&raw),
             raw,
-            precedents: HashSet::new(),
-            dependents: HashSet::new(),
-        }
-    }
-
-    pub fn new_all(
-        reference: CellRef,
-        eval: Eval,
-        raw: String,
-        precedents: HashSet<CellRef>,
-        dependents: HashSet<CellRef>,
-    ) -> Self {
-        Self {
-            reference,
-            eval,
-            raw,
-            precedents,
-            dependents,
+            spill: None,
+            spill_anchor: None,
         }
     }
 
     pub fn raw(&self) -> String {
         self.raw.to_owned()
     }
+    pub fn formula(&self) -> Option<&Formula> {
+        self.formula.as_ref()
+    }
     pub fn eval(&self) -> Eval {
         self.eval.to_owned()
     }
+    // The value of the formula, for anchors this is the whole array rather than the top left
+    // value shown in the cell
+    pub fn result(&self) -> Eval {
+        match &self.spill {
+            Some(spill) => Eval::Range(spill.array.to_owned()),
+            None => self.eval.to_owned(),
+        }
+    }
+    pub fn spill(&self) -> Option<&Spill> {
+        self.spill.as_ref()
+    }
+    pub fn spill_anchor(&self) -> Option<CellRef> {
+        self.spill_anchor
+    }
+    // Empty and not part of an array, nothing but its edges would be lost by removing it
+    pub fn is_blank(&self) -> bool {
+        self.raw.is_empty() && self.spill.is_none() && self.spill_anchor.is_none()
+    }
+    #[allow(dead_code)]
     pub fn reference(&self) -> CellRef {
         self.reference.to_owned()
     }
 
     pub fn set_raw(&mut self, raw: String) {
+        self.formula = compile(&raw);
         self.raw = raw;
     }
     pub fn set_eval(&mut self, eval: Eval) {
         self.eval = eval;
     }
-    pub fn set_ref(&mut self, reference: CellRef) {
-        self.reference = reference;
-    }
-
-    pub fn add_dep(&mut self, it: CellRef) {
-        self.dependents.insert(it);
+    pub fn set_spill(&mut self, spill: Option<Spill>) {
+        self.spill = spill;
     }
-
-    pub fn remove_dep(&mut self, it: &CellRef) {
-        self.dependents.remove(&it);
-    }
-
-    pub fn add_prec(&mut self, it: CellRef) {
-        self.precedents.insert(it);
+    pub fn set_spill_anchor(&mut self, anchor: Option<CellRef>) {
+        self.spill_anchor = anchor;
     }
-
-    pub fn set_precs(&mut self, it: HashSet<CellRef>) {
-        self.precedents = it;
-    }
-
-    pub fn deps(&self) -> HashSet<CellRef> {
-        self.dependents.to_owned()
+    #[allow(dead_code)]
+    pub fn set_ref(&mut self, reference: CellRef) {
+        self.reference = reference;
     }
+}
 
-    pub fn precs(&self) -> HashSet<CellRef> {
-        self.precedents.to_owned()
-    }
+fn compile(raw: &str) -> Option<Formula> {
+    raw.strip_prefix('=').map(Formula::compile)
 }
 
 impl CellRef {
-    // Zero indexed
+    // Zero indexed, the $ of absolute references such as $A$1 are ignored
     pub fn new(s: String) -> Result<CellRef, LeadErr> {
-        let s = s.trim();
+        let s = s.trim().replace('$', "");
+        let s = s.as_str();
         let mut col: usize = 0;
         let mut i = 0;
 
@@ -108,12 +277,20 @@ impl CellRef {
                 let val = (u - b'A' + 1) as usize; // A->1 ... Z->26
                 col = col * 26 + val;
                 i = idx + ch.len_utf8();
+
+                if col > MAX_COLS {
+                    return Err(LeadErr {
+                        title: "Parse error.".into(),
+                        desc: format!("Column is outside the sheet in cell ref: {s}."),
+                        code: LeadErrCode::Syntax,
+                    });
+                }
             } else {
                 break;
             }
         }
 
-        if col <= 0 {
+        if col == 0 {
             return Err(LeadErr {
                 title: "Parse error.".into(),
                 desc: format!("Missing column letters in cell ref: {s}."),
@@ -121,11 +298,12 @@ impl CellRef {
             });
         }
 
+        // Whole columns such as C:C are read as ranges by the tokenizer
         let row_part = &s[i..];
         if row_part.is_empty() {
             return Err(LeadErr {
                 title: "Parse error.".into(),
-                desc: format!("Missing column letters in cell ref: {s}."),
+                desc: format!("Missing row number in cell ref: {s}."),
                 code: LeadErrCode::Syntax,
             });
         } else if !row_part.chars().all(|c| c.is_ascii_digit()) {
@@ -136,7 +314,9 @@ impl CellRef {
             });
         }
 
-        if let Ok(row) = row_part.parse::<usize>() {
+        if let Ok(row) = row_part.parse::<usize>()
+            && (1..=MAX_ROWS).contains(&row)
+        {
             Ok(CellRef {
                 row: row - 1,
                 col: col - 1,
@@ -144,7 +324,7 @@ impl CellRef {
         } else {
             Err(LeadErr {
                 title: "Parse error.".into(),
-                desc: format!("Invalid row number."),
+                desc: "Invalid row number.".into(),
                 code: LeadErrCode::Syntax,
             })
         }
@@ -21,6 +21,10 @@ pub enum LeadErrCode {
     Unsupported,
     Invalid,
     Ref,
+    // An array could not spill because cells in its way are occupied
+    Spill,
+    // A value is missing, e.g. padding when stacking arrays of different sizes
+    NotAvailable,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -29,4 +33,6 @@ pub enum Literal {
     Number(f64),
     Boolean(bool),
     String(String),
+    // Serial date, see evaluator/datetime.rs
+    DateTime(f64),
 }
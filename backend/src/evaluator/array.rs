@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::evaluator::Eval;
+
+// A rectangular block of values stored row-major. Ranges keep the shape of the area they
+// reference, with Eval::CellRef elements, while functions returning several values build arrays
+// of plain values which spill into the neighbouring cells.
+#[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct Array {
+    pub rows: usize,
+    pub cols: usize,
+    pub values: Vec<Eval>,
+}
+
+impl Array {
+    pub fn new(rows: usize, cols: usize, values: Vec<Eval>) -> Array {
+        debug_assert_eq!(rows * cols, values.len());
+        Array { rows, cols, values }
+    }
+
+    // Rows must all have the same length
+    pub fn from_rows(rows: Vec<Vec<Eval>>) -> Array {
+        let cols = rows.first().map_or(0, |it| it.len());
+        Array::new(rows.len(), cols, rows.into_iter().flatten().collect())
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> &Eval {
+        &self.values[row * self.cols + col]
+    }
+
+    pub fn row(&self, row: usize) -> &[Eval] {
+        &self.values[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Eval]> {
+        self.values.chunks(self.cols.max(1))
+    }
+
+    pub fn transpose(&self) -> Array {
+        let values = (0..self.cols)
+            .flat_map(|col| (0..self.rows).map(move |row| (row, col)))
+            .map(|(row, col)| self.get(row, col).to_owned())
+            .collect();
+
+        Array::new(self.cols, self.rows, values)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    // The values with cell references replaced by the values of the cells
+    pub fn unwrapped(self) -> Array {
+        let values = self
+            .values
+            .into_iter()
+            .map(|it| match it {
+                Eval::CellRef { eval, .. } => *eval,
+                it => it,
+            })
+            .collect();
+
+        Array::new(self.rows, self.cols, values)
+    }
+}
@@ -0,0 +1,730 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Array, Eval, Precedents, counting::UniqueKey, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Dynamic array functions. Most of them work on rows, functions working on columns transpose
+// their input, apply the row version and transpose the result back.
+
+fn empty_err(func_name: &str) -> LeadErr {
+    eval_err(
+        format!("{func_name} result is empty."),
+        LeadErrCode::Invalid,
+    )
+}
+
+// Optional boolean argument, false when omitted
+fn eval_flag(
+    args: &[Expr],
+    index: usize,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<bool, LeadErr> {
+    match args.get(index) {
+        Some(arg) => Ok(eval_number(arg, precs, grid, func_name)? != 0.0),
+        None => Ok(false),
+    }
+}
+
+fn eval_int(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<i64, LeadErr> {
+    Ok(eval_number(arg, precs, grid, func_name)?.trunc() as i64)
+}
+
+fn value_number(value: &Eval, func_name: &str) -> Result<f64, LeadErr> {
+    match value {
+        Eval::Literal(lit) => coerce_number(lit),
+        Eval::Err(e) => return Err(e.to_owned()),
+        _ => None,
+    }
+    .ok_or(eval_err(
+        format!("{func_name} function requires numeric argument(s)."),
+        LeadErrCode::TypeErr,
+    ))
+}
+
+fn truthy(value: &Eval, func_name: &str) -> Result<bool, LeadErr> {
+    match value {
+        Eval::Literal(Literal::Boolean(b)) => Ok(*b),
+        Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => Ok(*n != 0.0),
+        Eval::Unset => Ok(false),
+        Eval::Err(e) => Err(e.to_owned()),
+        _ => Err(eval_err(
+            format!("{func_name} requires boolean or numeric include values."),
+            LeadErrCode::TypeErr,
+        )),
+    }
+}
+
+fn by_rows(
+    array: Array,
+    by_col: bool,
+    func: impl FnOnce(Array) -> Result<Array, LeadErr>,
+) -> Result<Array, LeadErr> {
+    if by_col {
+        Ok(func(array.transpose())?.transpose())
+    } else {
+        func(array)
+    }
+}
+
+// -------------------------------------------------- //
+
+// Blanks sort last in either direction
+fn compare_keys(a: &Eval, b: &Eval, descending: bool) -> Ordering {
+    match (a, b) {
+        (Eval::Unset, Eval::Unset) => Ordering::Equal,
+        (Eval::Unset, _) => Ordering::Greater,
+        (_, Eval::Unset) => Ordering::Less,
+        _ if descending => compare_values(b, a),
+        _ => compare_values(a, b),
+    }
+}
+
+// Stable sort of the rows by a list of (key per row, descending) pairs
+fn sort_rows(array: &Array, keys: &[(Vec<Eval>, bool)]) -> Array {
+    let mut order: Vec<usize> = (0..array.rows).collect();
+    order.sort_by(|&a, &b| {
+        keys.iter()
+            .map(|(key, descending)| compare_keys(&key[a], &key[b], *descending))
+            .find(|it| it.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+
+    Array::from_rows(order.iter().map(|&row| array.row(row).to_vec()).collect())
+}
+
+fn is_descending(value: &Eval, func_name: &str) -> Result<bool, LeadErr> {
+    match value_number(value, func_name)? {
+        1.0 => Ok(false),
+        -1.0 => Ok(true),
+        _ => Err(eval_err(
+            format!("{func_name} order must be 1 (ascending) or -1 (descending)."),
+            LeadErrCode::Invalid,
+        )),
+    }
+}
+
+pub fn eval_sort(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?;
+    let indices = match args.get(1) {
+        Some(arg) => eval_array(arg, precs, grid)?.values,
+        None => vec![Eval::Literal(Literal::Number(1.0))],
+    };
+    let orders = match args.get(2) {
+        Some(arg) => eval_array(arg, precs, grid)?.values,
+        None => vec![Eval::Literal(Literal::Number(1.0))],
+    };
+    let by_col = eval_flag(args, 3, precs, grid, "SORT")?;
+
+    if orders.len() != 1 && orders.len() != indices.len() {
+        return Err(eval_err(
+            "SORT requires one order or an order for each sort index.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let sorted = by_rows(array, by_col, |array| {
+        let mut keys = Vec::with_capacity(indices.len());
+
+        for (i, index) in indices.iter().enumerate() {
+            let index = value_number(index, "SORT")?.trunc();
+            if index < 1.0 || index > array.cols as f64 {
+                return Err(eval_err(
+                    format!("SORT index {index} is outside the array."),
+                    LeadErrCode::Invalid,
+                ));
+            }
+
+            let col = index as usize - 1;
+            let key = (0..array.rows).map(|row| array.get(row, col).to_owned());
+            let order = &orders[if orders.len() == 1 { 0 } else { i }];
+            keys.push((key.collect(), is_descending(order, "SORT")?));
+        }
+
+        Ok(sort_rows(&array, &keys))
+    })?;
+
+    Ok(Eval::Range(sorted))
+}
+
+// SORTBY(array, by1, [order1], [by2, order2], ...)
+pub fn eval_sortby(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?;
+    let mut keys = Vec::new();
+    let mut by_col = None;
+
+    for pair in args[1..].chunks(2) {
+        let by = eval_array(&pair[0], precs, grid)?;
+        let descending = match pair.get(1) {
+            Some(arg) => is_descending(&eval_array(arg, precs, grid)?.values[0], "SORTBY")?,
+            None => false,
+        };
+
+        // A column sorts the rows of the array and a row sorts its columns
+        let sorts_cols = if by.cols == 1 && by.rows == array.rows {
+            false
+        } else if by.rows == 1 && by.cols == array.cols {
+            true
+        } else {
+            return Err(eval_err(
+                "SORTBY requires each sort array to be a row or column matching the array.".into(),
+                LeadErrCode::Invalid,
+            ));
+        };
+
+        if by_col.is_some_and(|it| it != sorts_cols) {
+            return Err(eval_err(
+                "SORTBY requires all sort arrays to have the same orientation.".into(),
+                LeadErrCode::Invalid,
+            ));
+        }
+
+        by_col = Some(sorts_cols);
+        keys.push((by.values, descending));
+    }
+
+    let sorted = by_rows(array, by_col.unwrap_or(false), |array| {
+        Ok(sort_rows(&array, &keys))
+    })?;
+
+    Ok(Eval::Range(sorted))
+}
+
+// -------------------------------------------------- //
+
+pub fn eval_sequence(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let rows = eval_int(&args[0], precs, grid, "SEQUENCE")?;
+    let cols = match args.get(1) {
+        Some(arg) => eval_int(arg, precs, grid, "SEQUENCE")?,
+        None => 1,
+    };
+    let start = match args.get(2) {
+        Some(arg) => eval_number(arg, precs, grid, "SEQUENCE")?,
+        None => 1.0,
+    };
+    let step = match args.get(3) {
+        Some(arg) => eval_number(arg, precs, grid, "SEQUENCE")?,
+        None => 1.0,
+    };
+
+    if rows < 1 || cols < 1 {
+        return Err(eval_err(
+            "SEQUENCE requires at least one row and column.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+    let (rows, cols) = (rows as usize, cols as usize);
+    check_cells(rows, cols, "SEQUENCE")?;
+
+    // The values are linear in i, with a finite start they are all finite when the last one is
+    if !(start + step * (rows * cols - 1) as f64).is_finite() {
+        return Err(eval_err(
+            "SEQUENCE result is not a finite number.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let values = (0..rows * cols)
+        .map(|i| Eval::Literal(Literal::Number(start + step * i as f64)))
+        .collect();
+
+    Ok(Eval::Range(Array::new(rows, cols, values)))
+}
+
+pub fn eval_filter(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?;
+    let include = eval_array(&args[1], precs, grid)?;
+
+    let by_col = if include.cols == 1 && include.rows == array.rows {
+        false
+    } else if include.rows == 1 && include.cols == array.cols {
+        true
+    } else {
+        return Err(eval_err(
+            "FILTER requires the include array to be a row or column matching the array.".into(),
+            LeadErrCode::Invalid,
+        ));
+    };
+
+    let mut keep = Vec::with_capacity(include.values.len());
+    for value in &include.values {
+        keep.push(truthy(value, "FILTER")?);
+    }
+
+    if !keep.contains(&true) {
+        return match args.get(2) {
+            Some(arg) => Ok(Eval::Range(eval_array(arg, precs, grid)?)),
+            None => Err(empty_err("FILTER")),
+        };
+    }
+
+    let filtered = by_rows(array, by_col, |array| {
+        let rows = array
+            .rows()
+            .zip(&keep)
+            .filter(|(_, keep)| **keep)
+            .map(|(row, _)| row.to_vec())
+            .collect();
+        Ok(Array::from_rows(rows))
+    })?;
+
+    Ok(Eval::Range(filtered))
+}
+
+pub fn eval_unique(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?;
+    let by_col = eval_flag(args, 1, precs, grid, "UNIQUE")?;
+    let exactly_once = eval_flag(args, 2, precs, grid, "UNIQUE")?;
+
+    let unique = by_rows(array, by_col, |array| {
+        let keys: Vec<Vec<Option<UniqueKey>>> = array
+            .rows()
+            .map(|row| row.iter().map(UniqueKey::of).collect())
+            .collect();
+
+        let mut counts: HashMap<&Vec<Option<UniqueKey>>, usize> = HashMap::new();
+        for key in &keys {
+            *counts.entry(key).or_default() += 1;
+        }
+
+        // First occurrences in their original order
+        let mut seen = HashSet::new();
+        let rows: Vec<Vec<Eval>> = array
+            .rows()
+            .zip(&keys)
+            .filter(|(_, key)| seen.insert(*key) && (!exactly_once || counts[key] == 1))
+            .map(|(row, _)| row.to_vec())
+            .collect();
+
+        if rows.is_empty() {
+            return Err(empty_err("UNIQUE"));
+        }
+        Ok(Array::from_rows(rows))
+    })?;
+
+    Ok(Eval::Range(unique))
+}
+
+pub fn eval_transpose(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    Ok(Eval::Range(eval_array(&args[0], precs, grid)?.transpose()))
+}
+
+// -------------------------------------------------- //
+
+// Stacks arrays on top of each other, narrower arrays are padded with #N/A
+fn vstack(arrays: Vec<Array>, func_name: &str) -> Result<Array, LeadErr> {
+    let cols = arrays.iter().map(|it| it.cols).max().unwrap_or(0);
+    check_cells(arrays.iter().map(|it| it.rows).sum(), cols, func_name)?;
+    let rows = arrays
+        .iter()
+        .flat_map(|array| {
+            array.rows().map(|row| {
+                let mut row = row.to_vec();
+                row.resize_with(cols, not_available);
+                row
+            })
+        })
+        .collect();
+
+    Ok(Array::from_rows(rows))
+}
+
+fn eval_arrays(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Vec<Array>, LeadErr> {
+    args.iter()
+        .map(|arg| eval_array(arg, precs, grid))
+        .collect()
+}
+
+pub fn eval_vstack(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    Ok(Eval::Range(vstack(
+        eval_arrays(args, precs, grid)?,
+        "VSTACK",
+    )?))
+}
+
+pub fn eval_hstack(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let arrays = eval_arrays(args, precs, grid)?;
+    let transposed = arrays.iter().map(Array::transpose).collect();
+    Ok(Eval::Range(vstack(transposed, "HSTACK")?.transpose()))
+}
+
+// The first n rows, or the last -n rows when n is negative
+fn take_rows(array: Array, n: i64) -> Array {
+    let count = (n.unsigned_abs() as usize).min(array.rows);
+    let skip = if n < 0 { array.rows - count } else { 0 };
+    Array::from_rows(
+        array
+            .rows()
+            .skip(skip)
+            .take(count)
+            .map(|it| it.to_vec())
+            .collect(),
+    )
+}
+
+// All but the first n rows, or all but the last -n rows when n is negative
+fn drop_rows(array: Array, n: i64) -> Array {
+    let count = (n.unsigned_abs() as usize).min(array.rows);
+    let skip = if n < 0 { 0 } else { count };
+    let kept = array.rows - count;
+    Array::from_rows(
+        array
+            .rows()
+            .skip(skip)
+            .take(kept)
+            .map(|it| it.to_vec())
+            .collect(),
+    )
+}
+
+fn eval_take_drop(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: fn(Array, i64) -> Array,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?;
+    let rows = eval_int(&args[1], precs, grid, func_name)?;
+    let cols = match args.get(2) {
+        Some(arg) => Some(eval_int(arg, precs, grid, func_name)?),
+        None => None,
+    };
+
+    let mut res = func(array, rows);
+    if let Some(cols) = cols {
+        res = func(res.transpose(), cols).transpose();
+    }
+
+    if res.is_empty() {
+        return Err(empty_err(func_name));
+    }
+    Ok(Eval::Range(res))
+}
+
+pub fn eval_take(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    eval_take_drop(args, precs, grid, take_rows, "TAKE")
+}
+
+pub fn eval_drop(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    eval_take_drop(args, precs, grid, drop_rows, "DROP")
+}
+
+// Negative indices count from the last column
+pub fn eval_choosecols(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let array = eval_array(&args[0], precs, grid)?.transpose();
+    let mut cols = Vec::with_capacity(args.len() - 1);
+
+    for arg in &args[1..] {
+        let index = eval_int(arg, precs, grid, "CHOOSECOLS")?;
+        let col = match index {
+            1.. => index - 1,
+            ..0 => array.rows as i64 + index,
+            0 => -1,
+        };
+
+        if col < 0 || col >= array.rows as i64 {
+            return Err(eval_err(
+                format!("CHOOSECOLS index {index} is outside the array."),
+                LeadErrCode::Invalid,
+            ));
+        }
+        cols.push(array.row(col as usize).to_vec());
+    }
+
+    Ok(Eval::Range(Array::from_rows(cols).transpose()))
+}
+
+// Sum of the products of corresponding elements, values that are not numbers count as zero
+pub fn eval_sumproduct(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let arrays = eval_arrays(args, precs, grid)?;
+    let (rows, cols) = (arrays[0].rows, arrays[0].cols);
+
+    if arrays.iter().any(|it| it.rows != rows || it.cols != cols) {
+        return Err(eval_err(
+            "SUMPRODUCT requires arrays of the same size.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let mut total = 0.0;
+    for i in 0..rows * cols {
+        let mut product = 1.0;
+        for array in &arrays {
+            product *= match &array.values[i] {
+                Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => *n,
+                Eval::Err(e) => return Err(e.to_owned()),
+                _ => 0.0,
+            };
+        }
+        total += product;
+    }
+
+    Ok(Eval::Literal(Literal::Number(total)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    fn text(s: &str) -> Eval {
+        Eval::Literal(Literal::String(s.into()))
+    }
+
+    fn nums(rows: usize, cols: usize, values: &[f64]) -> Eval {
+        Eval::Range(Array::new(
+            rows,
+            cols,
+            values.iter().map(|n| num(*n)).collect(),
+        ))
+    }
+
+    #[test]
+    fn test_sequence() {
+        let eval = |s: &str| evaluate(s.into(), None).0;
+
+        assert_eq!(eval("SEQUENCE(3)"), nums(3, 1, &[1.0, 2.0, 3.0]));
+        assert_eq!(
+            eval("SEQUENCE(2, 3, 0, 2)"),
+            nums(2, 3, &[0.0, 2.0, 4.0, 6.0, 8.0, 10.0])
+        );
+        assert_eq!(eval("TRANSPOSE(SEQUENCE(2))"), nums(1, 2, &[1.0, 2.0]));
+        assert!(is_err(eval("SEQUENCE(0)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("SEQUENCE(2000, 2000)"), LeadErrCode::Invalid));
+        assert!(is_err(
+            eval("SEQUENCE(2, 2, 1, 1e308)"),
+            LeadErrCode::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_sort() {
+        let grid = grid_from(&[
+            &["b", "2", "x"],
+            &["a", "3", "y"],
+            &["C", "1", "x"],
+            &["a", "1", "z"],
+        ]);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        let col = |values: &[&str]| {
+            Eval::Range(Array::new(
+                values.len(),
+                1,
+                values.iter().map(|it| text(it)).collect(),
+            ))
+        };
+
+        assert_eq!(eval("SORT(A1:A4)"), col(&["a", "a", "b", "C"]));
+        assert_eq!(eval("SORT(A1:A4, 1, -1)"), col(&["C", "b", "a", "a"]));
+        assert_eq!(
+            eval("CHOOSECOLS(SORT(A1:C4, HSTACK(3, 2), HSTACK(1, -1)), 1)"),
+            col(&["b", "C", "a", "a"])
+        );
+        assert_eq!(eval("SORT(B1:B4)"), nums(4, 1, &[1.0, 1.0, 2.0, 3.0]));
+        assert_eq!(
+            eval("SORT(B1:B4, 1, -1)"),
+            nums(4, 1, &[3.0, 2.0, 1.0, 1.0])
+        );
+        assert_eq!(
+            eval("SORT(TRANSPOSE(B1:B4), 1, 1, true)"),
+            nums(1, 4, &[1.0, 1.0, 2.0, 3.0])
+        );
+        assert!(is_err(eval("SORT(A1:B4, 3)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("SORT(A1:B4, 1, 2)"), LeadErrCode::Invalid));
+
+        // Ties on the first key are broken by the second, descending
+        assert_eq!(
+            eval("CHOOSECOLS(SORTBY(A1:C4, C1:C4, 1, B1:B4, -1), 1)"),
+            col(&["b", "C", "a", "a"])
+        );
+        assert_eq!(eval("SORTBY(A1:A4, B1:B4)"), col(&["C", "a", "b", "a"]));
+        assert!(is_err(eval("SORTBY(A1:A4, B1:B3)"), LeadErrCode::Invalid));
+    }
+
+    #[test]
+    fn test_filter_unique() {
+        let grid = grid_from(&[
+            &["a", "1", "=true"],
+            &["b", "2", "=false"],
+            &["A", "1", "1"],
+            &["c", "3", "0"],
+        ]);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        assert_eq!(eval("FILTER(B1:B4, C1:C4)"), nums(2, 1, &[1.0, 1.0]));
+        assert_eq!(
+            eval("FILTER(TRANSPOSE(B1:B4), TRANSPOSE(C1:C4))"),
+            nums(1, 2, &[1.0, 1.0])
+        );
+        assert!(is_err(eval("FILTER(B1:B4, C1:C2)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("FILTER(B2, C2)"), LeadErrCode::Invalid));
+        assert_eq!(eval("FILTER(B2, C2, 0)"), nums(1, 1, &[0.0]));
+        assert!(is_err(eval("FILTER(B1:B4, A1:A4)"), LeadErrCode::TypeErr));
+
+        assert_eq!(eval("UNIQUE(B1:B4)"), nums(3, 1, &[1.0, 2.0, 3.0]));
+        assert_eq!(eval("UNIQUE(B1:B4, false, true)"), nums(2, 1, &[2.0, 3.0]));
+        // Text is compared case-insensitively, the first occurrence is kept
+        assert_eq!(
+            eval("UNIQUE(A1:B4)"),
+            Eval::Range(Array::from_rows(vec![
+                vec![text("a"), num(1.0)],
+                vec![text("b"), num(2.0)],
+                vec![text("c"), num(3.0)],
+            ]))
+        );
+        assert_eq!(
+            eval("UNIQUE(TRANSPOSE(B1:B4), true)"),
+            nums(1, 3, &[1.0, 2.0, 3.0])
+        );
+        assert!(is_err(
+            eval("UNIQUE(SEQUENCE(2, 1, 1, 0), false, true)"),
+            LeadErrCode::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_reshaping() {
+        let eval = |s: &str| evaluate(s.into(), None).0;
+        let seq = "SEQUENCE(3, 3)";
+
+        assert_eq!(
+            eval(&format!("TAKE({seq}, 2)")),
+            nums(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0])
+        );
+        assert_eq!(
+            eval(&format!("TAKE({seq}, -1, -2)")),
+            nums(1, 2, &[8.0, 9.0])
+        );
+        assert_eq!(
+            eval(&format!("TAKE({seq}, 10, 1)")),
+            nums(3, 1, &[1.0, 4.0, 7.0])
+        );
+        assert_eq!(
+            eval(&format!("DROP({seq}, 1, -1)")),
+            nums(2, 2, &[4.0, 5.0, 7.0, 8.0])
+        );
+        assert_eq!(
+            eval(&format!("DROP({seq}, -2)")),
+            nums(1, 3, &[1.0, 2.0, 3.0])
+        );
+        assert!(is_err(
+            eval(&format!("DROP({seq}, 3)")),
+            LeadErrCode::Invalid
+        ));
+        assert!(is_err(
+            eval(&format!("TAKE({seq}, 0)")),
+            LeadErrCode::Invalid
+        ));
+
+        assert_eq!(
+            eval(&format!("CHOOSECOLS({seq}, -1, 1)")),
+            nums(3, 2, &[3.0, 1.0, 6.0, 4.0, 9.0, 7.0])
+        );
+        assert!(is_err(
+            eval(&format!("CHOOSECOLS({seq}, 0)")),
+            LeadErrCode::Invalid
+        ));
+        assert!(is_err(
+            eval(&format!("CHOOSECOLS({seq}, 4)")),
+            LeadErrCode::Invalid
+        ));
+
+        assert_eq!(
+            eval("VSTACK(SEQUENCE(1, 2), 5)"),
+            Eval::Range(Array::from_rows(vec![
+                vec![num(1.0), num(2.0)],
+                vec![num(5.0), not_available()],
+            ]))
+        );
+        assert_eq!(
+            eval("HSTACK(SEQUENCE(2), SEQUENCE(2, 1, 3))"),
+            nums(2, 2, &[1.0, 3.0, 2.0, 4.0])
+        );
+        assert!(is_err(
+            eval("VSTACK(SEQUENCE(1048576), 1)"),
+            LeadErrCode::Invalid
+        ));
+        assert!(is_err(
+            eval("HSTACK(SEQUENCE(1, 1048576), SEQUENCE(1, 1048576))"),
+            LeadErrCode::Invalid
+        ));
+    }
+
+    #[test]
+    fn test_array_spill() {
+        let mut grid = grid_from(&[&["3", "1"], &["1", "2"], &["2", "3"]]);
+        set_cells(&mut grid, &[("D1", "=SORT(A1:B3)")]);
+        assert_eq!(value(&grid, "D1"), num(1.0));
+        assert_eq!(value(&grid, "E3"), num(1.0));
+
+        set_cells(&mut grid, &[("A1", "0")]);
+        assert_eq!(value(&grid, "D1"), num(0.0));
+        assert_eq!(value(&grid, "E1"), num(1.0));
+    }
+}
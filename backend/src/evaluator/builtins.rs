@@ -0,0 +1,893 @@
+use crate::{
+    common::{LeadErr, LeadErrCode},
+    evaluator::{
+        arrays::*,
+        counting::*,
+        criteria::*,
+        datetime::*,
+        finance::*,
+        matrix::*,
+        numerics::*,
+        patterns::*,
+        random::*,
+        references::*,
+        registry::{ArgKind::*, Arity, Builtin},
+        regression::*,
+        stats::*,
+        utils::*,
+    },
+};
+
+// The functions every Grid starts with. Keep entries grouped by module.
+
+macro_rules! builtin {
+    ($name:expr, $arity:expr, $kinds:expr, $desc:expr, $eval:expr) => {
+        Builtin {
+            name: $name,
+            arity: $arity,
+            arg_kinds: &$kinds,
+            volatile: false,
+            description: $desc,
+            eval: $eval,
+        }
+    };
+}
+
+// Folded from 0.0 as an empty f64 sum is -0.0
+fn sum(nums: &[f64]) -> Result<f64, LeadErr> {
+    Ok(nums.iter().fold(0.0, |acc, n| acc + n))
+}
+
+fn max(nums: &[f64]) -> Result<f64, LeadErr> {
+    nums.iter().cloned().reduce(f64::max).ok_or(LeadErr {
+        title: "Evaluation error.".into(),
+        desc: "MAX on empty set.".into(),
+        code: LeadErrCode::Unsupported,
+    })
+}
+
+fn min(nums: &[f64]) -> Result<f64, LeadErr> {
+    nums.iter().cloned().reduce(f64::min).ok_or(LeadErr {
+        title: "Evaluation error.".into(),
+        desc: "MIN on empty set.".into(),
+        code: LeadErrCode::Unsupported,
+    })
+}
+
+// Like Excel, MAXIFS and MINIFS return 0 when no cells match
+fn max_or_zero(nums: &[f64]) -> Result<f64, LeadErr> {
+    Ok(nums.iter().cloned().reduce(f64::max).unwrap_or(0.0))
+}
+
+fn min_or_zero(nums: &[f64]) -> Result<f64, LeadErr> {
+    Ok(nums.iter().cloned().reduce(f64::min).unwrap_or(0.0))
+}
+
+pub fn builtins() -> Vec<Builtin> {
+    let exact = Arity::exact;
+    let between = Arity::between;
+    let at_least = Arity::at_least;
+
+    vec![
+        // Aggregates
+        builtin!("SUM", at_least(1), [Range], "Adds numbers.", |a, p, g| {
+            eval_numeric_func(a, p, g, sum, "SUM")
+        }),
+        builtin!(
+            "AVG",
+            at_least(1),
+            [Range],
+            "Arithmetic mean.",
+            |a, p, g| { eval_numeric_func(a, p, g, average, "AVG") }
+        ),
+        builtin!(
+            "PROD",
+            at_least(1),
+            [Range],
+            "Multiplies numbers.",
+            |a, p, g| { eval_numeric_func(a, p, g, |nums| Ok(nums.iter().product()), "PROD") }
+        ),
+        builtin!("MAX", at_least(1), [Range], "Largest number.", |a, p, g| {
+            eval_numeric_func(a, p, g, max, "MAX")
+        }),
+        builtin!(
+            "MIN",
+            at_least(1),
+            [Range],
+            "Smallest number.",
+            |a, p, g| { eval_numeric_func(a, p, g, min, "MIN") }
+        ),
+        builtin!(
+            "SUMPRODUCT",
+            at_least(1),
+            [Range],
+            "Sum of the products of corresponding elements.",
+            eval_sumproduct
+        ),
+        // Counting
+        builtin!("COUNT", at_least(1), [Range], "Counts numbers.", eval_count),
+        builtin!(
+            "COUNTA",
+            at_least(1),
+            [Range],
+            "Counts non-blank values.",
+            eval_counta
+        ),
+        builtin!(
+            "COUNTBLANK",
+            at_least(1),
+            [Range],
+            "Counts blank cells.",
+            eval_countblank
+        ),
+        builtin!(
+            "COUNTUNIQUE",
+            at_least(1),
+            [Range],
+            "Counts distinct values.",
+            eval_countunique
+        ),
+        // Conditional aggregates
+        builtin!(
+            "SUMIF",
+            between(2, 3),
+            [Range, Criteria, Range],
+            "Sums the cells matching a criterion.",
+            |a, p, g| eval_if_func(a, p, g, sum, "SUMIF")
+        ),
+        builtin!(
+            "SUMIFS",
+            at_least(3),
+            [Range, Range, Criteria],
+            "Sums the cells matching all criteria.",
+            |a, p, g| eval_ifs_func(a, p, g, sum, "SUMIFS")
+        ),
+        builtin!(
+            "AVERAGEIF",
+            between(2, 3),
+            [Range, Criteria, Range],
+            "Mean of the cells matching a criterion.",
+            |a, p, g| eval_if_func(a, p, g, average, "AVERAGEIF")
+        ),
+        builtin!(
+            "AVERAGEIFS",
+            at_least(3),
+            [Range, Range, Criteria],
+            "Mean of the cells matching all criteria.",
+            |a, p, g| eval_ifs_func(a, p, g, average, "AVERAGEIFS")
+        ),
+        builtin!(
+            "COUNTIF",
+            exact(2),
+            [Range, Criteria],
+            "Counts the cells matching a criterion.",
+            |a, p, g| eval_countifs(a, p, g, "COUNTIF")
+        ),
+        builtin!(
+            "COUNTIFS",
+            at_least(2),
+            [Range, Criteria],
+            "Counts the rows matching all criteria.",
+            |a, p, g| eval_countifs(a, p, g, "COUNTIFS")
+        ),
+        builtin!(
+            "MAXIFS",
+            at_least(3),
+            [Range, Range, Criteria],
+            "Largest of the cells matching all criteria.",
+            |a, p, g| eval_ifs_func(a, p, g, max_or_zero, "MAXIFS")
+        ),
+        builtin!(
+            "MINIFS",
+            at_least(3),
+            [Range, Range, Criteria],
+            "Smallest of the cells matching all criteria.",
+            |a, p, g| eval_ifs_func(a, p, g, min_or_zero, "MINIFS")
+        ),
+        // Statistics
+        builtin!("MEDIAN", at_least(1), [Range], "Median.", |a, p, g| {
+            eval_numeric_func(a, p, g, median, "MEDIAN")
+        }),
+        builtin!(
+            "MODE",
+            at_least(1),
+            [Range],
+            "Most common number.",
+            |a, p, g| { eval_numeric_func(a, p, g, mode, "MODE") }
+        ),
+        builtin!(
+            "MODE.SNGL",
+            at_least(1),
+            [Range],
+            "Most common number.",
+            |a, p, g| { eval_numeric_func(a, p, g, mode, "MODE") }
+        ),
+        builtin!(
+            "STDEV",
+            at_least(1),
+            [Range],
+            "Sample standard deviation.",
+            |a, p, g| { eval_numeric_func(a, p, g, stdev_s, "STDEV.S") }
+        ),
+        builtin!(
+            "STDEV.S",
+            at_least(1),
+            [Range],
+            "Sample standard deviation.",
+            |a, p, g| { eval_numeric_func(a, p, g, stdev_s, "STDEV.S") }
+        ),
+        builtin!(
+            "STDEV.P",
+            at_least(1),
+            [Range],
+            "Population standard deviation.",
+            |a, p, g| { eval_numeric_func(a, p, g, stdev_p, "STDEV.P") }
+        ),
+        builtin!(
+            "VAR",
+            at_least(1),
+            [Range],
+            "Sample variance.",
+            |a, p, g| { eval_numeric_func(a, p, g, var_s, "VAR.S") }
+        ),
+        builtin!(
+            "VAR.S",
+            at_least(1),
+            [Range],
+            "Sample variance.",
+            |a, p, g| { eval_numeric_func(a, p, g, var_s, "VAR.S") }
+        ),
+        builtin!(
+            "VAR.P",
+            at_least(1),
+            [Range],
+            "Population variance.",
+            |a, p, g| { eval_numeric_func(a, p, g, var_p, "VAR.P") }
+        ),
+        builtin!(
+            "SKEW",
+            at_least(1),
+            [Range],
+            "Sample skewness.",
+            |a, p, g| { eval_numeric_func(a, p, g, skew, "SKEW") }
+        ),
+        builtin!(
+            "KURT",
+            at_least(1),
+            [Range],
+            "Sample excess kurtosis.",
+            |a, p, g| { eval_numeric_func(a, p, g, kurt, "KURT") }
+        ),
+        builtin!(
+            "GEOMEAN",
+            at_least(1),
+            [Range],
+            "Geometric mean.",
+            |a, p, g| { eval_numeric_func(a, p, g, geomean, "GEOMEAN") }
+        ),
+        builtin!(
+            "HARMEAN",
+            at_least(1),
+            [Range],
+            "Harmonic mean.",
+            |a, p, g| { eval_numeric_func(a, p, g, harmean, "HARMEAN") }
+        ),
+        builtin!(
+            "PERCENTILE",
+            exact(2),
+            [Range, Number],
+            "k-th percentile, inclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, percentile_inc, "PERCENTILE.INC")
+        ),
+        builtin!(
+            "PERCENTILE.INC",
+            exact(2),
+            [Range, Number],
+            "k-th percentile, inclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, percentile_inc, "PERCENTILE.INC")
+        ),
+        builtin!(
+            "PERCENTILE.EXC",
+            exact(2),
+            [Range, Number],
+            "k-th percentile, exclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, percentile_exc, "PERCENTILE.EXC")
+        ),
+        builtin!(
+            "QUARTILE",
+            exact(2),
+            [Range, Number],
+            "Quartile, inclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, quartile_inc, "QUARTILE.INC")
+        ),
+        builtin!(
+            "QUARTILE.INC",
+            exact(2),
+            [Range, Number],
+            "Quartile, inclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, quartile_inc, "QUARTILE.INC")
+        ),
+        builtin!(
+            "QUARTILE.EXC",
+            exact(2),
+            [Range, Number],
+            "Quartile, exclusive.",
+            |a, p, g| eval_array_k_func(a, p, g, quartile_exc, "QUARTILE.EXC")
+        ),
+        builtin!(
+            "LARGE",
+            exact(2),
+            [Range, Number],
+            "k-th largest number.",
+            |a, p, g| { eval_array_k_func(a, p, g, large, "LARGE") }
+        ),
+        builtin!(
+            "SMALL",
+            exact(2),
+            [Range, Number],
+            "k-th smallest number.",
+            |a, p, g| { eval_array_k_func(a, p, g, small, "SMALL") }
+        ),
+        builtin!(
+            "RANK",
+            between(2, 3),
+            [Number, Range, Number],
+            "Rank of a number in a list.",
+            eval_rank
+        ),
+        builtin!(
+            "RANK.EQ",
+            between(2, 3),
+            [Number, Range, Number],
+            "Rank of a number in a list.",
+            eval_rank
+        ),
+        builtin!(
+            "CORREL",
+            exact(2),
+            [Range, Range],
+            "Correlation coefficient.",
+            |a, p, g| { eval_paired_func(a, p, g, correl, "CORREL") }
+        ),
+        builtin!(
+            "COVAR",
+            exact(2),
+            [Range, Range],
+            "Population covariance.",
+            |a, p, g| { eval_paired_func(a, p, g, covariance_p, "COVARIANCE.P") }
+        ),
+        builtin!(
+            "COVARIANCE.P",
+            exact(2),
+            [Range, Range],
+            "Population covariance.",
+            |a, p, g| { eval_paired_func(a, p, g, covariance_p, "COVARIANCE.P") }
+        ),
+        builtin!(
+            "COVARIANCE.S",
+            exact(2),
+            [Range, Range],
+            "Sample covariance.",
+            |a, p, g| { eval_paired_func(a, p, g, covariance_s, "COVARIANCE.S") }
+        ),
+        // Regression
+        builtin!(
+            "SLOPE",
+            exact(2),
+            [Range, Range],
+            "Slope of the regression line.",
+            |a, p, g| { eval_line_func(a, p, g, slope, "SLOPE") }
+        ),
+        builtin!(
+            "INTERCEPT",
+            exact(2),
+            [Range, Range],
+            "Intercept of the regression line.",
+            |a, p, g| eval_line_func(a, p, g, intercept, "INTERCEPT")
+        ),
+        builtin!(
+            "RSQ",
+            exact(2),
+            [Range, Range],
+            "Coefficient of determination.",
+            |a, p, g| { eval_line_func(a, p, g, rsq, "RSQ") }
+        ),
+        builtin!(
+            "STEYX",
+            exact(2),
+            [Range, Range],
+            "Standard error of the regression.",
+            |a, p, g| eval_line_func(a, p, g, steyx, "STEYX")
+        ),
+        builtin!(
+            "FORECAST",
+            exact(3),
+            [Number, Range, Range],
+            "Linear prediction at x.",
+            eval_forecast
+        ),
+        builtin!(
+            "FORECAST.LINEAR",
+            exact(3),
+            [Number, Range, Range],
+            "Linear prediction at x.",
+            eval_forecast
+        ),
+        builtin!(
+            "TREND",
+            between(1, 4),
+            [Range, Range, Range, Any],
+            "Values along a least squares fit.",
+            eval_trend
+        ),
+        builtin!(
+            "GROWTH",
+            between(1, 4),
+            [Range, Range, Range, Any],
+            "Values along an exponential fit.",
+            eval_growth
+        ),
+        builtin!(
+            "LINEST",
+            between(1, 3),
+            [Range, Range, Any],
+            "Coefficients of a least squares fit.",
+            eval_linest
+        ),
+        // Finance
+        builtin!(
+            "PMT",
+            between(3, 5),
+            [Number],
+            "Payment per period of a loan.",
+            |a, p, g| eval_tvm_func(a, p, g, |a, b, c, d, e| Ok(pmt(a, b, c, d, e)), "PMT")
+        ),
+        builtin!(
+            "PV",
+            between(3, 5),
+            [Number],
+            "Present value of an investment.",
+            |a, p, g| eval_tvm_func(a, p, g, |a, b, c, d, e| Ok(pv(a, b, c, d, e)), "PV")
+        ),
+        builtin!(
+            "FV",
+            between(3, 5),
+            [Number],
+            "Future value of an investment.",
+            |a, p, g| eval_tvm_func(a, p, g, |a, b, c, d, e| Ok(fv(a, b, c, d, e)), "FV")
+        ),
+        builtin!(
+            "NPER",
+            between(3, 5),
+            [Number],
+            "Number of periods.",
+            |a, p, g| { eval_tvm_func(a, p, g, nper, "NPER") }
+        ),
+        builtin!(
+            "RATE",
+            between(3, 6),
+            [Number],
+            "Interest rate per period.",
+            eval_rate
+        ),
+        builtin!(
+            "NPV",
+            at_least(2),
+            [Number, Range],
+            "Net present value.",
+            eval_npv
+        ),
+        builtin!(
+            "IRR",
+            between(1, 2),
+            [Range, Number],
+            "Internal rate of return.",
+            eval_irr
+        ),
+        builtin!(
+            "XNPV",
+            exact(3),
+            [Number, Range, Range],
+            "Net present value of dated cash flows.",
+            eval_xnpv
+        ),
+        builtin!(
+            "XIRR",
+            between(2, 3),
+            [Range, Range, Number],
+            "Internal rate of return of dated cash flows.",
+            eval_xirr
+        ),
+        // Dates
+        Builtin {
+            volatile: true,
+            ..builtin!(
+                "TODAY",
+                exact(0),
+                [],
+                "Current date.",
+                |_, _, g| eval_today(g)
+            )
+        },
+        Builtin {
+            volatile: true,
+            ..builtin!("NOW", exact(0), [], "Current date and time.", |_, _, g| {
+                eval_now(g)
+            })
+        },
+        builtin!(
+            "DATE",
+            exact(3),
+            [Number],
+            "Date from year, month and day.",
+            eval_date
+        ),
+        builtin!(
+            "TIME",
+            exact(3),
+            [Number],
+            "Time from hour, minute and second.",
+            eval_time
+        ),
+        builtin!("YEAR", exact(1), [Date], "Year of a date.", |a, p, g| {
+            eval_date_part(a, p, g, "YEAR")
+        }),
+        builtin!("MONTH", exact(1), [Date], "Month of a date.", |a, p, g| {
+            eval_date_part(a, p, g, "MONTH")
+        }),
+        builtin!(
+            "DAY",
+            exact(1),
+            [Date],
+            "Day of the month of a date.",
+            |a, p, g| { eval_date_part(a, p, g, "DAY") }
+        ),
+        builtin!(
+            "WEEKDAY",
+            between(1, 2),
+            [Date, Number],
+            "Day of the week of a date.",
+            eval_weekday
+        ),
+        builtin!(
+            "EDATE",
+            exact(2),
+            [Date, Number],
+            "Date a number of months away.",
+            |a, p, g| { eval_month_offset(a, p, g, "EDATE") }
+        ),
+        builtin!(
+            "EOMONTH",
+            exact(2),
+            [Date, Number],
+            "Last day of the month a number of months away.",
+            |a, p, g| eval_month_offset(a, p, g, "EOMONTH")
+        ),
+        builtin!(
+            "DATEDIF",
+            exact(3),
+            [Date, Date, Text],
+            "Difference between two dates in the given unit.",
+            eval_datedif
+        ),
+        builtin!(
+            "NETWORKDAYS",
+            between(2, 3),
+            [Date, Date, Range],
+            "Working days between two dates.",
+            eval_networkdays
+        ),
+        builtin!(
+            "WORKDAY",
+            between(2, 3),
+            [Date, Number, Range],
+            "Date a number of working days away.",
+            eval_workday
+        ),
+        // Maths
+        builtin!("ABS", exact(1), [Number], "Absolute value.", eval_abs),
+        builtin!("LOG", exact(1), [Number], "Natural logarithm.", eval_log),
+        builtin!("SQRT", exact(1), [Number], "Square root.", eval_sqrt),
+        builtin!("EXP", exact(1), [Number], "e raised to a power.", eval_exp),
+        builtin!("SIN", exact(1), [Number], "Sine.", eval_sin),
+        builtin!("COS", exact(1), [Number], "Cosine.", eval_cos),
+        builtin!("TAN", exact(1), [Number], "Tangent.", eval_tan),
+        builtin!("ASIN", exact(1), [Number], "Inverse sine.", eval_asin),
+        builtin!("ACOS", exact(1), [Number], "Inverse cosine.", eval_acos),
+        builtin!("ATAN", exact(1), [Number], "Inverse tangent.", eval_atan),
+        builtin!("PI", exact(0), [], "The constant pi.", |_, _, _| eval_pi()),
+        builtin!(
+            "TAU",
+            exact(0),
+            [],
+            "The constant tau, two pi.",
+            |_, _, _| eval_tau()
+        ),
+        builtin!("SQRT2", exact(0), [], "Square root of two.", |_, _, _| {
+            eval_sqrt2()
+        }),
+        builtin!(
+            "ROUND",
+            between(1, 2),
+            [Number],
+            "Rounds to a number of digits.",
+            eval_round
+        ),
+        builtin!(
+            "ROUNDUP",
+            between(1, 2),
+            [Number],
+            "Rounds away from zero to a number of digits.",
+            eval_roundup
+        ),
+        builtin!(
+            "ROUNDDOWN",
+            between(1, 2),
+            [Number],
+            "Rounds towards zero to a number of digits.",
+            eval_rounddown
+        ),
+        builtin!(
+            "MROUND",
+            exact(2),
+            [Number],
+            "Rounds to a multiple.",
+            eval_mround
+        ),
+        builtin!(
+            "FLOOR",
+            between(1, 2),
+            [Number],
+            "Rounds down to a multiple.",
+            eval_floor
+        ),
+        builtin!(
+            "CEILING",
+            between(1, 2),
+            [Number],
+            "Rounds up to a multiple.",
+            eval_ceiling
+        ),
+        builtin!(
+            "INT",
+            exact(1),
+            [Number],
+            "Rounds down to an integer.",
+            eval_int
+        ),
+        builtin!(
+            "TRUNC",
+            between(1, 2),
+            [Number],
+            "Truncates to a number of digits.",
+            eval_trunc
+        ),
+        builtin!(
+            "MOD",
+            exact(2),
+            [Number],
+            "Remainder of a division.",
+            eval_mod
+        ),
+        builtin!(
+            "QUOTIENT",
+            exact(2),
+            [Number],
+            "Integer part of a division.",
+            eval_quotient
+        ),
+        builtin!("SIGN", exact(1), [Number], "Sign of a number.", eval_sign),
+        builtin!(
+            "GCD",
+            at_least(1),
+            [Range],
+            "Greatest common divisor.",
+            |a, p, g| { eval_numeric_func(a, p, g, gcd, "GCD") }
+        ),
+        builtin!(
+            "LCM",
+            at_least(1),
+            [Range],
+            "Least common multiple.",
+            |a, p, g| { eval_numeric_func(a, p, g, lcm, "LCM") }
+        ),
+        builtin!("FACT", exact(1), [Number], "Factorial.", eval_fact),
+        builtin!(
+            "COMBIN",
+            exact(2),
+            [Number],
+            "Number of combinations.",
+            eval_combin
+        ),
+        builtin!(
+            "PERMUT",
+            exact(2),
+            [Number],
+            "Number of permutations.",
+            eval_permut
+        ),
+        builtin!(
+            "POWER",
+            exact(2),
+            [Number],
+            "Number raised to a power.",
+            eval_power
+        ),
+        // Arrays
+        builtin!(
+            "SEQUENCE",
+            between(1, 4),
+            [Number],
+            "Array of evenly spaced numbers.",
+            eval_sequence
+        ),
+        builtin!(
+            "SORT",
+            between(1, 4),
+            [Range, Range, Range, Any],
+            "Sorts an array by one or more of its columns.",
+            eval_sort
+        ),
+        builtin!(
+            "SORTBY",
+            at_least(2),
+            [Range, Range, Number],
+            "Sorts an array by other arrays.",
+            eval_sortby
+        ),
+        builtin!(
+            "FILTER",
+            between(2, 3),
+            [Range, Range, Any],
+            "Rows or columns of an array where include is true.",
+            eval_filter
+        ),
+        builtin!(
+            "UNIQUE",
+            between(1, 3),
+            [Range, Any, Any],
+            "Distinct rows or columns of an array.",
+            eval_unique
+        ),
+        builtin!(
+            "TRANSPOSE",
+            exact(1),
+            [Range],
+            "Swaps the rows and columns of an array.",
+            eval_transpose
+        ),
+        builtin!(
+            "VSTACK",
+            at_least(1),
+            [Range],
+            "Stacks arrays vertically.",
+            eval_vstack
+        ),
+        builtin!(
+            "HSTACK",
+            at_least(1),
+            [Range],
+            "Stacks arrays horizontally.",
+            eval_hstack
+        ),
+        builtin!(
+            "TAKE",
+            between(2, 3),
+            [Range, Number, Number],
+            "Rows and columns from the start or end of an array.",
+            eval_take
+        ),
+        builtin!(
+            "DROP",
+            between(2, 3),
+            [Range, Number, Number],
+            "Array without rows and columns from its start or end.",
+            eval_drop
+        ),
+        builtin!(
+            "CHOOSECOLS",
+            at_least(2),
+            [Range, Number],
+            "Columns of an array by index.",
+            eval_choosecols
+        ),
+        // Matrices
+        builtin!(
+            "MMULT",
+            exact(2),
+            [Range, Range],
+            "Matrix product.",
+            eval_mmult
+        ),
+        builtin!(
+            "MDETERM",
+            exact(1),
+            [Range],
+            "Determinant of a square matrix.",
+            eval_mdeterm
+        ),
+        builtin!(
+            "MINVERSE",
+            exact(1),
+            [Range],
+            "Inverse of a square matrix.",
+            eval_minverse
+        ),
+        builtin!(
+            "MUNIT",
+            exact(1),
+            [Number],
+            "Identity matrix of a size.",
+            eval_munit
+        ),
+        builtin!(
+            "LINSOLVE",
+            exact(2),
+            [Range, Range],
+            "Solves the linear system AX = B.",
+            eval_linsolve
+        ),
+        // Regular expressions
+        builtin!(
+            "REGEXMATCH",
+            between(2, 3),
+            [Range, Text, Any],
+            "Whether text matches a regular expression.",
+            eval_regexmatch
+        ),
+        builtin!(
+            "REGEXEXTRACT",
+            between(2, 4),
+            [Text, Text, Number, Any],
+            "Matches or capture groups of a regular expression.",
+            eval_regexextract
+        ),
+        builtin!(
+            "REGEXREPLACE",
+            between(3, 5),
+            [Range, Text, Text, Number, Any],
+            "Replaces the matches of a regular expression.",
+            eval_regexreplace
+        ),
+        // Random numbers
+        Builtin {
+            volatile: true,
+            ..builtin!("RAND", exact(0), [], "Random number in [0, 1).", eval_rand)
+        },
+        Builtin {
+            volatile: true,
+            ..builtin!(
+                "RANDBETWEEN",
+                exact(2),
+                [Number, Number],
+                "Random integer between two bounds.",
+                eval_randbetween
+            )
+        },
+        Builtin {
+            volatile: true,
+            ..builtin!(
+                "RANDARRAY",
+                between(0, 5),
+                [Number, Number, Number, Number, Any],
+                "Array of random numbers.",
+                eval_randarray
+            )
+        },
+        // References
+        builtin!(
+            "INDIRECT",
+            exact(1),
+            [Text],
+            "Cell or range named by text.",
+            eval_indirect
+        ),
+        builtin!(
+            "OFFSET",
+            between(3, 5),
+            [Range, Number, Number, Number, Number],
+            "Range moved and resized from a reference.",
+            eval_offset
+        ),
+    ]
+}
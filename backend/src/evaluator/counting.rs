@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use crate::{
+    common::{LeadErr, Literal},
+    evaluator::{Eval, Precedents, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// See NonNumeric in utils.rs for how each counting function treats text, booleans and blanks.
+
+fn count_values(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    pred: impl Fn(&Eval) -> bool,
+) -> Result<usize, LeadErr> {
+    let mut count = 0;
+
+    for arg in args {
+        count += eval_range_values(arg, precs, grid)?
+            .iter()
+            .filter(|it| pred(it))
+            .count();
+    }
+
+    Ok(count)
+}
+
+fn is_blank(eval: &Eval) -> bool {
+    matches!(eval, Eval::Unset) || matches!(eval, Eval::Literal(Literal::String(s)) if s.is_empty())
+}
+
+pub fn eval_count(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let numbers = collect_numbers(args, precs, grid, NonNumeric::Skip, "COUNT")?;
+
+    Ok(Eval::Literal(Literal::Number(numbers.len() as f64)))
+}
+
+pub fn eval_counta(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let count = count_values(args, precs, grid, |it| !matches!(it, Eval::Unset))?;
+
+    Ok(Eval::Literal(Literal::Number(count as f64)))
+}
+
+pub fn eval_countblank(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let count = count_values(args, precs, grid, is_blank)?;
+
+    Ok(Eval::Literal(Literal::Number(count as f64)))
+}
+
+// Identifies equal values, text is compared case-insensitively
+#[derive(Hash, PartialEq, Eq)]
+pub enum UniqueKey {
+    Number(u64),
+    Boolean(bool),
+    Text(String),
+    Err(String),
+}
+
+impl UniqueKey {
+    // None for blanks and empty strings
+    pub fn of(value: &Eval) -> Option<UniqueKey> {
+        match value {
+            Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => {
+                Some(UniqueKey::Number((n + 0.0).to_bits()))
+            }
+            Eval::Literal(Literal::Boolean(b)) => Some(UniqueKey::Boolean(*b)),
+            Eval::Literal(Literal::String(s)) if !s.is_empty() => {
+                Some(UniqueKey::Text(s.to_lowercase()))
+            }
+            Eval::Err(e) => Some(UniqueKey::Err(e.desc.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+// Text is compared case-insensitively, consistent with criteria matching
+pub fn eval_countunique(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let mut seen = HashSet::new();
+
+    for arg in args {
+        for value in eval_range_values(arg, precs, grid)? {
+            seen.extend(UniqueKey::of(&value));
+        }
+    }
+
+    Ok(Eval::Literal(Literal::Number(seen.len() as f64)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    #[test]
+    fn test_counting_functions() {
+        let grid = grid_from(&[
+            &["Amount"],
+            &["10"],
+            &["20"],
+            &[""],
+            &["abc"],
+            &["20"],
+            &["ABC"],
+        ]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        // A header in the range is skipped
+        assert_eq!(eval("SUM(A1:A8)"), num(50.0));
+        assert_eq!(eval("AVG(A1:A8)"), num(50.0 / 3.0));
+        assert_eq!(eval("SUM(A1)"), num(0.0));
+        assert_eq!(eval("SUM(A2, 1, true, \"2\")"), num(14.0));
+        assert!(matches!(eval("SUM(1, \"abc\")"), Eval::Err(_)));
+
+        assert_eq!(eval("COUNT(A1:A8)"), num(3.0));
+        assert_eq!(eval("COUNT(A1:A8, 1, true, \"2\", \"abc\")"), num(6.0));
+        // A4 was set to "", which leaves it blank
+        assert_eq!(eval("COUNTA(A1:A8)"), num(6.0));
+        assert_eq!(eval("COUNTBLANK(A1:A8)"), num(2.0));
+        assert_eq!(eval("COUNTUNIQUE(A1:A8)"), num(4.0));
+        assert!(matches!(eval("COUNT()"), Eval::Err(_)));
+
+        // Only finite numbers are numeric text
+        for text in ["nan", "inf", "-infinity"] {
+            assert!(matches!(eval(&format!("MAX(\"{text}\", 1)")), Eval::Err(_)));
+            assert!(matches!(eval(&format!("MIN(\"{text}\", 1)")), Eval::Err(_)));
+            assert!(matches!(
+                eval(&format!("MEDIAN(\"{text}\", 1, 2)")),
+                Eval::Err(_)
+            ));
+            assert!(matches!(
+                eval(&format!("RANK(\"{text}\", A1:A8)")),
+                Eval::Err(_)
+            ));
+            assert_eq!(eval(&format!("COUNT(\"{text}\", 1)")), num(1.0));
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use std::cmp::Ordering;
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Eval, Precedents, datetime::parse_datetime, evaluate_expr, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Criteria strings follow the Excel mini-language used by the IF/IFS family:
+//
+// "Travel"    equal to Travel (case-insensitive, * and ? are wildcards, ~ escapes)
+// ">=10"      comparison prefixes: =, <>, <, <=, >, >=
+// ">=2024-01-01" dates compare as their serial numbers
+// "="         blank cells
+// "<>"        non-blank cells
+// 5 / true    a non-string criterion is an equality test against that value
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum CriterionOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum CriterionValue {
+    Number(f64),
+    Boolean(bool),
+    Text(String),
+    Blank,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Criterion {
+    op: CriterionOp,
+    value: CriterionValue,
+}
+
+impl Criterion {
+    pub fn new(eval: &Eval) -> Criterion {
+        let value = match eval {
+            Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => CriterionValue::Number(*n),
+            Eval::Literal(Literal::Boolean(b)) => CriterionValue::Boolean(*b),
+            Eval::Literal(Literal::String(s)) => return Criterion::parse(s),
+            _ => CriterionValue::Blank,
+        };
+
+        Criterion {
+            op: CriterionOp::Eq,
+            value,
+        }
+    }
+
+    fn parse(s: &str) -> Criterion {
+        let (op, rest) = if let Some(rest) = s.strip_prefix(">=") {
+            (CriterionOp::Ge, rest)
+        } else if let Some(rest) = s.strip_prefix("<=") {
+            (CriterionOp::Le, rest)
+        } else if let Some(rest) = s.strip_prefix("<>") {
+            (CriterionOp::Ne, rest)
+        } else if let Some(rest) = s.strip_prefix('>') {
+            (CriterionOp::Gt, rest)
+        } else if let Some(rest) = s.strip_prefix('<') {
+            (CriterionOp::Lt, rest)
+        } else if let Some(rest) = s.strip_prefix('=') {
+            (CriterionOp::Eq, rest)
+        } else {
+            (CriterionOp::Eq, s)
+        };
+
+        let value = if rest.is_empty() {
+            CriterionValue::Blank
+        } else if let Some(n) = rest.trim().parse::<f64>().ok().or(parse_datetime(rest)) {
+            CriterionValue::Number(n)
+        } else if rest.eq_ignore_ascii_case("true") {
+            CriterionValue::Boolean(true)
+        } else if rest.eq_ignore_ascii_case("false") {
+            CriterionValue::Boolean(false)
+        } else {
+            CriterionValue::Text(rest.to_lowercase())
+        };
+
+        Criterion { op, value }
+    }
+
+    pub fn matches(&self, eval: &Eval) -> bool {
+        let eval = match eval {
+            Eval::CellRef { eval, .. } => eval.as_ref(),
+            it => it,
+        };
+
+        let is_blank = matches!(eval, Eval::Unset)
+            || matches!(eval, Eval::Literal(Literal::String(s)) if s.is_empty());
+
+        let ord = match (&self.value, eval) {
+            (CriterionValue::Blank, _) => {
+                return match self.op {
+                    CriterionOp::Ne => !is_blank,
+                    _ => is_blank,
+                };
+            }
+            (
+                CriterionValue::Number(n),
+                Eval::Literal(Literal::Number(x) | Literal::DateTime(x)),
+            ) => x.partial_cmp(n),
+            (CriterionValue::Number(n), Eval::Literal(Literal::String(s))) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|_| self.op == CriterionOp::Eq || self.op == CriterionOp::Ne)
+                .and_then(|x| x.partial_cmp(n)),
+            (CriterionValue::Boolean(b), Eval::Literal(Literal::Boolean(x))) => Some(x.cmp(b)),
+            (CriterionValue::Text(pat), Eval::Literal(Literal::String(s))) => match self.op {
+                CriterionOp::Eq | CriterionOp::Ne => {
+                    if wildcard_match(pat, &s.to_lowercase()) {
+                        Some(Ordering::Equal)
+                    } else {
+                        Some(Ordering::Less)
+                    }
+                }
+                _ => Some(s.to_lowercase().as_str().cmp(pat.as_str())),
+            },
+            _ => None,
+        };
+
+        match ord {
+            Some(ord) => match self.op {
+                CriterionOp::Eq => ord == Ordering::Equal,
+                CriterionOp::Ne => ord != Ordering::Equal,
+                CriterionOp::Lt => ord == Ordering::Less,
+                CriterionOp::Le => ord != Ordering::Greater,
+                CriterionOp::Gt => ord == Ordering::Greater,
+                CriterionOp::Ge => ord != Ordering::Less,
+            },
+            // Values of a different type never compare, but they are "not equal"
+            None => self.op == CriterionOp::Ne,
+        }
+    }
+}
+
+// Glob style matching where * matches any run of characters, ? matches exactly one
+// and ~ escapes the following character.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let txt: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < txt.len() {
+        if p < pat.len() && pat[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+            continue;
+        }
+
+        if p < pat.len() {
+            let (literal, width) = match pat[p] {
+                '~' if p + 1 < pat.len() => (Some(pat[p + 1]), 2),
+                '?' => (None, 1),
+                c => (Some(c), 1),
+            };
+
+            if literal.is_none_or(|c| c == txt[t]) {
+                p += width;
+                t += 1;
+                continue;
+            }
+        }
+
+        match backtrack {
+            Some((star_p, star_t)) => {
+                p = star_p + 1;
+                t = star_t + 1;
+                backtrack = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+
+    pat[p..].iter().all(|c| *c == '*')
+}
+
+// -------------------------------------------------- //
+
+fn eval_criterion(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Criterion, LeadErr> {
+    let value = match evaluate_expr(arg, precs, grid)? {
+        Eval::CellRef { eval, .. } => *eval,
+        it => it,
+    };
+
+    match value {
+        Eval::Range(_) => Err(eval_err(
+            format!("{func_name} criteria must be a single value."),
+            LeadErrCode::TypeErr,
+        )),
+        Eval::Err(e) => Err(e),
+        it => Ok(Criterion::new(&it)),
+    }
+}
+
+// Evaluates (range, criterion) argument pairs into a mask over the cells of the ranges
+fn eval_criteria_mask(
+    pairs: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    size: Option<usize>,
+    func_name: &str,
+) -> Result<Vec<bool>, LeadErr> {
+    let mut mask: Option<Vec<bool>> = size.map(|n| vec![true; n]);
+
+    for pair in pairs.chunks(2) {
+        let cells = eval_range_values(&pair[0], precs, grid)?;
+        let criterion = eval_criterion(&pair[1], precs, grid, func_name)?;
+
+        let mask = mask.get_or_insert_with(|| vec![true; cells.len()]);
+        if mask.len() != cells.len() {
+            return Err(range_size_err(func_name, mask.len(), cells.len()));
+        }
+
+        for (keep, cell) in mask.iter_mut().zip(cells.iter()) {
+            *keep = *keep && criterion.matches(cell);
+        }
+    }
+
+    Ok(mask.unwrap_or_default())
+}
+
+fn range_size_err(func_name: &str, expected: usize, found: usize) -> LeadErr {
+    eval_err(
+        format!(
+            "{func_name} requires ranges of the same size, found {expected} and {found} cells."
+        ),
+        LeadErrCode::Invalid,
+    )
+}
+
+// Numbers of the target range that line up with a true entry in the mask. Text, booleans and
+// blanks are skipped while errors are propagated.
+fn masked_numbers(cells: &[Eval], mask: &[bool]) -> Result<Vec<f64>, LeadErr> {
+    let mut numbers = Vec::new();
+
+    for (cell, keep) in cells.iter().zip(mask) {
+        if !keep {
+            continue;
+        }
+
+        match cell {
+            Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => numbers.push(*n),
+            Eval::Err(e) => return Err(e.clone()),
+            _ => {}
+        }
+    }
+
+    Ok(numbers)
+}
+
+// COUNTIF(range, criterion) and COUNTIFS(range1, criterion1, ...)
+pub fn eval_countifs(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    // The registry checks the number of arguments but not that they pair up
+    if !args.len().is_multiple_of(2) {
+        return Err(eval_err(
+            format!("{func_name} function requires range and criteria argument pairs."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let mask = eval_criteria_mask(args, precs, grid, None, func_name)?;
+    let count = mask.iter().filter(|it| **it).count();
+
+    Ok(Eval::Literal(Literal::Number(count as f64)))
+}
+
+// SUMIF style functions: FUNC(range, criterion, [target_range])
+pub fn eval_if_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: impl Fn(&[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let mask = eval_criteria_mask(&args[..2], precs, grid, None, func_name)?;
+    let target = match args.get(2) {
+        Some(arg) => eval_range_values(arg, precs, grid)?,
+        None => eval_range_values(&args[0], precs, grid)?,
+    };
+
+    if target.len() != mask.len() {
+        return Err(range_size_err(func_name, mask.len(), target.len()));
+    }
+
+    let res = func(&masked_numbers(&target, &mask)?)?;
+    Ok(Eval::Literal(Literal::Number(res)))
+}
+
+// SUMIFS style functions: FUNC(target_range, range1, criterion1, ...)
+pub fn eval_ifs_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: impl Fn(&[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    if args.len().is_multiple_of(2) {
+        return Err(eval_err(
+            format!(
+                "{func_name} function requires a target range followed by range and criteria argument pairs."
+            ),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let target = eval_range_values(&args[0], precs, grid)?;
+    let mask = eval_criteria_mask(&args[1..], precs, grid, Some(target.len()), func_name)?;
+
+    let res = func(&masked_numbers(&target, &mask)?)?;
+    Ok(Eval::Literal(Literal::Number(res)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    fn text(s: &str) -> Eval {
+        Eval::Literal(Literal::String(s.into()))
+    }
+
+    fn crit(s: &str) -> Criterion {
+        Criterion::new(&text(s))
+    }
+
+    #[test]
+    fn test_criteria_comparisons() {
+        assert!(crit(">=10").matches(&num(10.0)));
+        assert!(!crit(">10").matches(&num(10.0)));
+        assert!(crit("<10").matches(&num(9.5)));
+        assert!(crit("<=10").matches(&num(10.0)));
+        assert!(crit("<>10").matches(&num(11.0)));
+        assert!(crit("=10").matches(&num(10.0)));
+        assert!(crit("10").matches(&text("10")));
+        assert!(!crit(">5").matches(&text("10")));
+        assert!(Criterion::new(&num(3.0)).matches(&num(3.0)));
+        assert!(
+            Criterion::new(&Eval::Literal(Literal::Boolean(true)))
+                .matches(&Eval::Literal(Literal::Boolean(true)))
+        );
+    }
+
+    #[test]
+    fn test_criteria_text() {
+        assert!(crit("travel").matches(&text("Travel")));
+        assert!(!crit("travel").matches(&text("Travelling")));
+        assert!(crit("<>travel").matches(&text("Food")));
+        assert!(crit("<>travel").matches(&num(1.0)));
+        assert!(crit(">=b").matches(&text("c")));
+        assert!(!crit(">=b").matches(&text("a")));
+    }
+
+    #[test]
+    fn test_criteria_dates() {
+        let date = |s: &str| Eval::Literal(Literal::DateTime(parse_datetime(s).unwrap()));
+
+        assert!(crit(">=2024-01-01").matches(&date("2024-03-15")));
+        assert!(!crit(">=2024-01-01").matches(&date("2023-12-31")));
+        assert!(crit("2024-01-01").matches(&date("2024-01-01")));
+    }
+
+    #[test]
+    fn test_criteria_wildcards() {
+        assert!(crit("tr*").matches(&text("Travel")));
+        assert!(crit("*vel").matches(&text("Travel")));
+        assert!(crit("t?avel").matches(&text("Travel")));
+        assert!(!crit("t?vel").matches(&text("Travel")));
+        assert!(crit("*").matches(&text("")));
+        assert!(crit("a*b*c").matches(&text("aXXbYYc")));
+        assert!(crit("what~?").matches(&text("what?")));
+        assert!(!crit("what~?").matches(&text("whats")));
+        assert!(crit("~*").matches(&text("*")));
+        assert!(!crit("~*").matches(&text("x")));
+    }
+
+    #[test]
+    fn test_criteria_blank() {
+        assert!(crit("=").matches(&Eval::Unset));
+        assert!(crit("").matches(&text("")));
+        assert!(!crit("=").matches(&num(0.0)));
+        assert!(crit("<>").matches(&num(0.0)));
+        assert!(!crit("<>").matches(&Eval::Unset));
+        assert!(Criterion::new(&Eval::Unset).matches(&Eval::Unset));
+    }
+
+    #[test]
+    fn test_conditional_aggregates() {
+        let mut grid = grid_from(&[
+            &["Travel", "2023-12-30", "100"],
+            &["Food", "2024-01-02", "20"],
+            &["Travel", "2024-01-05", "250"],
+            &["travel", "2024-02-10", "50"],
+        ]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        assert_eq!(
+            eval("SUMIFS(C1:C4, A1:A4, \"Travel\", B1:B4, \">=2024-01-01\")"),
+            num(300.0)
+        );
+        assert_eq!(eval("SUMIF(A1:A4, \"Travel\", C1:C4)"), num(400.0));
+        assert_eq!(eval("SUMIF(C1:C4, \">60\")"), num(350.0));
+        assert_eq!(eval("COUNTIF(A1:A4, \"t*\")"), num(3.0));
+        assert_eq!(
+            eval("COUNTIFS(A1:A4, \"<>Food\", C1:C4, \"<200\")"),
+            num(2.0)
+        );
+        assert_eq!(eval("AVERAGEIF(A1:A4, \"Food\", C1:C4)"), num(20.0));
+        assert_eq!(eval("MAXIFS(C1:C4, A1:A4, \"Travel\")"), num(250.0));
+        assert_eq!(eval("MINIFS(C1:C4, A1:A4, \"Nothing\")"), num(0.0));
+        assert_eq!(eval("COUNTIF(D1:D4, \"=\")"), num(4.0));
+
+        // Criteria read from cells
+        set_cells(&mut grid, &[("E1", "Travel"), ("E2", ">60")]);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        assert_eq!(eval("SUMIF(A1:A4, E1, C1:C4)"), num(400.0));
+        assert_eq!(eval("COUNTIF(C1:C4, E2)"), num(2.0));
+
+        let Eval::Literal(Literal::Number(empty)) = eval("SUMIF(A1:A4, \"Nothing\", C1:C4)") else {
+            panic!("Expected a number.");
+        };
+        assert!(empty == 0.0 && empty.is_sign_positive());
+
+        // Whole columns, with the criteria in E1 and E2 outside the summed rows
+        assert_eq!(eval("SUMIFS(C:C, A:A, \"Travel\")"), num(400.0));
+        assert_eq!(eval("COUNTIF(1:1, \"Travel\")"), num(2.0));
+
+        let Eval::Err(err) = eval("SUMIFS(C1:C4, A1:A3, \"Travel\")") else {
+            panic!("Expected a range size error.");
+        };
+        assert_eq!(err.code, LeadErrCode::Invalid);
+
+        let Eval::Err(err) = eval("AVERAGEIF(A1:A4, \"Nothing\", C1:C4)") else {
+            panic!("Expected a divide by zero error.");
+        };
+        assert_eq!(err.code, LeadErrCode::DivZero);
+    }
+}
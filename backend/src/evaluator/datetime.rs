@@ -0,0 +1,643 @@
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Eval, Precedents, evaluate_expr, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Dates and times are serial numbers counting days from 1899-12-30, which is 0. 1900-01-01 is
+// therefore 2 and 2024-01-01 is 45292, and the fractional part is the time of day, 0.5 being
+// noon. Unlike Lotus and Excel there is no fictional 1900-02-29, so serials from 1900-03-01 on
+// match Excel's and earlier ones are one more.
+//
+// Values are stored as Literal::DateTime(serial), which behaves as a number in arithmetic and
+// numeric functions but tells clients to display it as a date.
+
+// Days between 1899-12-30 and the unix epoch
+const UNIX_EPOCH_SERIAL: i64 = 25569;
+// 9999-12-31, the last date Excel supports. Arguments are checked against it before any date
+// arithmetic so huge values are an error instead of an overflow or a long loop.
+const MAX_SERIAL: f64 = 2958465.0;
+const MAX_YEAR: f64 = 9999.0;
+const MAX_MONTHS: f64 = MAX_YEAR * 12.0;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+// Source of the current time for TODAY and NOW, injectable so tests stay deterministic
+pub trait Clock: Send + Sync {
+    // Current UTC date and time as a serial number
+    fn now(&self) -> f64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |it| it.as_secs_f64());
+
+        UNIX_EPOCH_SERIAL as f64 + secs / SECONDS_PER_DAY
+    }
+}
+
+#[allow(dead_code)]
+pub struct FixedClock(pub f64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> f64 {
+        self.0
+    }
+}
+
+// -------------------------------------------------- //
+
+// Days since the unix epoch of a proleptic Gregorian date.
+// Ref: https://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, i64, i64) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+
+    (
+        if m <= 2 {
+            yoe + era * 400 + 1
+        } else {
+            yoe + era * 400
+        },
+        m,
+        d,
+    )
+}
+
+// Serial of a date, months and days outside their usual range roll over like Excel's DATE
+pub fn serial_from_ymd(y: i64, m: i64, d: i64) -> i64 {
+    let y = y + (m - 1).div_euclid(12);
+    let m = (m - 1).rem_euclid(12) + 1;
+
+    days_from_civil(y, m, 1) + (d - 1) + UNIX_EPOCH_SERIAL
+}
+
+pub fn ymd_from_serial(serial: f64) -> (i64, i64, i64) {
+    civil_from_days(serial.floor() as i64 - UNIX_EPOCH_SERIAL)
+}
+
+fn days_in_month(y: i64, m: i64) -> i64 {
+    serial_from_ymd(y, m + 1, 1) - serial_from_ymd(y, m, 1)
+}
+
+// 0 is Sunday
+fn weekday_index(serial: i64) -> i64 {
+    (serial - UNIX_EPOCH_SERIAL + 4).rem_euclid(7)
+}
+
+fn is_weekend(serial: i64) -> bool {
+    matches!(weekday_index(serial), 0 | 6)
+}
+
+// Parses ISO style dates and times: 2024-01-15, 2024-01-15 13:45, 2024-01-15T13:45:30 and
+// time only values such as 13:45
+pub fn parse_datetime(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (Some(date), Some(time)),
+        None if s.contains(':') => (None, Some(s)),
+        None => (Some(s), None),
+    };
+
+    let mut serial = 0.0;
+
+    if let Some(date) = date {
+        let parts: Vec<&str> = date.split('-').collect();
+        if parts.len() != 3 || parts[0].len() != 4 || parts.iter().any(|it| it.is_empty()) {
+            return None;
+        }
+
+        let nums: Vec<i64> = parts
+            .iter()
+            .map(|it| {
+                it.parse::<i64>()
+                    .ok()
+                    .filter(|_| it.chars().all(|c| c.is_ascii_digit()))
+            })
+            .collect::<Option<_>>()?;
+        let (y, m, d) = (nums[0], nums[1], nums[2]);
+
+        if !(1..=12).contains(&m) || d < 1 || d > days_in_month(y, m) {
+            return None;
+        }
+        serial += serial_from_ymd(y, m, d) as f64;
+    }
+
+    if let Some(time) = time {
+        let parts: Vec<&str> = time.split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return None;
+        }
+
+        let h = parts[0].parse::<u32>().ok().filter(|h| *h < 24)?;
+        let m = parts[1].parse::<u32>().ok().filter(|m| *m < 60)?;
+        let sec = match parts.get(2) {
+            Some(it) => it.parse::<f64>().ok().filter(|s| (0.0..60.0).contains(s))?,
+            None => 0.0,
+        };
+        serial += (h as f64 * 3600.0 + m as f64 * 60.0 + sec) / SECONDS_PER_DAY;
+    }
+
+    Some(serial)
+}
+
+// -------------------------------------------------- //
+
+fn in_range(serial: f64) -> bool {
+    (0.0..MAX_SERIAL + 1.0).contains(&serial)
+}
+
+fn date(serial: f64) -> Result<Eval, LeadErr> {
+    if in_range(serial) {
+        Ok(Eval::Literal(Literal::DateTime(serial)))
+    } else {
+        Err(eval_err(
+            "Dates must be between 1899-12-30 and 9999-12-31.".into(),
+            LeadErrCode::Invalid,
+        ))
+    }
+}
+
+// Whole number argument within -limit..=limit
+fn eval_bounded(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    limit: f64,
+    func_name: &str,
+) -> Result<i64, LeadErr> {
+    let n = eval_number(arg, precs, grid, func_name)?.trunc();
+
+    if n.abs() > limit {
+        return Err(eval_err(
+            format!("{func_name} argument {n} is out of range."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(n as i64)
+}
+
+fn number(n: f64) -> Result<Eval, LeadErr> {
+    Ok(Eval::Literal(Literal::Number(n)))
+}
+
+// Serial of a date argument, dates given as text are parsed
+fn eval_serial(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<f64, LeadErr> {
+    let serial = eval_number(arg, precs, grid, func_name)?;
+
+    if !in_range(serial) {
+        return Err(eval_err(
+            format!("{func_name} requires a date between 1899-12-30 and 9999-12-31."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(serial)
+}
+
+fn eval_holidays(
+    arg: Option<&Expr>,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<HashSet<i64>, LeadErr> {
+    match arg {
+        Some(arg) => Ok(collect_numbers(
+            std::slice::from_ref(arg),
+            precs,
+            grid,
+            NonNumeric::Coerce,
+            func_name,
+        )?
+        .into_iter()
+        .map(|it| it.floor() as i64)
+        .collect()),
+        None => Ok(HashSet::new()),
+    }
+}
+
+pub fn eval_today(grid: Option<&Grid>) -> Result<Eval, LeadErr> {
+    date(grid.map_or(SystemClock.now(), |g| g.clock().now()).floor())
+}
+
+pub fn eval_now(grid: Option<&Grid>) -> Result<Eval, LeadErr> {
+    date(grid.map_or(SystemClock.now(), |g| g.clock().now()))
+}
+
+// DATE(year, month, day), years below 1900 are offset from 1900 like Excel
+pub fn eval_date(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let y = eval_bounded(&args[0], precs, grid, MAX_YEAR, "DATE")?;
+    let m = eval_bounded(&args[1], precs, grid, MAX_MONTHS, "DATE")?;
+    let d = eval_bounded(&args[2], precs, grid, MAX_SERIAL, "DATE")?;
+
+    if y < 0 {
+        return Err(eval_err(
+            "DATE requires a year between 0 and 9999.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let y = if y < 1900 { y + 1900 } else { y };
+    date(serial_from_ymd(y, m, d) as f64)
+}
+
+// TIME(hour, minute, second), wraps around after 24 hours
+pub fn eval_time(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let mut secs = 0.0;
+    for (arg, scale) in args.iter().zip([3600.0, 60.0, 1.0]) {
+        secs += eval_number(arg, precs, grid, "TIME")?.trunc() * scale;
+    }
+
+    date((secs / SECONDS_PER_DAY).rem_euclid(1.0))
+}
+
+// YEAR, MONTH and DAY
+pub fn eval_date_part(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let (y, m, d) = ymd_from_serial(eval_serial(&args[0], precs, grid, func_name)?);
+    let res = match func_name {
+        "YEAR" => y,
+        "MONTH" => m,
+        _ => d,
+    };
+
+    number(res as f64)
+}
+
+// WEEKDAY(date, [type]) with Excel's return types 1, 2, 3 and 11 to 17
+pub fn eval_weekday(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let serial = eval_serial(&args[0], precs, grid, "WEEKDAY")?.floor() as i64;
+    let kind = match args.get(1) {
+        Some(arg) => eval_number(arg, precs, grid, "WEEKDAY")?.trunc() as i64,
+        None => 1,
+    };
+
+    // Index of the weekday that is numbered first, 0 being Sunday
+    let (first, base) = match kind {
+        1 => (0, 1),
+        2 => (1, 1),
+        3 => (1, 0),
+        11..=17 => ((kind - 10) % 7, 1),
+        _ => {
+            return Err(eval_err(
+                format!("WEEKDAY does not support return type {kind}."),
+                LeadErrCode::Invalid,
+            ));
+        }
+    };
+
+    number(((weekday_index(serial) - first).rem_euclid(7) + base) as f64)
+}
+
+fn add_months(serial: f64, months: i64, end_of_month: bool) -> f64 {
+    let (y, m, d) = ymd_from_serial(serial);
+    let (y, m) = (
+        y + (m - 1 + months).div_euclid(12),
+        (m - 1 + months).rem_euclid(12) + 1,
+    );
+
+    let last = days_in_month(y, m);
+    let d = if end_of_month { last } else { d.min(last) };
+
+    serial_from_ymd(y, m, d) as f64
+}
+
+// EDATE(start, months) and EOMONTH(start, months)
+pub fn eval_month_offset(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let start = eval_serial(&args[0], precs, grid, func_name)?;
+    let months = eval_bounded(&args[1], precs, grid, MAX_MONTHS, func_name)?;
+
+    date(add_months(start, months, func_name == "EOMONTH"))
+}
+
+pub fn datedif(start: f64, end: f64, unit: &str) -> Result<f64, LeadErr> {
+    let (s, e) = (start.floor() as i64, end.floor() as i64);
+    if s > e {
+        return Err(eval_err(
+            "DATEDIF requires the start date to be on or before the end date.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let (y1, m1, d1) = ymd_from_serial(start);
+    let (y2, m2, d2) = ymd_from_serial(end);
+    let months = (y2 - y1) * 12 + (m2 - m1) - if d2 < d1 { 1 } else { 0 };
+
+    let res = match unit.to_uppercase().as_str() {
+        "Y" => months / 12,
+        "M" => months,
+        "D" => e - s,
+        "YM" => months % 12,
+        "MD" => {
+            if d2 >= d1 {
+                d2 - d1
+            } else {
+                let (py, pm) = if m2 == 1 { (y2 - 1, 12) } else { (y2, m2 - 1) };
+                days_in_month(py, pm) - d1 + d2
+            }
+        }
+        "YD" => {
+            let shifted = |y| serial_from_ymd(y, m1, d1.min(days_in_month(y, m1)));
+            let anniversary = if shifted(y2) <= e {
+                shifted(y2)
+            } else {
+                shifted(y2 - 1)
+            };
+            e - anniversary
+        }
+        it => {
+            return Err(eval_err(
+                format!("DATEDIF does not support the unit {it:?}."),
+                LeadErrCode::Invalid,
+            ));
+        }
+    };
+
+    Ok(res as f64)
+}
+
+// DATEDIF(start, end, unit)
+pub fn eval_datedif(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let start = eval_serial(&args[0], precs, grid, "DATEDIF")?;
+    let end = eval_serial(&args[1], precs, grid, "DATEDIF")?;
+    let unit = match evaluate_expr(&args[2], precs, grid)? {
+        Eval::Literal(Literal::String(s)) => s,
+        Eval::CellRef { eval, .. } => match *eval {
+            Eval::Literal(Literal::String(s)) => s,
+            _ => String::new(),
+        },
+        _ => String::new(),
+    };
+
+    number(datedif(start, end, &unit)?)
+}
+
+// Working days from lo to hi, both ends included. Any 7 consecutive days hold 5 weekdays so
+// only the days after the last whole week are checked one by one.
+fn working_days(lo: i64, hi: i64, holidays: &HashSet<i64>) -> i64 {
+    let weeks = (hi - lo + 1) / 7;
+    let rest = (lo + weeks * 7..=hi).filter(|d| !is_weekend(*d)).count() as i64;
+    let off = holidays
+        .iter()
+        .filter(|d| (lo..=hi).contains(*d) && !is_weekend(**d))
+        .count() as i64;
+
+    weeks * 5 + rest - off
+}
+
+// Working days between two dates, both ends included. Negative when end is before start.
+pub fn networkdays(start: f64, end: f64, holidays: &HashSet<i64>) -> f64 {
+    let (s, e) = (start.floor() as i64, end.floor() as i64);
+    let (lo, hi, sign) = if s <= e { (s, e, 1.0) } else { (e, s, -1.0) };
+
+    sign * working_days(lo, hi, holidays) as f64
+}
+
+// The date a number of working days before or after start. Whole weeks are jumped at once,
+// leaving at least one working day so the last steps land on a working day.
+pub fn workday(start: f64, days: i64, holidays: &HashSet<i64>) -> f64 {
+    let mut serial = start.floor() as i64;
+    let step = days.signum();
+    let mut remaining = days.abs();
+
+    while remaining > 0 {
+        let weeks = (remaining - 1) / 5;
+        if weeks > 0 {
+            let next = serial + step * weeks * 7;
+            let (lo, hi) = if step > 0 {
+                (serial + 1, next)
+            } else {
+                (next, serial - 1)
+            };
+            remaining -= working_days(lo, hi, holidays);
+            serial = next;
+            continue;
+        }
+
+        serial += step;
+        if !is_weekend(serial) && !holidays.contains(&serial) {
+            remaining -= 1;
+        }
+    }
+
+    serial as f64
+}
+
+// NETWORKDAYS(start, end, [holidays])
+pub fn eval_networkdays(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let start = eval_serial(&args[0], precs, grid, "NETWORKDAYS")?;
+    let end = eval_serial(&args[1], precs, grid, "NETWORKDAYS")?;
+    let holidays = eval_holidays(args.get(2), precs, grid, "NETWORKDAYS")?;
+
+    number(networkdays(start, end, &holidays))
+}
+
+// WORKDAY(start, days, [holidays])
+pub fn eval_workday(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let start = eval_serial(&args[0], precs, grid, "WORKDAY")?;
+    let days = eval_bounded(&args[1], precs, grid, MAX_SERIAL, "WORKDAY")?;
+    let holidays = eval_holidays(args.get(2), precs, grid, "WORKDAY")?;
+
+    date(workday(start, days, &holidays))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    fn serial(s: &str) -> f64 {
+        parse_datetime(s).unwrap()
+    }
+
+    #[test]
+    fn test_serial_conversions() {
+        assert_eq!(serial("1899-12-30"), 0.0);
+        assert_eq!(serial("1900-01-01"), 2.0);
+        assert_eq!(serial("1900-03-01"), 61.0);
+        assert_eq!(serial("2024-01-01"), 45292.0);
+        assert_eq!(serial("2008-01-01"), 39448.0);
+        assert_eq!(serial("2024-01-01 12:00"), 45292.5);
+        assert_eq!(serial("2024-01-01T06:00:00"), 45292.25);
+        assert_eq!(serial("18:00"), 0.75);
+        assert_eq!(ymd_from_serial(45352.7), (2024, 3, 1));
+        assert_eq!(parse_datetime("2023-02-29"), None);
+        assert_eq!(parse_datetime("2024-1"), None);
+        assert_eq!(parse_datetime("24:00"), None);
+        assert_eq!(serial_from_ymd(2024, 14, 1), serial_from_ymd(2025, 2, 1));
+        assert_eq!(serial_from_ymd(2024, 3, 0), serial_from_ymd(2024, 2, 29));
+    }
+
+    #[test]
+    fn test_datedif() {
+        let d = |a, b, unit| datedif(serial(a), serial(b), unit).unwrap();
+
+        assert_eq!(d("2001-01-01", "2003-01-01", "Y"), 2.0);
+        assert_eq!(d("2001-06-01", "2002-08-15", "D"), 440.0);
+        assert_eq!(d("2001-06-01", "2002-08-15", "YD"), 75.0);
+        assert_eq!(d("2001-06-01", "2002-08-15", "M"), 14.0);
+        assert_eq!(d("2001-06-01", "2002-08-15", "YM"), 2.0);
+        assert_eq!(d("2001-06-15", "2002-08-01", "MD"), 17.0);
+        assert!(datedif(serial("2002-01-01"), serial("2001-01-01"), "D").is_err());
+    }
+
+    #[test]
+    fn test_date_functions() {
+        let mut grid = Grid::new().with_clock(FixedClock(serial("2024-05-17 15:30")));
+        set_cells(
+            &mut grid,
+            &[
+                ("A1", "2024-01-31"),
+                ("B1", "=A1+30"),
+                ("A2", "2024-01-01"),
+                ("A3", "2024-01-15"),
+            ],
+        );
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        let date = |s: &str| Eval::Literal(Literal::DateTime(serial(s)));
+
+        assert_eq!(value(&grid, "B1"), date("2024-03-01"));
+        assert_eq!(eval("A1 - A2"), num(30.0));
+        assert_eq!(eval("A1 - 30"), date("2024-01-01"));
+        assert_eq!(eval("TODAY()"), date("2024-05-17"));
+        assert_eq!(eval("NOW()"), date("2024-05-17 15:30"));
+        assert_eq!(eval("DATE(2024, 2, 30)"), date("2024-03-01"));
+        assert_eq!(eval("DATE(124, 1, 1)"), date("2024-01-01"));
+        assert_eq!(eval("TIME(36, 0, 0)"), date("12:00"));
+        assert_eq!(eval("YEAR(A1)"), num(2024.0));
+        assert_eq!(eval("MONTH(A1)"), num(1.0));
+        assert_eq!(eval("DAY(\"2024-01-31\")"), num(31.0));
+        // 2024-01-01 was a Monday
+        assert_eq!(eval("WEEKDAY(A2)"), num(2.0));
+        assert_eq!(eval("WEEKDAY(A2, 2)"), num(1.0));
+        assert_eq!(eval("WEEKDAY(A2, 3)"), num(0.0));
+        assert_eq!(eval("WEEKDAY(A2, 16)"), num(3.0));
+        assert_eq!(eval("EDATE(A1, 1)"), date("2024-02-29"));
+        assert_eq!(eval("EDATE(A1, -13)"), date("2022-12-31"));
+        assert_eq!(eval("EOMONTH(A2, 1)"), date("2024-02-29"));
+        assert_eq!(eval("DATEDIF(A2, A1, \"D\")"), num(30.0));
+        assert_eq!(eval("NETWORKDAYS(A2, A1)"), num(23.0));
+        assert_eq!(eval("NETWORKDAYS(A2, A1, A3)"), num(22.0));
+        assert_eq!(eval("NETWORKDAYS(A1, A2)"), num(-23.0));
+        assert_eq!(eval("WORKDAY(A2, 10)"), date("2024-01-15"));
+        assert_eq!(eval("WORKDAY(A2, 10, A3)"), date("2024-01-16"));
+        assert_eq!(eval("WORKDAY(A2, -1)"), date("2023-12-29"));
+        assert_eq!(eval("DATE(9999, 12, 31)"), date("9999-12-31"));
+        assert_eq!(eval("NETWORKDAYS(1, 2958465)"), num(2113190.0));
+        assert_eq!(
+            eval("SUMIF(A1:A3, \">=2024-01-10\")"),
+            num(serial("2024-01-31") + serial("2024-01-15"))
+        );
+    }
+    #[test]
+    fn test_date_limits() {
+        let invalid = |s: &str| is_err(evaluate(s.into(), None).0, LeadErrCode::Invalid);
+
+        assert!(invalid("DATE(1e18, 1, 1)"));
+        assert!(invalid("DATE(2024, 1e18, 1)"));
+        assert!(invalid("DATE(2024, 1, 1e18)"));
+        assert!(invalid("DATE(9999, 12, 32)"));
+        assert!(invalid("DATE(-1, 1, 1)"));
+        assert!(invalid("EDATE(1, 1e18)"));
+        assert!(invalid("EDATE(2958465, 1)"));
+        assert!(invalid("YEAR(1e300)"));
+        assert!(invalid("WORKDAY(1, 1e10)"));
+        assert!(invalid("WORKDAY(2958465, 1)"));
+        assert!(invalid("NETWORKDAYS(1, 1e11)"));
+    }
+
+    #[test]
+    fn test_working_days() {
+        // One day at a time, the definition the week counting has to agree with
+        let is_working = |d: i64, holidays: &HashSet<i64>| !is_weekend(d) && !holidays.contains(&d);
+        let holidays: HashSet<i64> = [45294, 45300, 45301, 45306, 45330].into();
+
+        for start in 45285..45300 {
+            for end in 45280..45350 {
+                let (lo, hi) = (start.min(end), start.max(end));
+                let count = (lo..=hi).filter(|d| is_working(*d, &holidays)).count() as f64;
+                let sign = if start <= end { 1.0 } else { -1.0 };
+                assert_eq!(
+                    networkdays(start as f64, end as f64, &holidays),
+                    sign * count
+                );
+            }
+
+            for days in -40i64..40 {
+                let mut serial = start;
+                for _ in 0..days.abs() {
+                    serial += days.signum();
+                    while !is_working(serial, &holidays) {
+                        serial += days.signum();
+                    }
+                }
+                assert_eq!(workday(start as f64, days, &holidays), serial as f64);
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Eval, Precedents, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Time value of money functions follow Excel's sign convention: money paid out is negative and
+// money received is positive, so every function solves
+//
+// pv (1 + r)^n + pmt (1 + r type) ((1 + r)^n - 1) / r + fv = 0
+//
+// where type is 1 for payments at the start of a period and 0 for the end.
+//
+// Dates used by XNPV and XIRR are the serial day numbers of the datetime module: day 0 is
+// 1899-12-30, so 1900-01-01 is 2 and 2024-01-01 is 45292. Only the differences between dates
+// matter, each year is counted as 365 days.
+
+// The iterative solvers (IRR, XIRR and RATE) run Newton's method from the guess for at most
+// MAX_NEWTON_ITER steps, stopping once a step is smaller than RATE_TOL relative to the rate. If
+// that fails they look for a sign change of the function over a fixed set of rates above -1 and
+// bisect it for at most MAX_BISECT_ITER steps. If neither converges an Invalid error is returned.
+const MAX_NEWTON_ITER: usize = 100;
+const MAX_BISECT_ITER: usize = 200;
+const RATE_TOL: f64 = 1e-12;
+
+fn find_rate(f: impl Fn(f64) -> f64, guess: f64, func_name: &str) -> Result<f64, LeadErr> {
+    // Newton's method with a central difference derivative
+    let mut x = guess;
+    for _ in 0..MAX_NEWTON_ITER {
+        let y = f(x);
+        let h = 1e-6 * (1.0 + x.abs());
+        let dy = (f(x + h) - f(x - h)) / (2.0 * h);
+
+        if !y.is_finite() || !dy.is_finite() || dy == 0.0 {
+            break;
+        }
+
+        let next = x - y / dy;
+        if next <= -1.0 {
+            // Rates at or below -100% are undefined, step half way towards -1 instead
+            x = (x - 1.0) / 2.0;
+            continue;
+        }
+
+        if (next - x).abs() <= RATE_TOL * x.abs().max(1.0) {
+            return Ok(next);
+        }
+        x = next;
+    }
+
+    // Bisection over the first bracket with a sign change
+    let candidates = [
+        -0.999999, -0.99, -0.9, -0.75, -0.5, -0.25, -0.1, 0.0, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 5.0,
+        10.0, 100.0, 1000.0,
+    ];
+    for pair in candidates.windows(2) {
+        let (mut lo, mut hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (f(lo), f(hi));
+
+        if !f_lo.is_finite() || !f_hi.is_finite() || f_lo.signum() == f_hi.signum() {
+            continue;
+        }
+
+        for _ in 0..MAX_BISECT_ITER {
+            let mid = (lo + hi) / 2.0;
+            if f(mid).signum() == f_lo.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+
+            if hi - lo <= RATE_TOL * lo.abs().max(1.0) {
+                return Ok((lo + hi) / 2.0);
+            }
+        }
+    }
+
+    Err(eval_err(
+        format!("{func_name} did not converge to a rate."),
+        LeadErrCode::Invalid,
+    ))
+}
+
+// -------------------------------------------------- //
+
+// Growth factor of an annuity, the limit as r -> 0 is used for a zero rate
+fn annuity(rate: f64, nper: f64, kind: f64) -> f64 {
+    if rate == 0.0 {
+        nper
+    } else {
+        (1.0 + rate * kind) * ((1.0 + rate).powf(nper) - 1.0) / rate
+    }
+}
+
+pub fn pmt(rate: f64, nper: f64, pv: f64, fv: f64, kind: f64) -> f64 {
+    -(pv * (1.0 + rate).powf(nper) + fv) / annuity(rate, nper, kind)
+}
+
+pub fn pv(rate: f64, nper: f64, pmt: f64, fv: f64, kind: f64) -> f64 {
+    -(fv + pmt * annuity(rate, nper, kind)) / (1.0 + rate).powf(nper)
+}
+
+pub fn fv(rate: f64, nper: f64, pmt: f64, pv: f64, kind: f64) -> f64 {
+    -(pv * (1.0 + rate).powf(nper) + pmt * annuity(rate, nper, kind))
+}
+
+pub fn nper(rate: f64, pmt: f64, pv: f64, fv: f64, kind: f64) -> Result<f64, LeadErr> {
+    let res = if rate == 0.0 {
+        -(pv + fv) / pmt
+    } else {
+        let p = pmt * (1.0 + rate * kind);
+        ((p - fv * rate) / (p + pv * rate)).ln() / (1.0 + rate).ln()
+    };
+
+    if res.is_finite() {
+        Ok(res)
+    } else {
+        Err(eval_err(
+            "NPER has no solution for these cash flows.".into(),
+            LeadErrCode::Invalid,
+        ))
+    }
+}
+
+pub fn rate(nper: f64, pmt: f64, pv: f64, fv: f64, kind: f64, guess: f64) -> Result<f64, LeadErr> {
+    find_rate(
+        |r| pv * (1.0 + r).powf(nper) + pmt * annuity(r, nper, kind) + fv,
+        guess,
+        "RATE",
+    )
+}
+
+// Values are discounted starting one period from now, as in Excel
+pub fn npv(rate: f64, values: &[f64]) -> f64 {
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| v / (1.0 + rate).powi(i as i32 + 1))
+        .sum()
+}
+
+pub fn irr(values: &[f64], guess: f64) -> Result<f64, LeadErr> {
+    require_sign_change(values, "IRR")?;
+    find_rate(|r| npv(r, values) * (1.0 + r), guess, "IRR")
+}
+
+pub fn xnpv(rate: f64, values: &[f64], dates: &[f64]) -> f64 {
+    values
+        .iter()
+        .zip(dates)
+        .map(|(v, d)| v / (1.0 + rate).powf((d - dates[0]) / 365.0))
+        .sum()
+}
+
+pub fn xirr(values: &[f64], dates: &[f64], guess: f64) -> Result<f64, LeadErr> {
+    require_sign_change(values, "XIRR")?;
+    find_rate(|r| xnpv(r, values, dates), guess, "XIRR")
+}
+
+fn require_sign_change(values: &[f64], func_name: &str) -> Result<(), LeadErr> {
+    if !values.iter().any(|v| *v > 0.0) || !values.iter().any(|v| *v < 0.0) {
+        return Err(eval_err(
+            format!("{func_name} requires at least one positive and one negative value."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(())
+}
+
+// -------------------------------------------------- //
+
+fn eval_opt_number(
+    args: &[Expr],
+    idx: usize,
+    default: f64,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<f64, LeadErr> {
+    match args.get(idx) {
+        Some(arg) => eval_number(arg, precs, grid, func_name),
+        None => Ok(default),
+    }
+}
+
+fn number(res: f64, func_name: &str) -> Result<Eval, LeadErr> {
+    if res.is_finite() {
+        Ok(Eval::Literal(Literal::Number(res)))
+    } else {
+        Err(eval_err(
+            format!("{func_name} has no finite result for these arguments."),
+            LeadErrCode::Invalid,
+        ))
+    }
+}
+
+// FUNC(a, b, c, [d], [e]) where d and e default to 0, the shape shared by PMT, PV, FV and NPER
+pub fn eval_tvm_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: fn(f64, f64, f64, f64, f64) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let mut nums = [0.0; 5];
+    for (idx, num) in nums.iter_mut().enumerate() {
+        *num = eval_opt_number(args, idx, 0.0, precs, grid, func_name)?;
+    }
+
+    number(
+        func(nums[0], nums[1], nums[2], nums[3], nums[4])?,
+        func_name,
+    )
+}
+
+// RATE(nper, pmt, pv, [fv], [type], [guess])
+pub fn eval_rate(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let mut nums = [0.0; 6];
+    for (idx, num) in nums.iter_mut().enumerate() {
+        let default = if idx == 5 { 0.1 } else { 0.0 };
+        *num = eval_opt_number(args, idx, default, precs, grid, "RATE")?;
+    }
+
+    number(
+        rate(nums[0], nums[1], nums[2], nums[3], nums[4], nums[5])?,
+        "RATE",
+    )
+}
+
+// NPV(rate, value1, ...)
+pub fn eval_npv(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let rate = eval_number(&args[0], precs, grid, "NPV")?;
+    let values = collect_numbers(&args[1..], precs, grid, NonNumeric::Coerce, "NPV")?;
+
+    number(npv(rate, &values), "NPV")
+}
+
+// IRR(values, [guess])
+pub fn eval_irr(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let values = collect_numbers(&args[..1], precs, grid, NonNumeric::Coerce, "IRR")?;
+    let guess = eval_opt_number(args, 1, 0.1, precs, grid, "IRR")?;
+
+    number(irr(&values, guess)?, "IRR")
+}
+
+fn eval_dated_values(
+    values: &Expr,
+    dates: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<(Vec<f64>, Vec<f64>), LeadErr> {
+    let strict = |evals: Vec<Eval>| -> Result<Vec<f64>, LeadErr> {
+        evals
+            .into_iter()
+            .map(|it| match it {
+                Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => Ok(n),
+                Eval::Err(e) => Err(e),
+                _ => Err(eval_err(
+                    format!("{func_name} requires numeric values and dates."),
+                    LeadErrCode::TypeErr,
+                )),
+            })
+            .collect()
+    };
+
+    let values = strict(eval_range_values(values, precs, grid)?)?;
+    let dates = strict(eval_range_values(dates, precs, grid)?)?;
+
+    if values.len() != dates.len() || values.is_empty() {
+        return Err(eval_err(
+            format!(
+                "{func_name} requires the same number of values and dates, found {} and {}.",
+                values.len(),
+                dates.len()
+            ),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    if dates.iter().any(|d| *d < dates[0]) {
+        return Err(eval_err(
+            format!("{func_name} requires every date to be on or after the first date."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok((values, dates))
+}
+
+// XNPV(rate, values, dates)
+pub fn eval_xnpv(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let rate = eval_number(&args[0], precs, grid, "XNPV")?;
+    let (values, dates) = eval_dated_values(&args[1], &args[2], precs, grid, "XNPV")?;
+
+    number(xnpv(rate, &values, &dates), "XNPV")
+}
+
+// XIRR(values, dates, [guess])
+pub fn eval_xirr(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let (values, dates) = eval_dated_values(&args[0], &args[1], precs, grid, "XIRR")?;
+    let guess = eval_opt_number(args, 2, 0.1, precs, grid, "XIRR")?;
+
+    number(xirr(&values, &dates, guess)?, "XIRR")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values are the outputs of Excel on the same arguments
+    #[test]
+    fn test_finance_table() {
+        let flows = [-70000.0, 12000.0, 15000.0, 18000.0, 21000.0, 26000.0];
+        let x_values = [-10000.0, 2750.0, 4250.0, 3250.0, 2750.0];
+        // 2008-01-01, 2008-03-01, 2008-10-30, 2009-02-15, 2009-04-01
+        let x_dates = [39448.0, 39508.0, 39751.0, 39859.0, 39904.0];
+
+        let table: Vec<(&str, Result<f64, LeadErr>, f64)> = vec![
+            (
+                "PMT",
+                Ok(pmt(0.08 / 12.0, 10.0, 10000.0, 0.0, 0.0)),
+                -1037.032089359152,
+            ),
+            (
+                "PMT",
+                Ok(pmt(0.06 / 12.0, 216.0, 0.0, 50000.0, 0.0)),
+                -129.0811608679909,
+            ),
+            ("PMT", Ok(pmt(0.0, 10.0, 1000.0, 0.0, 0.0)), -100.0),
+            (
+                "PV",
+                Ok(pv(0.08 / 12.0, 240.0, 500.0, 0.0, 0.0)),
+                -59777.14585118802,
+            ),
+            (
+                "FV",
+                Ok(fv(0.06 / 12.0, 10.0, -200.0, -500.0, 1.0)),
+                2581.403374060179,
+            ),
+            (
+                "NPER",
+                nper(0.01, -100.0, -1000.0, 10000.0, 1.0),
+                59.67386567429457,
+            ),
+            (
+                "NPV",
+                Ok(npv(0.1, &[-10000.0, 3000.0, 4200.0, 6800.0])),
+                1188.443412335223,
+            ),
+            ("IRR", irr(&flows, 0.1), 0.08663094803653162),
+            ("IRR", irr(&flows[..5], 0.1), -0.02124484827341099),
+            ("IRR", irr(&flows[..3], -0.1), -0.4435069413347405),
+            (
+                "XNPV",
+                Ok(xnpv(0.09, &x_values, &x_dates)),
+                2086.647602031537,
+            ),
+            ("XIRR", xirr(&x_values, &x_dates, 0.1), 0.3733625335188315),
+            (
+                "RATE",
+                rate(48.0, -200.0, 8000.0, 0.0, 0.0, 0.1),
+                0.007701472488202044,
+            ),
+        ];
+
+        for (name, res, expected) in table {
+            assert_close(name, res, expected);
+        }
+    }
+
+    #[test]
+    fn test_finance_errors() {
+        assert_eq!(
+            irr(&[100.0, 200.0], 0.1).unwrap_err().code,
+            LeadErrCode::Invalid
+        );
+        // A loan that is never paid back has no rate
+        assert_eq!(
+            rate(10.0, 100.0, 1000.0, 0.0, 0.0, 0.1).unwrap_err().code,
+            LeadErrCode::Invalid
+        );
+    }
+
+    #[test]
+    fn test_finance_functions() {
+        use crate::evaluator::evaluate;
+
+        let grid = grid_from(&[&["-10000", "39448"], &["2750", "39508"], &["4250", "39751"]]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        assert_eq!(eval("PMT(0, 10, 1000)"), num(-100.0));
+        assert_eq!(eval("FV(0, 10, -100)"), num(1000.0));
+        assert_eq!(
+            eval("XNPV(0.09, A1:A3, B1:B3)"),
+            num(xnpv(
+                0.09,
+                &[-10000.0, 2750.0, 4250.0],
+                &[39448.0, 39508.0, 39751.0]
+            ))
+        );
+        assert!(matches!(eval("XIRR(A1:A3, B1:B2)"), Eval::Err(_)));
+        assert!(matches!(eval("PMT(0.1, 10)"), Eval::Err(_)));
+    }
+}
@@ -0,0 +1,364 @@
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Array, Eval, Precedents, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+type Matrix = Vec<Vec<f64>>;
+
+// Relative size below which a pivot marks a singular matrix
+const SINGULAR_TOL: f64 = 1e-12;
+
+// Largest square matrix MUNIT builds or the LU based functions factor, a million cells like the
+// array functions and about a billion steps to decompose
+const MAX_SIZE: usize = 1 << 10;
+
+fn singular_err(func_name: &str) -> LeadErr {
+    eval_err(
+        format!("{func_name} found a singular matrix."),
+        LeadErrCode::Invalid,
+    )
+}
+
+// Evaluates an argument into rows of numbers, every element must be a number
+fn eval_matrix(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Matrix, LeadErr> {
+    to_matrix(eval_array(arg, precs, grid)?, func_name)
+}
+
+fn to_matrix(array: Array, func_name: &str) -> Result<Matrix, LeadErr> {
+    let mut rows = Vec::with_capacity(array.rows);
+
+    for row in array.rows() {
+        let mut numbers = Vec::with_capacity(array.cols);
+        for value in row {
+            numbers.push(match value {
+                Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => *n,
+                Eval::Err(e) => return Err(e.to_owned()),
+                _ => {
+                    return Err(eval_err(
+                        format!("{func_name} requires a matrix of numbers."),
+                        LeadErrCode::TypeErr,
+                    ));
+                }
+            });
+        }
+        rows.push(numbers);
+    }
+
+    Ok(rows)
+}
+
+fn eval_square(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Matrix, LeadErr> {
+    let array = eval_array(arg, precs, grid)?;
+
+    if array.rows != array.cols {
+        return Err(eval_err(
+            format!("{func_name} requires a square matrix."),
+            LeadErrCode::Invalid,
+        ));
+    }
+    if array.rows > MAX_SIZE {
+        return Err(eval_err(
+            format!("{func_name} requires a matrix of at most {MAX_SIZE}x{MAX_SIZE}."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    to_matrix(array, func_name)
+}
+
+fn matrix_result(matrix: Matrix) -> Eval {
+    let rows = matrix
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|n| Eval::Literal(Literal::Number(n)))
+                .collect()
+        })
+        .collect();
+
+    Eval::Range(Array::from_rows(rows))
+}
+
+// -------------------------------------------------- //
+
+// LU decomposition with partial pivoting, PA = LU. L (below the diagonal, with an implied unit
+// diagonal) and U are stored in one matrix.
+struct Lu {
+    lu: Matrix,
+    // Row of A at each row of PA
+    perm: Vec<usize>,
+    // Sign of the permutation, for the determinant
+    sign: f64,
+}
+
+impl Lu {
+    // None for singular matrices
+    #[allow(clippy::needless_range_loop)]
+    fn new(a: &Matrix) -> Option<Lu> {
+        let n = a.len();
+        let mut lu = a.to_owned();
+        let mut perm: Vec<usize> = (0..n).collect();
+        let mut sign = 1.0;
+
+        let scale = a.iter().flatten().fold(0.0_f64, |max, x| max.max(x.abs()));
+        if scale == 0.0 {
+            return None;
+        }
+
+        for k in 0..n {
+            // The largest remaining entry of the column as the pivot keeps the multipliers at
+            // most one in size
+            let pivot = (k..n).max_by(|&i, &j| lu[i][k].abs().total_cmp(&lu[j][k].abs()))?;
+            if lu[pivot][k].abs() <= SINGULAR_TOL * scale {
+                return None;
+            }
+
+            if pivot != k {
+                lu.swap(pivot, k);
+                perm.swap(pivot, k);
+                sign = -sign;
+            }
+
+            for i in k + 1..n {
+                let factor = lu[i][k] / lu[k][k];
+                lu[i][k] = factor;
+                for j in k + 1..n {
+                    lu[i][j] -= factor * lu[k][j];
+                }
+            }
+        }
+
+        Some(Lu { lu, perm, sign })
+    }
+
+    fn determinant(&self) -> f64 {
+        (0..self.lu.len()).fold(self.sign, |det, i| det * self.lu[i][i])
+    }
+
+    // Solves Ax = b by forward and back substitution
+    fn solve(&self, b: &[f64]) -> Vec<f64> {
+        let n = self.lu.len();
+        let mut x: Vec<f64> = self.perm.iter().map(|&i| b[i]).collect();
+
+        for i in 0..n {
+            let s: f64 = (0..i).map(|j| self.lu[i][j] * x[j]).sum();
+            x[i] -= s;
+        }
+        for i in (0..n).rev() {
+            let s: f64 = (i + 1..n).map(|j| self.lu[i][j] * x[j]).sum();
+            x[i] = (x[i] - s) / self.lu[i][i];
+        }
+
+        x
+    }
+}
+
+fn transpose(matrix: &Matrix) -> Matrix {
+    let cols = matrix.first().map_or(0, |row| row.len());
+    (0..cols)
+        .map(|col| matrix.iter().map(|row| row[col]).collect())
+        .collect()
+}
+
+// Solves AX = B for each column of B
+fn solve_columns(a: &Matrix, b: &Matrix, func_name: &str) -> Result<Matrix, LeadErr> {
+    let lu = Lu::new(a).ok_or_else(|| singular_err(func_name))?;
+    let columns: Matrix = transpose(b).iter().map(|col| lu.solve(col)).collect();
+    Ok(transpose(&columns))
+}
+
+fn identity(n: usize) -> Matrix {
+    (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect()
+}
+
+// -------------------------------------------------- //
+
+pub fn eval_mmult(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let a = eval_matrix(&args[0], precs, grid, "MMULT")?;
+    let b = eval_matrix(&args[1], precs, grid, "MMULT")?;
+
+    if a[0].len() != b.len() {
+        return Err(eval_err(
+            format!(
+                "MMULT requires the columns of the first matrix ({}) to match the rows of the second ({}).",
+                a[0].len(),
+                b.len()
+            ),
+            LeadErrCode::Invalid,
+        ));
+    }
+    check_cells(a.len(), b[0].len(), "MMULT")?;
+
+    let b_cols = transpose(&b);
+    let product = a
+        .iter()
+        .map(|row| {
+            b_cols
+                .iter()
+                .map(|col| row.iter().zip(col).map(|(x, y)| x * y).sum())
+                .collect()
+        })
+        .collect();
+
+    Ok(matrix_result(product))
+}
+
+pub fn eval_mdeterm(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let a = eval_square(&args[0], precs, grid, "MDETERM")?;
+    let det = Lu::new(&a).map_or(0.0, |lu| lu.determinant());
+    Ok(Eval::Literal(Literal::Number(det)))
+}
+
+pub fn eval_minverse(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let a = eval_square(&args[0], precs, grid, "MINVERSE")?;
+    Ok(matrix_result(solve_columns(
+        &a,
+        &identity(a.len()),
+        "MINVERSE",
+    )?))
+}
+
+pub fn eval_munit(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let n = eval_number(&args[0], precs, grid, "MUNIT")?.trunc();
+
+    if n < 1.0 || n > MAX_SIZE as f64 {
+        return Err(eval_err(
+            format!("MUNIT requires a size between 1 and {MAX_SIZE}."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(matrix_result(identity(n as usize)))
+}
+
+// LINSOLVE(A, B) solves AX = B, B may have several columns
+pub fn eval_linsolve(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let a = eval_square(&args[0], precs, grid, "LINSOLVE")?;
+    let b = eval_matrix(&args[1], precs, grid, "LINSOLVE")?;
+
+    if b.len() != a.len() {
+        return Err(eval_err(
+            format!(
+                "LINSOLVE requires the right hand side to have {} row(s).",
+                a.len()
+            ),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(matrix_result(solve_columns(&a, &b, "LINSOLVE")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    fn numbers(eval: Eval) -> (usize, usize, Vec<f64>) {
+        let Eval::Range(array) = eval else {
+            panic!("expected an array, found {eval:?}");
+        };
+
+        let values = array
+            .values
+            .iter()
+            .map(|it| match it {
+                Eval::Literal(Literal::Number(n)) => *n,
+                it => panic!("expected a number, found {it:?}"),
+            })
+            .collect();
+        (array.rows, array.cols, values)
+    }
+
+    fn assert_all_close(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert_close("matrix element", Ok(*a), *e);
+        }
+    }
+
+    #[test]
+    fn test_matrix_functions() {
+        let grid = grid_from(&[
+            &["4", "7", "1", "2"],
+            &["2", "6", "3", "4"],
+            &["0", "0", "", ""],
+        ]);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        let (rows, cols, values) = numbers(eval("MMULT(A1:B2, C1:D2)"));
+        assert_eq!((rows, cols), (2, 2));
+        assert_all_close(&values, &[25.0, 36.0, 20.0, 28.0]);
+        assert_eq!(numbers(eval("MMULT(A1:B2, C1:C2)")).0, 2);
+        assert!(is_err(eval("MMULT(A1:B2, C1:D1)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("MMULT(A1:B3, C2:D3)"), LeadErrCode::TypeErr));
+
+        assert_eq!(eval("MDETERM(A1:B2)"), num(10.0));
+        assert_eq!(eval("MDETERM(A2:B3)"), num(0.0));
+        assert!(is_err(eval("MDETERM(A1:B3)"), LeadErrCode::Invalid));
+
+        let (_, _, values) = numbers(eval("MINVERSE(A1:B2)"));
+        assert_all_close(&values, &[0.6, -0.7, -0.2, 0.4]);
+        let (_, _, values) = numbers(eval("MMULT(A1:B2, MINVERSE(A1:B2))"));
+        assert_all_close(&values, &[1.0, 0.0, 0.0, 1.0]);
+        assert!(is_err(eval("MINVERSE(A2:B3)"), LeadErrCode::Invalid));
+
+        // A zero in the first pivot position needs a row swap
+        let (_, _, values) = numbers(eval("MINVERSE(HSTACK(VSTACK(0, 1), VSTACK(1, 0)))"));
+        assert_all_close(&values, &[0.0, 1.0, 1.0, 0.0]);
+
+        let (rows, cols, values) = numbers(eval("LINSOLVE(A1:B2, C1:C2)"));
+        assert_eq!((rows, cols), (2, 1));
+        assert_all_close(&values, &[-1.5, 1.0]);
+        let (_, _, values) = numbers(eval("LINSOLVE(A1:B2, C1:D2)"));
+        assert_all_close(&values, &[-1.5, -1.6, 1.0, 1.2]);
+        assert!(is_err(eval("LINSOLVE(A2:B3, C1:C2)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("LINSOLVE(A1:B2, C1)"), LeadErrCode::Invalid));
+
+        let (rows, cols, values) = numbers(eval("MUNIT(3)"));
+        assert_eq!((rows, cols), (3, 3));
+        assert_eq!(values, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(is_err(eval("MUNIT(0)"), LeadErrCode::Invalid));
+
+        // Sizes are checked before anything is built or decomposed
+        assert!(is_err(
+            eval("SUM(MMULT(SEQUENCE(1000000), TRANSPOSE(SEQUENCE(1000000))))"),
+            LeadErrCode::Invalid
+        ));
+        assert!(is_err(eval("MINVERSE(A1:AMK1025)"), LeadErrCode::Invalid));
+    }
+}
@@ -1,25 +1,45 @@
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    cell::CellRef,
+    cell::{CellRange, CellRef},
     common::{LeadErr, LeadErrCode, Literal},
-    evaluator::{numerics::*, utils::*},
+    evaluator::{datetime::parse_datetime, numerics::*},
     grid::Grid,
     parser::*,
     tokenizer::{Token, Tokenizer},
 };
 
-use std::{collections::HashSet, f64, fmt};
+use std::{cmp::Ordering, collections::HashSet, f64, fmt, sync::Arc};
 
+mod array;
+mod arrays;
+mod builtins;
+mod counting;
+mod criteria;
+mod datetime;
+mod finance;
+mod matrix;
 mod numerics;
+mod patterns;
+mod random;
+mod references;
+mod registry;
+mod regression;
+mod stats;
 mod utils;
 
+pub use array::Array;
+pub use datetime::{Clock, SystemClock};
+pub use patterns::RegexCache;
+pub use random::Rng;
+pub use registry::{FunctionInfo, FunctionRegistry};
+
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Eval {
     Literal(Literal),
     CellRef { eval: Box<Eval>, reference: CellRef },
-    Range(Vec<Eval>),
+    Range(Array),
     Err(LeadErr),
     Unset,
 }
@@ -36,21 +56,109 @@ impl fmt::Display for Eval {
     }
 }
 
-pub fn evaluate(str: String, grid: Option<&Grid>) -> (Eval, HashSet<CellRef>) {
-    match parse(&str) {
-        Ok((expr, _)) => {
-            let mut precs = HashSet::new();
+// The cells a formula read. Ranges are kept whole so that a large range is a single entry.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Precedents {
+    cells: HashSet<CellRef>,
+    ranges: HashSet<CellRange>,
+}
+
+impl Precedents {
+    pub fn new() -> Precedents {
+        Precedents::default()
+    }
+
+    pub fn insert(&mut self, cell: CellRef) {
+        self.cells.insert(cell);
+    }
+
+    pub fn insert_range(&mut self, range: CellRange) {
+        if range.len() == 1 {
+            self.cells.insert(range.start);
+        } else {
+            self.ranges.insert(range);
+        }
+    }
+
+    pub fn remove(&mut self, cell: &CellRef) {
+        self.cells.remove(cell);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty() && self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, cell: &CellRef) -> bool {
+        self.cells.contains(cell) || self.ranges.iter().any(|it| it.contains(*cell))
+    }
+
+    // Cells read on their own
+    pub fn cells(&self) -> &HashSet<CellRef> {
+        &self.cells
+    }
+
+    pub fn ranges(&self) -> &HashSet<CellRange> {
+        &self.ranges
+    }
+}
+
+// A formula parsed once when its cell is set and evaluated on every recalculation. Parse
+// errors are kept so that evaluating shows them.
+#[derive(Debug, Clone)]
+pub struct Formula(Arc<Result<Expr, LeadErr>>);
 
-            match evaluate_expr(&expr, &mut precs, grid) {
-                Ok(it) => (it, precs),
-                Err(it) => (Eval::Err(it), precs),
+impl Formula {
+    // Without the leading =
+    pub fn compile(str: &str) -> Formula {
+        Formula(Arc::new(parse(str).map(|(expr, _)| expr)))
+    }
+
+    pub fn evaluate(&self, grid: Option<&Grid>) -> (Eval, Precedents) {
+        match self.0.as_ref() {
+            Ok(expr) => {
+                let mut precs = Precedents::new();
+
+                match evaluate_expr(expr, &mut precs, grid) {
+                    Ok(it) => (it, precs),
+                    Err(it) => (Eval::Err(it), precs),
+                }
+            }
+            Err(e) => (Eval::Err(e.to_owned()), Precedents::new()),
+        }
+    }
+
+    // Whether the formula calls a volatile function, such as NOW or RAND
+    pub fn is_volatile(&self, functions: &FunctionRegistry) -> bool {
+        fn visit(expr: &Expr, functions: &FunctionRegistry) -> bool {
+            match expr {
+                Expr::Function { name, args } => {
+                    functions.get(name).is_some_and(|it| it.volatile())
+                        || args.iter().any(|it| visit(it, functions))
+                }
+                Expr::Group(expr) | Expr::Prefix { expr, .. } | Expr::Postfix { expr, .. } => {
+                    visit(expr, functions)
+                }
+                Expr::Infix { lhs, rhs, .. } => visit(lhs, functions) || visit(rhs, functions),
+                Expr::Literal(_) | Expr::CellRef(_) => false,
             }
         }
-        Err(e) => (Eval::Err(e), HashSet::new()),
+
+        self.0
+            .as_ref()
+            .as_ref()
+            .is_ok_and(|expr| visit(expr, functions))
     }
 }
 
+pub fn evaluate(str: String, grid: Option<&Grid>) -> (Eval, Precedents) {
+    Formula::compile(&str).evaluate(grid)
+}
+
 pub fn evaluate_literal(input: String) -> Eval {
+    if let Some(serial) = parse_datetime(&input) {
+        return Eval::Literal(Literal::DateTime(serial));
+    }
+
     let mut tokenizer = match Tokenizer::new(&input) {
         Ok(t) => t,
         Err(_) => {
@@ -58,22 +166,32 @@ pub fn evaluate_literal(input: String) -> Eval {
         }
     };
 
+    // A leading sign belongs to a number literal, e.g. -5
+    let sign = match tokenizer.peek() {
+        Token::Operator('-') if tokenizer.len() == 2 => Some(-1.0),
+        Token::Operator('+') if tokenizer.len() == 2 => Some(1.0),
+        _ => None,
+    };
+    if sign.is_some() {
+        tokenizer.next();
+    }
+
     if tokenizer.len() != 1 {
         return Eval::Literal(Literal::String(input.to_owned()));
     }
 
-    match tokenizer.next() {
-        Token::Literal(lit) => match lit {
-            Literal::Number(_) | Literal::String(_) => Eval::Literal(lit),
-            Literal::Boolean(_) => Eval::Literal(Literal::String(input.to_owned())),
-        },
+    match (tokenizer.next(), sign) {
+        (Token::Literal(Literal::Number(n)), sign) => {
+            Eval::Literal(Literal::Number(n * sign.unwrap_or(1.0)))
+        }
+        (Token::Literal(lit @ Literal::String(_)), None) => Eval::Literal(lit),
         _ => Eval::Literal(Literal::String(input.to_owned())),
     }
 }
 
 fn evaluate_expr(
     expr: &Expr,
-    precs: &mut HashSet<CellRef>,
+    precs: &mut Precedents,
     grid: Option<&Grid>,
 ) -> Result<Eval, LeadErr> {
     let res = match expr {
@@ -98,6 +216,21 @@ fn evaluate_expr(
                 });
             }
         }
+        // The corners are part of the range rather than cells read on their own
+        Expr::Infix {
+            op: InfixOp::RANGE,
+            lhs,
+            rhs,
+        } if matches!(
+            (lhs.as_ref(), rhs.as_ref()),
+            (Expr::CellRef(_), Expr::CellRef(_))
+        ) =>
+        {
+            let (Expr::CellRef(a), Expr::CellRef(b)) = (lhs.as_ref(), rhs.as_ref()) else {
+                unreachable!()
+            };
+            range_between(*a, *b, precs, grid)?
+        }
         Expr::Infix { op, lhs, rhs } => {
             let mut lval = evaluate_expr(lhs, precs, grid)?;
             let mut rval = evaluate_expr(rhs, precs, grid)?;
@@ -117,6 +250,12 @@ fn evaluate_expr(
                 InfixOp::MUL => eval_mul(&lval, &rval)?,
                 InfixOp::DIV => eval_div(&lval, &rval)?,
                 InfixOp::RANGE => eval_range(&lval, &rval, precs, grid)?,
+                InfixOp::EQ => eval_compare(&lval, &rval, Ordering::is_eq)?,
+                InfixOp::NE => eval_compare(&lval, &rval, Ordering::is_ne)?,
+                InfixOp::LT => eval_compare(&lval, &rval, Ordering::is_lt)?,
+                InfixOp::LE => eval_compare(&lval, &rval, Ordering::is_le)?,
+                InfixOp::GT => eval_compare(&lval, &rval, Ordering::is_gt)?,
+                InfixOp::GE => eval_compare(&lval, &rval, Ordering::is_ge)?,
                 _ => {
                     return Err(LeadErr {
                         title: "Evaluation error.".into(),
@@ -134,88 +273,21 @@ fn evaluate_expr(
             }
 
             match op {
-                PrefixOp::POS => eval_pos(&val)?,
-                PrefixOp::NEG => eval_neg(&val)?,
-                PrefixOp::NOT => eval_not(&val)?,
+                PrefixOp::POS => map_elements(&val, eval_pos)?,
+                PrefixOp::NEG => map_elements(&val, eval_neg)?,
+                PrefixOp::NOT => map_elements(&val, eval_not)?,
                 // _ => return Err(format!("Evaluation error: Unsupported operator {:?}", op)),
             }
         }
         Expr::Group(g) => evaluate_expr(g, precs, grid)?,
-        Expr::Function { name, args } => match name.as_str() {
-            "AVG" => eval_numeric_func(
-                args,
-                precs,
-                grid,
-                |nums| {
-                    if nums.is_empty() {
-                        Err(LeadErr {
-                            title: "Evaluation error.".into(),
-                            desc: "Attempted to divide by zero.".into(),
-                            code: LeadErrCode::DivZero,
-                        })
-                    } else {
-                        Ok(nums.iter().sum::<f64>() / nums.len() as f64)
-                    }
-                },
-                "AVG",
-            )?,
-            "SUM" => eval_numeric_func(args, precs, grid, |nums| Ok(nums.iter().sum()), "SUM")?,
-            "PROD" => {
-                eval_numeric_func(args, precs, grid, |nums| Ok(nums.iter().product()), "PROD")?
-            }
-            "MAX" => eval_numeric_func(
-                args,
-                precs,
-                grid,
-                |nums| {
-                    nums.iter()
-                        .cloned()
-                        .max_by(|a, b| a.partial_cmp(b).unwrap())
-                        .ok_or(LeadErr {
-                            title: "Evaluation error.".into(),
-                            desc: "MAX on empty set.".into(),
-                            code: LeadErrCode::Unsupported,
-                        })
-                },
-                "MAX",
-            )?,
-            "MIN" => eval_numeric_func(
-                args,
-                precs,
-                grid,
-                |nums| {
-                    nums.iter()
-                        .cloned()
-                        .min_by(|a, b| a.partial_cmp(b).unwrap())
-                        .ok_or(LeadErr {
-                            title: "Evaluation error.".into(),
-                            desc: "MIN on empty set.".into(),
-                            code: LeadErrCode::Unsupported,
-                        })
-                },
-                "MIN",
-            )?,
-            "ABS" => eval_abs(args, precs, grid)?,
-            "LOG" => eval_log(args, precs, grid)?,
-            "SQRT" => eval_sqrt(args, precs, grid)?,
-            "EXP" => eval_exp(args, precs, grid)?,
-            "SIN" => eval_sin(args, precs, grid)?,
-            "COS" => eval_cos(args, precs, grid)?,
-            "TAN" => eval_tan(args, precs, grid)?,
-            "ASIN" => eval_asin(args, precs, grid)?,
-            "ACOS" => eval_acos(args, precs, grid)?,
-            "ATAN" => eval_atan(args, precs, grid)?,
-            "PI" => eval_pi(args)?,
-            "TAU" => eval_tau(args)?,
-            "SQRT2" => eval_sqrt2(args)?,
-            it => {
-                return Err(LeadErr {
-                    title: "Evaluation error.".into(),
-                    desc: format!("Unsupported function {:?}", it),
-                    code: LeadErrCode::Unsupported,
-                });
-            }
-        },
+        Expr::Postfix {
+            op: PostfixOp::SPILL,
+            expr,
+        } => eval_spill_ref(expr, precs, grid)?,
+        Expr::Function { name, args } => {
+            let functions = grid.map_or(FunctionRegistry::builtin(), |g| g.functions());
+            functions.call(name, args, precs, grid)?
+        }
         it => {
             return Err(LeadErr {
                 title: "Evaluation error.".into(),
@@ -231,7 +303,7 @@ fn evaluate_expr(
 fn eval_range(
     lval: &Eval,
     rval: &Eval,
-    precs: &mut HashSet<CellRef>,
+    precs: &mut Precedents,
     grid: Option<&Grid>,
 ) -> Result<Eval, LeadErr> {
     match (lval, rval) {
@@ -244,42 +316,7 @@ fn eval_range(
                 eval: _,
                 reference: b_ref,
             },
-        ) => {
-            let mut cells = Vec::new();
-
-            // assume row-major expansion
-            let row_start = a_ref.row.min(b_ref.row);
-            let row_end = a_ref.row.max(b_ref.row);
-            let col_start = a_ref.col.min(b_ref.col);
-            let col_end = a_ref.col.max(b_ref.col);
-
-            for row in row_start..=row_end {
-                for col in col_start..=col_end {
-                    let reference = CellRef { row, col };
-
-                    let Some(g) = grid else {
-                        return Err(LeadErr {
-                            title: "Evaluation error.".into(),
-                            desc: "Found cell range but no grid.".into(),
-                            code: LeadErrCode::Server,
-                        });
-                    };
-
-                    cells.push(Eval::CellRef {
-                        eval: Box::new(
-                            g.get_cell(reference.to_owned())
-                                .map_or(Eval::Unset, |cell| cell.eval()),
-                        ),
-                        reference: {
-                            precs.insert(reference);
-                            reference
-                        },
-                    });
-                }
-            }
-
-            Ok(Eval::Range(cells))
-        }
+        ) => range_between(*a_ref, *b_ref, precs, grid),
         _ => Err(LeadErr {
             title: "Evaluation error.".into(),
             desc: "Expected cell reference types for RANGE function.".into(),
@@ -288,9 +325,88 @@ fn eval_range(
     }
 }
 
+// The cells of the rectangle with corners a_ref and b_ref
+fn range_between(
+    a_ref: CellRef,
+    b_ref: CellRef,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let Some(g) = grid else {
+        return Err(LeadErr {
+            title: "Evaluation error.".into(),
+            desc: "Found cell range but no grid.".into(),
+            code: LeadErrCode::Server,
+        });
+    };
+
+    precs.insert_range(CellRange::new(a_ref, b_ref));
+    let mut cells = Vec::new();
+
+    // assume row-major expansion
+    let row_start = a_ref.row.min(b_ref.row);
+    let row_end = a_ref.row.max(b_ref.row);
+    let col_start = a_ref.col.min(b_ref.col);
+    let col_end = a_ref.col.max(b_ref.col);
+
+    for row in row_start..=row_end {
+        for col in col_start..=col_end {
+            let reference = CellRef { row, col };
+
+            cells.push(Eval::CellRef {
+                eval: Box::new(
+                    g.get_cell(reference.to_owned())
+                        .map_or(Eval::Unset, |cell| cell.eval()),
+                ),
+                reference,
+            });
+        }
+    }
+
+    Ok(Eval::Range(Array::new(
+        row_end - row_start + 1,
+        col_end - col_start + 1,
+        cells,
+    )))
+}
+
+// A1# refers to the whole array spilled from A1
+fn eval_spill_ref(
+    expr: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let Expr::CellRef(anchor) = expr else {
+        return Err(LeadErr {
+            title: "Evaluation error.".into(),
+            desc: "The spill operator # requires a cell reference.".into(),
+            code: LeadErrCode::Syntax,
+        });
+    };
+
+    precs.insert(*anchor);
+    let cell = grid.and_then(|g| g.get_cell(*anchor).ok());
+
+    match cell.as_ref().and_then(|it| it.spill()) {
+        Some(spill) if spill.is_blocked() => Err(spill.err()),
+        Some(spill) => {
+            let corner = CellRef {
+                row: anchor.row + spill.array.rows - 1,
+                col: anchor.col + spill.array.cols - 1,
+            };
+            range_between(*anchor, corner, precs, grid)
+        }
+        None => Err(LeadErr {
+            title: "Evaluation error.".into(),
+            desc: format!("{anchor} does not contain a spilled array."),
+            code: LeadErrCode::Ref,
+        }),
+    }
+}
+
 fn eval_pos(val: &Eval) -> Result<Eval, LeadErr> {
     match val {
-        Eval::Literal(Literal::Number(it)) => Ok(Eval::Literal(Literal::Number(*it))),
+        it @ Eval::Literal(Literal::Number(_) | Literal::DateTime(_)) => Ok(it.clone()),
         _ => Err(LeadErr {
             title: "Evaluation error.".into(),
             desc: "Expected numeric type for POS function.".into(),
@@ -301,7 +417,9 @@ fn eval_pos(val: &Eval) -> Result<Eval, LeadErr> {
 
 fn eval_neg(val: &Eval) -> Result<Eval, LeadErr> {
     match val {
-        Eval::Literal(Literal::Number(it)) => Ok(Eval::Literal(Literal::Number(-it))),
+        Eval::Literal(Literal::Number(it) | Literal::DateTime(it)) => {
+            Ok(Eval::Literal(Literal::Number(-it)))
+        }
         _ => Err(LeadErr {
             title: "Evaluation error.".into(),
             desc: "Expected numeric type for NEG function.".into(),
@@ -321,6 +439,7 @@ fn eval_not(val: &Eval) -> Result<Eval, LeadErr> {
     }
 }
 
+#[allow(dead_code)]
 fn eval_numeric_infix(lhs: &Literal, rhs: &Literal, op: fn(f64, f64) -> f64) -> Option<Literal> {
     match (lhs, rhs) {
         (Literal::Number(a), Literal::Number(b)) => Some(Literal::Number(op(*a, *b))),
@@ -1,78 +1,59 @@
-use std::collections::HashSet;
+use std::cmp::Ordering;
 
 use crate::{
-    cell::CellRef,
     common::{LeadErr, LeadErrCode, Literal},
-    evaluator::{Eval, evaluate_expr},
+    evaluator::{Array, Eval, Precedents, evaluate_expr, utils::*},
     grid::Grid,
     parser::Expr,
 };
 
 // -------------------------------------------------- //
 
-fn eval_const(args: &Vec<Expr>, value: Eval, label: &str) -> Result<Eval, LeadErr> {
-    if args.len() != 0 {
-        return Err(LeadErr {
-            title: "Evaluation error.".into(),
-            desc: format!("{label} function requires no arguments."),
-            code: LeadErrCode::Invalid,
-        });
-    }
-
-    Ok(value)
-}
-
 macro_rules! const_func {
-    ($fn_name:ident, $value:expr, $label:expr) => {
-        pub fn $fn_name(args: &Vec<Expr>) -> Result<Eval, LeadErr> {
-            eval_const(args, $value, $label)
+    ($fn_name:ident, $value:expr) => {
+        pub fn $fn_name() -> Result<Eval, LeadErr> {
+            Ok($value)
         }
     };
 }
 
 const_func!(
     eval_pi,
-    Eval::Literal(Literal::Number(std::f64::consts::PI)),
-    "PI"
+    Eval::Literal(Literal::Number(std::f64::consts::PI))
 );
 
 const_func!(
     eval_tau,
-    Eval::Literal(Literal::Number(std::f64::consts::TAU)),
-    "TAU"
+    Eval::Literal(Literal::Number(std::f64::consts::TAU))
 );
 
 const_func!(
     eval_sqrt2,
-    Eval::Literal(Literal::Number(std::f64::consts::SQRT_2)),
-    "SQRT2"
+    Eval::Literal(Literal::Number(std::f64::consts::SQRT_2))
 );
 
 // -------------------------------------------------- //
 
 fn eval_unary(
-    args: &Vec<Expr>,
-    precs: &mut HashSet<CellRef>,
+    args: &[Expr],
+    precs: &mut Precedents,
     grid: Option<&Grid>,
     func: fn(f64) -> f64,
     func_name: &str,
 ) -> Result<Eval, LeadErr> {
-    if args.len() != 1 {
-        return Err(LeadErr {
-            title: "Evaluation error.".into(),
-            desc: format!("{func_name} function requires a single argument."),
-            code: LeadErrCode::Invalid,
-        });
-    }
     let err = LeadErr {
         title: "Evaluation error.".into(),
         desc: format!("{func_name} function requires a numeric argument."),
         code: LeadErrCode::TypeErr,
     };
     match evaluate_expr(&args[0], precs, grid)? {
-        Eval::Literal(Literal::Number(num)) => Ok(Eval::Literal(Literal::Number(func(num)))),
+        Eval::Literal(Literal::Number(num) | Literal::DateTime(num)) => {
+            Ok(Eval::Literal(Literal::Number(func(num))))
+        }
         Eval::CellRef { eval, .. } => match *eval {
-            Eval::Literal(Literal::Number(n)) => Ok(Eval::Literal(Literal::Number(func(n)))),
+            Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => {
+                Ok(Eval::Literal(Literal::Number(func(n))))
+            }
             _ => Err(err),
         },
         _ => Err(err),
@@ -82,8 +63,8 @@ fn eval_unary(
 macro_rules! unary_func {
     ($fn_name:ident, $func:expr, $label:expr) => {
         pub fn $fn_name(
-            args: &Vec<Expr>,
-            precs: &mut HashSet<CellRef>,
+            args: &[Expr],
+            precs: &mut Precedents,
             grid: Option<&Grid>,
         ) -> Result<Eval, LeadErr> {
             eval_unary(args, precs, grid, $func, $label)
@@ -106,76 +87,675 @@ unary_func!(eval_atan, |x| x.atan(), "ATAN");
 
 // -------------------------------------------------- //
 
-fn eval_infix(
+macro_rules! n_arg_func {
+    ($fn_name:ident, $required:expr, $defaults:expr, $func:expr, $label:expr) => {
+        pub fn $fn_name(
+            args: &[Expr],
+            precs: &mut Precedents,
+            grid: Option<&Grid>,
+        ) -> Result<Eval, LeadErr> {
+            eval_n_arg_numeric($required, &$defaults, args, precs, grid, $func, $label)
+        }
+    };
+}
+
+// Rounds to 15 significant digits, the precision spreadsheets display. This removes binary
+// representation error so that e.g. 2.675 * 100 rounds to 268 rather than 267.
+fn clean(x: f64) -> f64 {
+    if x.is_finite() {
+        format!("{x:.14e}").parse().unwrap_or(x)
+    } else {
+        x
+    }
+}
+
+fn away_from_zero(x: f64) -> f64 {
+    x.signum() * x.abs().ceil()
+}
+
+// Rounds x to the given number of decimal digits (negative digits round to the left of the
+// decimal point) using mode to round the scaled value to an integer
+fn round_digits(x: f64, digits: f64, mode: fn(f64) -> f64) -> f64 {
+    let digits = digits.trunc().clamp(-308.0, 308.0) as i32;
+    let scale = 10f64.powi(digits.abs());
+
+    let scaled = if digits >= 0 { x * scale } else { x / scale };
+    if !scaled.is_finite() {
+        return x;
+    }
+
+    let rounded = mode(clean(scaled));
+    if digits >= 0 {
+        rounded / scale
+    } else {
+        rounded * scale
+    }
+}
+
+// FLOOR and CEILING round to a multiple of significance. A positive number can't be rounded
+// to a negative significance.
+fn round_multiple(x: f64, sig: f64, mode: fn(f64) -> f64, func_name: &str) -> Result<f64, LeadErr> {
+    if x > 0.0 && sig < 0.0 {
+        return Err(eval_err(
+            format!("{func_name} requires a positive significance for a positive number."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(clean(mode(clean(x / sig)) * sig))
+}
+
+fn floor(args: &[f64]) -> Result<f64, LeadErr> {
+    if args[1] == 0.0 {
+        return Err(eval_err(
+            "FLOOR requires a non-zero significance.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    round_multiple(args[0], args[1], f64::floor, "FLOOR")
+}
+
+fn ceiling(args: &[f64]) -> Result<f64, LeadErr> {
+    if args[1] == 0.0 {
+        return Ok(0.0);
+    }
+
+    round_multiple(args[0], args[1], f64::ceil, "CEILING")
+}
+
+fn mround(args: &[f64]) -> Result<f64, LeadErr> {
+    let (x, multiple) = (args[0], args[1]);
+
+    if multiple == 0.0 {
+        return Ok(0.0);
+    }
+    if x * multiple < 0.0 {
+        return Err(eval_err(
+            "MROUND requires the number and multiple to have the same sign.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(clean(clean(x / multiple).round() * multiple))
+}
+
+fn divisor(d: f64, func_name: &str) -> Result<f64, LeadErr> {
+    if d == 0.0 {
+        Err(eval_err(
+            format!("{func_name} attempted to divide by zero."),
+            LeadErrCode::DivZero,
+        ))
+    } else {
+        Ok(d)
+    }
+}
+
+// The result takes the sign of the divisor
+fn modulo(args: &[f64]) -> Result<f64, LeadErr> {
+    let d = divisor(args[1], "MOD")?;
+    Ok(args[0] - d * (args[0] / d).floor())
+}
+
+fn quotient(args: &[f64]) -> Result<f64, LeadErr> {
+    let d = divisor(args[1], "QUOTIENT")?;
+    Ok((args[0] / d).trunc())
+}
+
+fn sign(args: &[f64]) -> Result<f64, LeadErr> {
+    Ok(if args[0] == 0.0 {
+        0.0
+    } else {
+        args[0].signum()
+    })
+}
+
+fn power(args: &[f64]) -> Result<f64, LeadErr> {
+    let (base, exp) = (args[0], args[1]);
+
+    if base == 0.0 && exp < 0.0 {
+        return Err(eval_err(
+            "POWER attempted to divide by zero.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    finite(base.powf(exp), "POWER")
+}
+
+fn overflow_err(func_name: &str) -> LeadErr {
+    eval_err(
+        format!("{func_name} result is not a finite number."),
+        LeadErrCode::Invalid,
+    )
+}
+
+fn finite(x: f64, func_name: &str) -> Result<f64, LeadErr> {
+    if x.is_finite() {
+        Ok(x)
+    } else {
+        Err(overflow_err(func_name))
+    }
+}
+
+// Integer arguments of GCD, LCM, FACT, COMBIN and PERMUT are truncated and must not be negative
+fn non_negative_int(x: f64, func_name: &str) -> Result<f64, LeadErr> {
+    if x < 0.0 || x >= 2f64.powi(53) {
+        return Err(eval_err(
+            format!("{func_name} requires non-negative integer arguments below 2^53."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(x.trunc())
+}
+
+fn int_args(nums: &[f64], func_name: &str) -> Result<Vec<u64>, LeadErr> {
+    if nums.is_empty() {
+        return Err(eval_err(
+            format!("{func_name} function requires at least one argument."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    nums.iter()
+        .map(|it| Ok(non_negative_int(*it, func_name)? as u64))
+        .collect()
+}
+
+fn gcd_pair(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+pub fn gcd(nums: &[f64]) -> Result<f64, LeadErr> {
+    Ok(int_args(nums, "GCD")?.into_iter().fold(0, gcd_pair) as f64)
+}
+
+pub fn lcm(nums: &[f64]) -> Result<f64, LeadErr> {
+    let mut res = 1.0;
+
+    for n in int_args(nums, "LCM")? {
+        if n == 0 {
+            return Ok(0.0);
+        }
+        res = res / gcd_pair(res as u64, n) as f64 * n as f64;
+        non_negative_int(res, "LCM")?;
+    }
+
+    Ok(res)
+}
+
+// 170! is the largest factorial below f64::MAX
+const MAX_FACT: u64 = 170;
+
+fn fact(args: &[f64]) -> Result<f64, LeadErr> {
+    let n = non_negative_int(args[0], "FACT")? as u64;
+    if n > MAX_FACT {
+        return Err(overflow_err("FACT"));
+    }
+
+    Ok((1..=n).map(|it| it as f64).product())
+}
+
+fn choose_args(args: &[f64], func_name: &str) -> Result<(u64, u64), LeadErr> {
+    let n = non_negative_int(args[0], func_name)? as u64;
+    let k = non_negative_int(args[1], func_name)? as u64;
+
+    if k > n {
+        return Err(eval_err(
+            format!("{func_name} requires the number chosen to be at most the number of items."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok((n, k))
+}
+
+fn combin(args: &[f64]) -> Result<f64, LeadErr> {
+    let (n, k) = choose_args(args, "COMBIN")?;
+    let k = k.min(n - k);
+
+    // With k at most n / 2 the running product only grows, so it stops at the first overflow.
+    // A finite result has k below about a thousand.
+    let res = (0..k).try_fold(1.0, |acc, i| {
+        finite(acc * (n - i) as f64 / (i + 1) as f64, "COMBIN")
+    })?;
+    Ok(res.round())
+}
+
+fn permut(args: &[f64]) -> Result<f64, LeadErr> {
+    let (n, k) = choose_args(args, "PERMUT")?;
+    // Like COMBIN every factor is at least 1, so it stops at the first overflow
+    (n - k + 1..=n)
+        .rev()
+        .try_fold(1.0, |acc, it| finite(acc * it as f64, "PERMUT"))
+}
+
+// Rounding is half away from zero
+n_arg_func!(
+    eval_round,
+    1,
+    [0.0],
+    |a| Ok(round_digits(a[0], a[1], f64::round)),
+    "ROUND"
+);
+n_arg_func!(
+    eval_roundup,
+    1,
+    [0.0],
+    |a| Ok(round_digits(a[0], a[1], away_from_zero)),
+    "ROUNDUP"
+);
+n_arg_func!(
+    eval_rounddown,
+    1,
+    [0.0],
+    |a| Ok(round_digits(a[0], a[1], f64::trunc)),
+    "ROUNDDOWN"
+);
+n_arg_func!(
+    eval_trunc,
+    1,
+    [0.0],
+    |a| Ok(round_digits(a[0], a[1], f64::trunc)),
+    "TRUNC"
+);
+n_arg_func!(eval_int, 1, [], |a| Ok(a[0].floor()), "INT");
+n_arg_func!(eval_mround, 2, [], mround, "MROUND");
+n_arg_func!(eval_floor, 1, [1.0], floor, "FLOOR");
+n_arg_func!(eval_ceiling, 1, [1.0], ceiling, "CEILING");
+
+n_arg_func!(eval_mod, 2, [], modulo, "MOD");
+n_arg_func!(eval_quotient, 2, [], quotient, "QUOTIENT");
+n_arg_func!(eval_sign, 1, [], sign, "SIGN");
+n_arg_func!(eval_power, 2, [], power, "POWER");
+
+n_arg_func!(eval_fact, 1, [], fact, "FACT");
+n_arg_func!(eval_combin, 2, [], combin, "COMBIN");
+n_arg_func!(eval_permut, 2, [], permut, "PERMUT");
+
+// -------------------------------------------------- //
+
+// Operators applied to ranges work element-wise. A scalar is repeated over every element of the
+// other operand and a single row or column is repeated along the other dimension, so a row
+// combined with a column gives a table. Elements outside the smaller of two mismatched arrays
+// are #N/A and errors only affect their own element.
+fn as_array(eval: &Eval) -> Array {
+    match eval {
+        Eval::Range(range) => range.to_owned().unwrapped(),
+        Eval::CellRef { eval, .. } => Array::new(1, 1, vec![*eval.to_owned()]),
+        it => Array::new(1, 1, vec![it.to_owned()]),
+    }
+}
+
+fn broadcast_index(array: &Array, row: usize, col: usize) -> Option<&Eval> {
+    let row = if array.rows == 1 { 0 } else { row };
+    let col = if array.cols == 1 { 0 } else { col };
+    (row < array.rows && col < array.cols).then(|| array.get(row, col))
+}
+
+// Errors in the operands are passed through
+fn apply(
     lhs: &Eval,
     rhs: &Eval,
-    func: fn(f64, f64) -> f64,
-    func_name: &str,
+    func: &impl Fn(&Eval, &Eval) -> Result<Eval, LeadErr>,
 ) -> Result<Eval, LeadErr> {
-    let err = LeadErr {
-        title: "Evaluation error.".into(),
-        desc: format!("{func_name} function requires a numeric argument."),
-        code: LeadErrCode::TypeErr,
-    };
+    match (lhs, rhs) {
+        (Eval::Err(e), _) | (_, Eval::Err(e)) => Err(e.to_owned()),
+        _ => func(lhs, rhs),
+    }
+}
 
-    let l = match lhs.to_owned() {
-        Eval::Literal(Literal::Number(num)) => num,
-        Eval::CellRef { eval, .. } => match *eval {
-            Eval::Literal(Literal::Number(num)) => num,
-            _ => return Err(err),
-        },
-        _ => return Err(err),
-    };
+pub fn broadcast(
+    lhs: &Eval,
+    rhs: &Eval,
+    func: impl Fn(&Eval, &Eval) -> Result<Eval, LeadErr>,
+) -> Result<Eval, LeadErr> {
+    if !matches!(lhs, Eval::Range(_)) && !matches!(rhs, Eval::Range(_)) {
+        return apply(lhs, rhs, &func);
+    }
 
-    let r = match rhs.to_owned() {
-        Eval::Literal(Literal::Number(num)) => num,
-        Eval::CellRef { eval, .. } => match *eval {
-            Eval::Literal(Literal::Number(num)) => num,
-            _ => return Err(err),
-        },
-        _ => return Err(err),
-    };
+    let (l, r) = (as_array(lhs), as_array(rhs));
+    let (rows, cols) = (l.rows.max(r.rows), l.cols.max(r.cols));
+    check_cells(rows, cols, "Array")?;
 
-    Ok(Eval::Literal(Literal::Number(func(l, r))))
+    let mut values = Vec::with_capacity(rows * cols);
+    for row in 0..rows {
+        for col in 0..cols {
+            values.push(
+                match (broadcast_index(&l, row, col), broadcast_index(&r, row, col)) {
+                    (Some(a), Some(b)) => apply(a, b, &func).unwrap_or_else(Eval::Err),
+                    _ => not_available(),
+                },
+            );
+        }
+    }
+
+    Ok(Eval::Range(Array::new(rows, cols, values)))
+}
+
+// Applies a prefix operator to each element of a range
+pub fn map_elements(
+    eval: &Eval,
+    func: impl Fn(&Eval) -> Result<Eval, LeadErr>,
+) -> Result<Eval, LeadErr> {
+    match eval {
+        Eval::Range(range) => {
+            check_cells(range.rows, range.cols, "Array")?;
+            let range = range.to_owned().unwrapped();
+            let values = range
+                .values
+                .iter()
+                .map(|it| match it {
+                    Eval::Err(_) => it.to_owned(),
+                    it => func(it).unwrap_or_else(Eval::Err),
+                })
+                .collect();
+            Ok(Eval::Range(Array::new(range.rows, range.cols, values)))
+        }
+        it => func(it),
+    }
+}
+
+// -------------------------------------------------- //
+
+// Blanks and empty strings count as zero and booleans as one or zero
+fn infix_operand(eval: &Eval) -> Option<f64> {
+    match eval {
+        Eval::Literal(Literal::Number(num) | Literal::DateTime(num)) => Some(*num),
+        Eval::Literal(Literal::Boolean(b)) => Some(if *b { 1.0 } else { 0.0 }),
+        Eval::Literal(Literal::String(s)) if s.is_empty() => Some(0.0),
+        Eval::Unset => Some(0.0),
+        Eval::CellRef { eval, .. } => infix_operand(eval),
+        _ => None,
+    }
+}
+
+fn eval_infix(
+    lhs: &Eval,
+    rhs: &Eval,
+    func: fn(f64, f64) -> f64,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    match (infix_operand(lhs), infix_operand(rhs)) {
+        (Some(l), Some(r)) => Ok(Eval::Literal(Literal::Number(func(l, r)))),
+        _ => Err(LeadErr {
+            title: "Evaluation error.".into(),
+            desc: format!("{func_name} function requires a numeric argument."),
+            code: LeadErrCode::TypeErr,
+        }),
+    }
 }
 
 macro_rules! infix {
     ($fn_name:ident, $func:expr, $label:expr) => {
         pub fn $fn_name(lhs: &Eval, rhs: &Eval) -> Result<Eval, LeadErr> {
-            eval_infix(lhs, rhs, $func, $label)
+            broadcast(lhs, rhs, |l, r| eval_infix(l, r, $func, $label))
         }
     };
 }
 
-// Can concat string as well
-pub fn eval_add(lval: &Eval, rval: &Eval) -> Result<Eval, LeadErr> {
-    match (lval, rval) {
-        (Eval::Literal(a), Eval::Literal(b)) => {
-            if let Ok(res) = eval_infix(lval, rval, |x, y| x + y, "ADD") {
-                return Ok(res);
-            }
+// Date arithmetic: a date plus or minus a number of days is a date and the difference of two
+// dates is a number of days
+fn is_date(eval: &Eval) -> bool {
+    matches!(eval, Eval::Literal(Literal::DateTime(_)))
+}
 
-            // Try string concatenation
-            if let (Literal::String(x), Literal::String(y)) = (a, b) {
-                let mut res = x.to_owned();
-                res.push_str(y);
-                return Ok(Eval::Literal(Literal::String(res)));
-            }
+fn as_date(eval: Eval) -> Eval {
+    match eval {
+        Eval::Literal(Literal::Number(n)) => Eval::Literal(Literal::DateTime(n)),
+        it => it,
+    }
+}
 
-            Err(LeadErr {
-                title: "Evaluation error.".into(),
-                desc: "Expected string or numeric types for ADD function.".into(),
-                code: LeadErrCode::Unsupported,
-            })
+// Can concat string as well
+fn add(lval: &Eval, rval: &Eval) -> Result<Eval, LeadErr> {
+    if let Ok(res) = eval_infix(lval, rval, |x, y| x + y, "ADD") {
+        if is_date(lval) != is_date(rval) {
+            return Ok(as_date(res));
         }
-        _ => Err(LeadErr {
-            title: "Evaluation error.".into(),
-            desc: "Expected string or numeric types for ADD function.".into(),
-            code: LeadErrCode::Unsupported,
-        }),
+        return Ok(res);
+    }
+
+    // Try string concatenation
+    if let (Eval::Literal(Literal::String(x)), Eval::Literal(Literal::String(y))) = (lval, rval) {
+        let mut res = x.to_owned();
+        res.push_str(y);
+        return Ok(Eval::Literal(Literal::String(res)));
     }
+
+    Err(LeadErr {
+        title: "Evaluation error.".into(),
+        desc: "Expected string or numeric types for ADD function.".into(),
+        code: LeadErrCode::Unsupported,
+    })
+}
+
+fn sub(lval: &Eval, rval: &Eval) -> Result<Eval, LeadErr> {
+    let res = eval_infix(lval, rval, |x, y| x - y, "SUB")?;
+
+    if is_date(lval) && !is_date(rval) {
+        Ok(as_date(res))
+    } else {
+        Ok(res)
+    }
+}
+
+pub fn eval_add(lval: &Eval, rval: &Eval) -> Result<Eval, LeadErr> {
+    broadcast(lval, rval, add)
+}
+
+pub fn eval_sub(lval: &Eval, rval: &Eval) -> Result<Eval, LeadErr> {
+    broadcast(lval, rval, sub)
 }
 
-infix!(eval_sub, |x, y| x - y, "SUB");
 infix!(eval_mul, |x, y| x * y, "MUL");
 infix!(eval_div, |x, y| x / y, "DIV");
+
+// A blank, or empty string, compares as the empty value of the other operand's type
+fn blank_like(other: &Eval) -> Eval {
+    match other {
+        Eval::Literal(Literal::String(_)) => Eval::Literal(Literal::String("".into())),
+        Eval::Literal(Literal::Boolean(_)) => Eval::Literal(Literal::Boolean(false)),
+        _ => Eval::Literal(Literal::Number(0.0)),
+    }
+}
+
+fn compare(lval: &Eval, rval: &Eval, accept: fn(Ordering) -> bool) -> Result<Eval, LeadErr> {
+    let is_blank = |it: &Eval| match it {
+        Eval::Literal(Literal::String(s)) => s.is_empty(),
+        it => *it == Eval::Unset,
+    };
+
+    let ordering = if is_blank(lval) {
+        compare_values(&blank_like(rval), rval)
+    } else if is_blank(rval) {
+        compare_values(lval, &blank_like(lval))
+    } else {
+        compare_values(lval, rval)
+    };
+
+    Ok(Eval::Literal(Literal::Boolean(accept(ordering))))
+}
+
+// Compares like Excel, numbers are less than text which is less than booleans. Text is
+// compared case-insensitively.
+pub fn eval_compare(
+    lval: &Eval,
+    rval: &Eval,
+    accept: fn(Ordering) -> bool,
+) -> Result<Eval, LeadErr> {
+    broadcast(lval, rval, |l, r| compare(l, r, accept))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        common::{LeadErrCode, Literal},
+        evaluator::{
+            Array, Eval, evaluate,
+            utils::{grid_from, is_err, num},
+        },
+    };
+
+    #[test]
+    fn test_rounding_functions() {
+        let eval = |s: &str| evaluate(s.into(), None).0;
+
+        assert_eq!(eval("ROUND(2.5)"), num(3.0));
+        assert_eq!(eval("ROUND(-2.5)"), num(-3.0));
+        assert_eq!(eval("ROUND(2.675, 2)"), num(2.68));
+        assert_eq!(eval("ROUND(1.005, 2)"), num(1.01));
+        assert_eq!(eval("ROUND(1234.5678, -2)"), num(1200.0));
+        assert_eq!(eval("ROUND(-1250, -2)"), num(-1300.0));
+        assert_eq!(eval("ROUNDUP(2.71828, 3)"), num(2.719));
+        assert_eq!(eval("ROUNDUP(-3.14159, 1)"), num(-3.2));
+        assert_eq!(eval("ROUNDUP(0.1 + 0.2, 1)"), num(0.3));
+        assert_eq!(eval("ROUNDDOWN(-3.14159, 1)"), num(-3.1));
+        assert_eq!(eval("ROUNDDOWN(31415.92654, -2)"), num(31400.0));
+        assert_eq!(eval("TRUNC(-8.9)"), num(-8.0));
+        assert_eq!(eval("INT(-8.9)"), num(-9.0));
+        assert_eq!(eval("MROUND(10, 3)"), num(9.0));
+        assert_eq!(eval("MROUND(-10, -3)"), num(-9.0));
+        assert_eq!(eval("MROUND(1.3, 0.2)"), num(1.4));
+        assert!(matches!(eval("MROUND(5, -2)"), Eval::Err(_)));
+        assert_eq!(eval("FLOOR(3.7, 2)"), num(2.0));
+        assert_eq!(eval("FLOOR(-2.5, -2)"), num(-2.0));
+        assert_eq!(eval("FLOOR(-2.5, 2)"), num(-4.0));
+        assert_eq!(eval("FLOOR(0.3, 0.1)"), num(0.3));
+        assert!(matches!(eval("FLOOR(2.5, -2)"), Eval::Err(_)));
+        assert!(matches!(eval("FLOOR(2.5, 0)"), Eval::Err(_)));
+        assert_eq!(eval("CEILING(2.5, 1)"), num(3.0));
+        assert_eq!(eval("CEILING(-2.5, 2)"), num(-2.0));
+        assert_eq!(eval("CEILING(-2.5, -2)"), num(-4.0));
+        assert_eq!(eval("CEILING(1.5, 0.1)"), num(1.5));
+        assert_eq!(eval("CEILING(4.2)"), num(5.0));
+        assert!(matches!(eval("ROUND()"), Eval::Err(_)));
+        assert!(matches!(eval("ROUND(1, 2, 3)"), Eval::Err(_)));
+    }
+
+    #[test]
+    fn test_integer_functions() {
+        let grid = grid_from(&[&["24"], &["36"]]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        assert_eq!(eval("MOD(3, 2)"), num(1.0));
+        assert_eq!(eval("MOD(-3, 2)"), num(1.0));
+        assert_eq!(eval("MOD(3, -2)"), num(-1.0));
+        assert!(matches!(eval("MOD(3, 0)"), Eval::Err(_)));
+        assert_eq!(eval("QUOTIENT(-10, 3)"), num(-3.0));
+        assert_eq!(eval("SIGN(-0.5)"), num(-1.0));
+        assert_eq!(eval("SIGN(A3)"), num(0.0));
+        assert_eq!(eval("GCD(A1:A2, 60)"), num(12.0));
+        assert_eq!(eval("GCD(0, 5)"), num(5.0));
+        assert_eq!(eval("LCM(A1:A2)"), num(72.0));
+        assert_eq!(eval("LCM(4, 0)"), num(0.0));
+        assert!(matches!(eval("GCD(-4, 2)"), Eval::Err(_)));
+        assert_eq!(eval("FACT(5.9)"), num(120.0));
+        assert_eq!(eval("FACT(0)"), num(1.0));
+        assert!(matches!(eval("FACT(171)"), Eval::Err(_)));
+        assert_eq!(eval("COMBIN(8, 2)"), num(28.0));
+        assert_eq!(eval("COMBIN(60, 30)"), num(118264581564861424.0));
+        assert_eq!(eval("PERMUT(100, 3)"), num(970200.0));
+        assert!(matches!(eval("PERMUT(3, 4)"), Eval::Err(_)));
+        // Overflow early instead of looping over every factor
+        assert!(is_err(eval("FACT(1e15)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("COMBIN(1e15, 5e14)"), LeadErrCode::Invalid));
+        assert!(is_err(eval("PERMUT(1e15, 1e15)"), LeadErrCode::Invalid));
+        assert_eq!(eval("COMBIN(1e15, 1e15 - 1)"), num(1e15));
+        assert_eq!(eval("PERMUT(1e15, 1)"), num(1e15));
+        assert_eq!(eval("POWER(5, 2)"), num(25.0));
+        assert_eq!(eval("POWER(4, 0.5)"), num(2.0));
+        assert!(matches!(eval("POWER(-4, 0.5)"), Eval::Err(_)));
+        assert!(matches!(eval("POWER(0, -1)"), Eval::Err(_)));
+    }
+
+    #[test]
+    fn test_array_arithmetic() {
+        let grid = grid_from(&[
+            &["1", "2"],
+            &["2", ""],
+            &["3", "4"],
+            &["4", "=true"],
+            &["5", "abc"],
+        ]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        let boolean = |b: bool| Eval::Literal(Literal::Boolean(b));
+        let array = |rows: usize, cols: usize, values: Vec<Eval>| {
+            Eval::Range(Array::new(rows, cols, values))
+        };
+        let is_err =
+            |eval: &Eval, code: LeadErrCode| matches!(eval, Eval::Err(e) if e.code == code);
+
+        // Blanks are zero and booleans one or zero
+        assert_eq!(
+            eval("A1:A4 * B1:B4"),
+            array(4, 1, vec![num(2.0), num(0.0), num(12.0), num(4.0)])
+        );
+        assert_eq!(eval("SUM(A1:A4 * B1:B4)"), num(18.0));
+        assert_eq!(eval("SUMPRODUCT(A1:A4, B1:B4)"), num(14.0));
+        assert_eq!(eval("SUMPRODUCT(A1:A5, B1:B5)"), num(14.0));
+        assert!(is_err(
+            &eval("SUMPRODUCT(A1:A4, B1:B3)"),
+            LeadErrCode::Invalid
+        ));
+        assert_eq!(eval("SUM((A1:A5 > 2) * A1:A5)"), num(12.0));
+        assert_eq!(eval("-A1:A2"), array(2, 1, vec![num(-1.0), num(-2.0)]));
+        assert_eq!(eval("A1 + 1"), num(2.0));
+        assert_eq!(eval("C1 + 1"), num(1.0));
+
+        // Errors stay in their own element
+        let res = eval("A4:A5 / B4:B5");
+        let Eval::Range(res) = res else {
+            panic!("expected an array, found {res:?}");
+        };
+        assert_eq!(res.values[0], num(4.0));
+        assert!(is_err(&res.values[1], LeadErrCode::TypeErr));
+
+        // A row combined with a column gives a table, mismatched sizes are padded with #N/A
+        assert_eq!(
+            eval("SEQUENCE(1, 3) * A1:A2"),
+            array(
+                2,
+                3,
+                vec![num(1.0), num(2.0), num(3.0), num(2.0), num(4.0), num(6.0)]
+            )
+        );
+        let res = eval("A1:A3 + SEQUENCE(2)");
+        let Eval::Range(res) = res else {
+            panic!("expected an array, found {res:?}");
+        };
+        assert_eq!(res.values[..2], [num(2.0), num(4.0)]);
+        assert!(is_err(&res.values[2], LeadErrCode::NotAvailable));
+
+        assert_eq!(
+            eval("A1:A5 > 3"),
+            array(
+                5,
+                1,
+                [false, false, false, true, true].map(boolean).to_vec()
+            )
+        );
+        assert_eq!(eval("1 + 2 = 3"), boolean(true));
+        assert_eq!(eval("A1 <> 1"), boolean(false));
+        assert_eq!(eval("A1 <= 1"), boolean(true));
+        assert_eq!(eval("A1 >= 2"), boolean(false));
+        assert_eq!(eval("\"abc\" = B5"), boolean(true));
+        assert_eq!(eval("\"B\" > \"a\""), boolean(true));
+        assert_eq!(eval("\"a\" > 99"), boolean(true));
+        assert_eq!(eval("B2 = 0"), boolean(true));
+        assert_eq!(eval("B2 = \"\""), boolean(true));
+
+        // Results larger than the array limit are rejected before they are built
+        assert!(is_err(
+            &eval("SUM(SEQUENCE(1000000) * TRANSPOSE(SEQUENCE(1000000)))"),
+            LeadErrCode::Invalid
+        ));
+        assert!(is_err(&eval("-A1:B1048576"), LeadErrCode::Invalid));
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Array, Eval, Precedents, evaluate_expr, numerics::map_elements, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Regular expression functions, using the syntax of the regex crate. Replacements refer to
+// capture groups as $1 or ${name}.
+
+// Compiled patterns by (pattern, case insensitive). Every Grid has one so that recalculating a
+// column of formulas compiles their pattern once.
+#[derive(Default)]
+pub struct RegexCache {
+    patterns: Mutex<HashMap<(String, bool), Regex>>,
+}
+
+// Patterns of formulas evaluated without a grid
+static SHARED: LazyLock<RegexCache> = LazyLock::new(RegexCache::default);
+
+impl RegexCache {
+    // Bounds the memory held by patterns built from cell values
+    const CAPACITY: usize = 1024;
+
+    pub fn get(&self, pattern: &str, case_insensitive: bool) -> Result<Regex, LeadErr> {
+        let key = (pattern.to_owned(), case_insensitive);
+        let mut patterns = self.patterns.lock().unwrap_or_else(|it| it.into_inner());
+
+        if let Some(regex) = patterns.get(&key) {
+            return Ok(regex.clone());
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| LeadErr {
+                title: "Invalid regular expression.".into(),
+                desc: e.to_string(),
+                code: LeadErrCode::Syntax,
+            })?;
+
+        if patterns.len() >= Self::CAPACITY {
+            patterns.clear();
+        }
+        patterns.insert(key, regex.clone());
+        Ok(regex)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.patterns.lock().unwrap().len()
+    }
+}
+
+fn text_of(value: &Eval, func_name: &str) -> Result<String, LeadErr> {
+    match value {
+        Eval::Literal(Literal::String(s)) => Ok(s.to_owned()),
+        Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => Ok(n.to_string()),
+        Eval::Literal(Literal::Boolean(b)) => Ok(b.to_string().to_uppercase()),
+        Eval::CellRef { eval, .. } => text_of(eval, func_name),
+        Eval::Unset => Ok(String::new()),
+        Eval::Err(e) => Err(e.to_owned()),
+        Eval::Range(_) => Err(eval_err(
+            format!("{func_name} requires a single text value."),
+            LeadErrCode::TypeErr,
+        )),
+    }
+}
+
+fn eval_text(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<String, LeadErr> {
+    text_of(&evaluate_expr(arg, precs, grid)?, func_name)
+}
+
+fn eval_regex(
+    args: &[Expr],
+    flag_index: usize,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Regex, LeadErr> {
+    let pattern = eval_text(&args[1], precs, grid, func_name)?;
+    let case_insensitive = match args.get(flag_index) {
+        Some(arg) => eval_number(arg, precs, grid, func_name)? != 0.0,
+        None => false,
+    };
+
+    grid.map_or(&*SHARED, |g| g.regexes())
+        .get(&pattern, case_insensitive)
+}
+
+fn text(s: &str) -> Eval {
+    Eval::Literal(Literal::String(s.into()))
+}
+
+// -------------------------------------------------- //
+
+// REGEXMATCH(text, pattern, [case_insensitive]), text may be a range
+pub fn eval_regexmatch(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let value = evaluate_expr(&args[0], precs, grid)?;
+    let regex = eval_regex(args, 2, precs, grid, "REGEXMATCH")?;
+
+    map_elements(&value, |it| {
+        let matched = regex.is_match(&text_of(it, "REGEXMATCH")?);
+        Ok(Eval::Literal(Literal::Boolean(matched)))
+    })
+}
+
+// REGEXEXTRACT(text, pattern, [mode], [case_insensitive]). Mode 0 returns the first match, 1 a
+// column of all matches and 2 a row of the capture groups of the first match.
+pub fn eval_regexextract(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let input = eval_text(&args[0], precs, grid, "REGEXEXTRACT")?;
+    let regex = eval_regex(args, 3, precs, grid, "REGEXEXTRACT")?;
+    let mode = match args.get(2) {
+        Some(arg) => eval_number(arg, precs, grid, "REGEXEXTRACT")?,
+        None => 0.0,
+    };
+
+    let no_match = || {
+        eval_err(
+            "REGEXEXTRACT found no match.".into(),
+            LeadErrCode::NotAvailable,
+        )
+    };
+
+    match mode {
+        0.0 => {
+            let found = regex.find(&input).ok_or_else(no_match)?;
+            Ok(text(found.as_str()))
+        }
+        1.0 => {
+            let matches: Vec<Eval> = regex
+                .find_iter(&input)
+                .map(|it| text(it.as_str()))
+                .collect();
+            if matches.is_empty() {
+                return Err(no_match());
+            }
+            Ok(Eval::Range(Array::new(matches.len(), 1, matches)))
+        }
+        2.0 => {
+            let captures = regex.captures(&input).ok_or_else(no_match)?;
+            if captures.len() == 1 {
+                return Err(eval_err(
+                    "REGEXEXTRACT mode 2 requires a pattern with capture groups.".into(),
+                    LeadErrCode::Invalid,
+                ));
+            }
+
+            // Groups that did not take part in the match are empty
+            let groups: Vec<Eval> = captures
+                .iter()
+                .skip(1)
+                .map(|it| text(it.map_or("", |it| it.as_str())))
+                .collect();
+            Ok(Eval::Range(Array::new(1, groups.len(), groups)))
+        }
+        _ => Err(eval_err(
+            "REGEXEXTRACT mode must be 0, 1 or 2.".into(),
+            LeadErrCode::Invalid,
+        )),
+    }
+}
+
+// REGEXREPLACE(text, pattern, replacement, [occurrence], [case_insensitive]). Occurrence 0
+// replaces every match, n replaces the nth match and -n the nth from the end. Text may be a
+// range.
+pub fn eval_regexreplace(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let value = evaluate_expr(&args[0], precs, grid)?;
+    let regex = eval_regex(args, 4, precs, grid, "REGEXREPLACE")?;
+    let replacement = eval_text(&args[2], precs, grid, "REGEXREPLACE")?;
+    let occurrence = match args.get(3) {
+        Some(arg) => eval_number(arg, precs, grid, "REGEXREPLACE")?.trunc() as i64,
+        None => 0,
+    };
+
+    map_elements(&value, |it| {
+        let input = text_of(it, "REGEXREPLACE")?;
+        if occurrence == 0 {
+            return Ok(text(&regex.replace_all(&input, replacement.as_str())));
+        }
+
+        let captures: Vec<_> = regex.captures_iter(&input).collect();
+        let index = match occurrence {
+            1.. => occurrence as usize - 1,
+            _ => captures
+                .len()
+                .wrapping_sub(occurrence.unsigned_abs() as usize),
+        };
+        let Some(found) = captures.get(index) else {
+            return Ok(text(&input));
+        };
+
+        let whole = found.get(0).unwrap();
+        let mut res = input[..whole.start()].to_owned();
+        found.expand(&replacement, &mut res);
+        res.push_str(&input[whole.end()..]);
+        Ok(text(&res))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    #[test]
+    fn test_regex_functions() {
+        let grid = grid_from(&[
+            &["Transfer to acct 12-3456 on 2024-03-01"],
+            &["Card purchase 2024-03-05 ref 998"],
+            &["Fee"],
+        ]);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        let boolean = |b: bool| Eval::Literal(Literal::Boolean(b));
+
+        assert_eq!(eval("REGEXMATCH(A1, '\\d{2}-\\d{4}')"), boolean(true));
+        assert_eq!(eval("REGEXMATCH(A3, 'fee')"), boolean(false));
+        assert_eq!(eval("REGEXMATCH(A3, 'fee', true)"), boolean(true));
+        assert_eq!(eval("REGEXMATCH(123, '^\\d+$')"), boolean(true));
+        assert_eq!(
+            eval("REGEXMATCH(A1:A3, '\\d{4}-\\d{2}-\\d{2}')"),
+            Eval::Range(Array::new(
+                3,
+                1,
+                vec![boolean(true), boolean(true), boolean(false)]
+            ))
+        );
+
+        assert_eq!(eval("REGEXEXTRACT(A1, '\\d+-\\d+')"), text("12-3456"));
+        assert_eq!(
+            eval("REGEXEXTRACT(A2, '\\d+', 1)"),
+            Eval::Range(Array::new(
+                4,
+                1,
+                vec![text("2024"), text("03"), text("05"), text("998")]
+            ))
+        );
+        assert_eq!(
+            eval("REGEXEXTRACT(A1, '(\\d{4})-(\\d{2})-(?P<day>\\d{2})', 2)"),
+            Eval::Range(Array::new(1, 3, vec![text("2024"), text("03"), text("01")]))
+        );
+        assert!(is_err(
+            eval("REGEXEXTRACT(A3, '\\d')"),
+            LeadErrCode::NotAvailable
+        ));
+        assert!(is_err(
+            eval("REGEXEXTRACT(A1, '\\d', 2)"),
+            LeadErrCode::Invalid
+        ));
+
+        let date = "'(\\d{4})-(\\d{2})-(\\d{2})'";
+        assert_eq!(
+            eval(&format!("REGEXREPLACE(A2, {date}, '$3/$2/$1')")),
+            text("Card purchase 05/03/2024 ref 998")
+        );
+        assert_eq!(eval("REGEXREPLACE(A3, 'E', 'x', 0, 1)"), text("Fxx"));
+        assert_eq!(eval("REGEXREPLACE('a-b-c', '-', '+', 2)"), text("a-b+c"));
+        assert_eq!(eval("REGEXREPLACE('a-b-c', '-', '+', -2)"), text("a+b-c"));
+        assert_eq!(eval("REGEXREPLACE('a-b-c', '-', '+', 3)"), text("a-b-c"));
+
+        let Eval::Err(err) = eval("REGEXMATCH(A1, '(')") else {
+            panic!("expected an invalid pattern error");
+        };
+        assert_eq!(err.code, LeadErrCode::Syntax);
+        assert!(err.desc.contains("unclosed group"));
+
+        // Patterns are compiled once per grid
+        assert_eq!(grid.regexes().len(), 12);
+        eval("REGEXMATCH(A1:A3, 'fee', true)");
+        assert_eq!(grid.regexes().len(), 12);
+    }
+}
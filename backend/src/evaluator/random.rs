@@ -0,0 +1,191 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Array, Eval, Precedents, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// SplitMix64, small and fast with good enough statistics for spreadsheet use. Every Grid owns
+// one so that a seed reproduces the random numbers of a workbook.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    // Seeded from the system time
+    pub fn from_time() -> Rng {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |it| it.as_nanos() as u64);
+        Rng::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Random numbers of formulas evaluated without a grid
+static SHARED: LazyLock<Mutex<Rng>> = LazyLock::new(|| Mutex::new(Rng::from_time()));
+
+// Draws count numbers in [0, 1) from the grid's generator
+fn draw(grid: Option<&Grid>, count: usize) -> Vec<f64> {
+    let rng = grid.map_or(&*SHARED, |g| g.rng());
+    let mut rng = rng.lock().unwrap_or_else(|it| it.into_inner());
+    (0..count).map(|_| rng.next_f64()).collect()
+}
+
+// -------------------------------------------------- //
+
+pub fn eval_rand(
+    _args: &[Expr],
+    _precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    Ok(Eval::Literal(Literal::Number(draw(grid, 1)[0])))
+}
+
+// RANDBETWEEN(bottom, top), an integer between the bounds inclusive
+pub fn eval_randbetween(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let bottom = eval_number(&args[0], precs, grid, "RANDBETWEEN")?.ceil();
+    let top = eval_number(&args[1], precs, grid, "RANDBETWEEN")?.floor();
+
+    if bottom > top {
+        return Err(eval_err(
+            "RANDBETWEEN requires bottom to be at most top.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let n = bottom + (draw(grid, 1)[0] * (top - bottom + 1.0)).floor();
+    Ok(Eval::Literal(Literal::Number(n.min(top))))
+}
+
+// RANDARRAY([rows], [cols], [min], [max], [integer])
+pub fn eval_randarray(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let mut params = [1.0, 1.0, 0.0, 1.0, 0.0];
+    for (param, arg) in params.iter_mut().zip(args) {
+        *param = eval_number(arg, precs, grid, "RANDARRAY")?;
+    }
+
+    let [rows, cols, min, max, integer] = params;
+    let (rows, cols, integer) = (rows.trunc(), cols.trunc(), integer != 0.0);
+    let (min, max) = if integer {
+        (min.ceil(), max.floor())
+    } else {
+        (min, max)
+    };
+
+    if rows < 1.0 || cols < 1.0 || rows * cols > MAX_CELLS as f64 {
+        return Err(eval_err(
+            format!("RANDARRAY requires between 1 and {MAX_CELLS} cells."),
+            LeadErrCode::Invalid,
+        ));
+    }
+    if min > max {
+        return Err(eval_err(
+            "RANDARRAY requires min to be at most max.".into(),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let (rows, cols) = (rows as usize, cols as usize);
+    let values = draw(grid, rows * cols)
+        .into_iter()
+        .map(|x| {
+            let n = if integer {
+                (min + (x * (max - min + 1.0)).floor()).min(max)
+            } else {
+                min + x * (max - min)
+            };
+            Eval::Literal(Literal::Number(n))
+        })
+        .collect();
+
+    Ok(Eval::Range(Array::new(rows, cols, values)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    fn numbers(eval: Eval) -> Vec<f64> {
+        let values = match eval {
+            Eval::Range(array) => array.values,
+            it => vec![it],
+        };
+        values
+            .into_iter()
+            .map(|it| match it {
+                Eval::Literal(Literal::Number(n)) => n,
+                it => panic!("expected a number, found {it:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_random_functions() {
+        let grid = Grid::new().with_seed(42);
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        let xs = numbers(eval("RANDARRAY(50, 2)"));
+        assert_eq!(xs.len(), 100);
+        assert!(xs.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!(xs.windows(2).any(|it| it[0] != it[1]));
+
+        let ns = numbers(eval("RANDARRAY(200, 1, -2, 2, true)"));
+        assert!(
+            ns.iter()
+                .all(|n| n.fract() == 0.0 && (-2.0..=2.0).contains(n))
+        );
+        for n in [-2.0, -1.0, 0.0, 1.0, 2.0] {
+            assert!(ns.contains(&n));
+        }
+
+        let n = numbers(eval("RANDBETWEEN(1.5, 3)"))[0];
+        assert!(n == 2.0 || n == 3.0);
+        assert_eq!(numbers(eval("RANDBETWEEN(5, 5)")), [5.0]);
+        assert!(matches!(eval("RANDBETWEEN(2, 1)"), Eval::Err(_)));
+        assert!(matches!(eval("RANDARRAY(0)"), Eval::Err(_)));
+
+        // The same seed gives the same numbers
+        let same = Grid::new().with_seed(42);
+        assert_eq!(
+            numbers(evaluate("RANDARRAY(50, 2)".into(), Some(&same)).0),
+            xs
+        );
+        let other = Grid::new().with_seed(7);
+        assert_ne!(
+            numbers(evaluate("RANDARRAY(50, 2)".into(), Some(&other)).0),
+            xs
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    cell::{CellRef, MAX_COLS, MAX_ROWS},
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Eval, Precedents, evaluate_expr, range_between, utils::*},
+    grid::Grid,
+    parser::{Expr, InfixOp, parse},
+};
+
+// Functions computing the cells they read at runtime. The cells are added to the precedents
+// like any other reference, and the grid registers them again after every evaluation.
+
+// The value of the cell, or the range of cells, from top_left spanning rows x cols
+fn eval_area(
+    top_left: CellRef,
+    rows: usize,
+    cols: usize,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    if rows == 1 && cols == 1 {
+        return evaluate_expr(&Expr::CellRef(top_left), precs, grid);
+    }
+
+    let bottom_right = top_left
+        .row
+        .checked_add(rows - 1)
+        .zip(top_left.col.checked_add(cols - 1))
+        .map(|(row, col)| CellRef { row, col })
+        .filter(|it| it.row < MAX_ROWS && it.col < MAX_COLS);
+    let Some(bottom_right) = bottom_right else {
+        return Err(eval_err(
+            "Reference is outside the grid.".into(),
+            LeadErrCode::Ref,
+        ));
+    };
+    range_between(top_left, bottom_right, precs, grid)
+}
+
+// INDIRECT(text), the cell or range named by text such as "B2" or "A1:C3"
+pub fn eval_indirect(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let mut text = evaluate_expr(&args[0], precs, grid)?;
+    while let Eval::CellRef { eval, .. } = text {
+        text = *eval;
+    }
+    let text = match text {
+        Eval::Literal(Literal::String(s)) => s,
+        Eval::Err(e) => return Err(e),
+        it => {
+            return Err(eval_err(
+                format!("INDIRECT requires a reference as text, found {it}."),
+                LeadErrCode::Ref,
+            ));
+        }
+    };
+
+    let invalid = || {
+        eval_err(
+            format!("INDIRECT found an invalid reference {text:?}."),
+            LeadErrCode::Ref,
+        )
+    };
+    let expr = match parse(&text) {
+        Ok((expr, _)) => expr,
+        Err(_) => return Err(invalid()),
+    };
+
+    match &expr {
+        Expr::CellRef(_) => evaluate_expr(&expr, precs, grid),
+        Expr::Infix {
+            op: InfixOp::RANGE,
+            lhs,
+            rhs,
+        } if matches!(**lhs, Expr::CellRef(_)) && matches!(**rhs, Expr::CellRef(_)) => {
+            evaluate_expr(&expr, precs, grid)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// OFFSET(reference, rows, cols, [height], [width]), the area of height x width cells, by
+// default the size of reference, moved rows down and cols right from reference
+pub fn eval_offset(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let (top_left, height, width) = match evaluate_expr(&args[0], precs, grid)? {
+        Eval::CellRef { reference, .. } => (reference, 1, 1),
+        Eval::Range(range) => match range.values.first() {
+            Some(Eval::CellRef { reference, .. }) => (*reference, range.rows, range.cols),
+            _ => {
+                return Err(eval_err(
+                    "OFFSET requires a reference.".into(),
+                    LeadErrCode::Ref,
+                ));
+            }
+        },
+        Eval::Err(e) => return Err(e),
+        _ => {
+            return Err(eval_err(
+                "OFFSET requires a reference.".into(),
+                LeadErrCode::Ref,
+            ));
+        }
+    };
+
+    let mut params = [0.0, 0.0, height as f64, width as f64];
+    for (param, arg) in params.iter_mut().zip(&args[1..]) {
+        *param = eval_number(arg, precs, grid, "OFFSET")?.trunc();
+    }
+    let [rows, cols, height, width] = params;
+
+    // NaN fails every comparison, so it would pass the bounds checks below
+    if params.iter().any(|it| !it.is_finite()) {
+        return Err(eval_err(
+            "OFFSET requires finite numbers.".into(),
+            LeadErrCode::Ref,
+        ));
+    }
+    if height < 1.0 || width < 1.0 {
+        return Err(eval_err(
+            "OFFSET requires a height and width of at least 1.".into(),
+            LeadErrCode::Ref,
+        ));
+    }
+
+    // Checked as floats so huge arguments can't overflow before they're rejected
+    let row = top_left.row as f64 + rows;
+    let col = top_left.col as f64 + cols;
+    if row < 0.0 || col < 0.0 || row + height > MAX_ROWS as f64 || col + width > MAX_COLS as f64 {
+        return Err(eval_err(
+            "OFFSET moved outside the grid.".into(),
+            LeadErrCode::Ref,
+        ));
+    }
+
+    let top_left = CellRef {
+        row: row as usize,
+        col: col as usize,
+    };
+    eval_area(top_left, height as usize, width as usize, precs, grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indirect() {
+        let mut grid = Grid::new();
+        set_cells(
+            &mut grid,
+            &[
+                ("A1", "1"),
+                ("A2", "2"),
+                ("A3", "3"),
+                ("F1", "A1"),
+                ("C1", "=F1"),
+                ("D1", "=INDIRECT(C1)"),
+                ("D2", "=SUM(INDIRECT(\"A1:\" & \"A3\"))"),
+            ],
+        );
+        assert_eq!(value(&grid, "D1"), num(1.0));
+
+        // The target is a dependency found at runtime
+        grid.update_cell(at("A1"), "10".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(10.0));
+
+        // Retargeting through propagation moves the dependency
+        grid.update_cell(at("F1"), "A2".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(2.0));
+        grid.update_cell(at("A2"), "20".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(20.0));
+        let updated = grid.update_cell(at("A1"), "11".into()).unwrap();
+        assert!(!updated.contains(&at("D1")));
+
+        grid.update_cell(at("F1"), "not a ref".into()).unwrap();
+        assert!(is_err(value(&grid, "D1"), LeadErrCode::Ref));
+
+        // References outside the sheet are invalid rather than walked
+        for text in ["A1:A1000000000", "A0", "XFE1", "ZZZZZZZZZZZZZZZZ1"] {
+            grid.update_cell(at("F1"), text.into()).unwrap();
+            assert!(is_err(value(&grid, "D1"), LeadErrCode::Ref), "{text}");
+        }
+    }
+
+    #[test]
+    fn test_offset() {
+        let mut grid = Grid::new();
+        set_cells(
+            &mut grid,
+            &[
+                ("A1", "1"),
+                ("A2", "2"),
+                ("B2", "3"),
+                ("E1", "0"),
+                ("D1", "=OFFSET(A1, E1, 1)"),
+                ("D2", "=SUM(OFFSET(A1, 0, 0, 2, 2))"),
+                ("D3", "=SUM(OFFSET(A1:A2, 0, 1))"),
+            ],
+        );
+
+        assert_eq!(value(&grid, "D1"), Eval::Unset);
+        assert_eq!(value(&grid, "D2"), num(6.0));
+        assert_eq!(value(&grid, "D3"), num(3.0));
+
+        // Outside the sheet is an error before any cell is built or walked
+        for raw in [
+            "=OFFSET(A1, 1e20, 0, 2, 1)",
+            "=OFFSET(A1, 1e20, 0)",
+            "=OFFSET(A1, 0, 1e20)",
+            "=OFFSET(A1, 0, 0, 1e12, 1)",
+            "=OFFSET(A1, 1048575, 0, 2, 1)",
+            "=OFFSET(A1, 1e308 * 10 - 1e308 * 10, 0)",
+            "=OFFSET(A1, 0, 1e308 * 10 - 1e308 * 10)",
+            "=OFFSET(A1, 0, 0, 1e308 * 10 - 1e308 * 10)",
+            "=OFFSET(A1, 0, 0, 1, 1e308 * 10)",
+        ] {
+            grid.update_cell(at("D4"), raw.into()).unwrap();
+            assert!(is_err(value(&grid, "D4"), LeadErrCode::Ref), "{raw}");
+        }
+        grid.update_cell(at("D4"), "=OFFSET(A1, \"nan\", 0)".into())
+            .unwrap();
+        assert!(matches!(value(&grid, "D4"), Eval::Err(_)));
+        grid.update_cell(at("D4"), "=OFFSET(A1, 1048575, 16383)".into())
+            .unwrap();
+        assert_eq!(value(&grid, "D4"), Eval::Unset);
+
+        grid.update_cell(at("E1"), "1".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(3.0));
+        grid.update_cell(at("B2"), "4".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(4.0));
+
+        grid.update_cell(at("E1"), "-1".into()).unwrap();
+        assert!(is_err(value(&grid, "D1"), LeadErrCode::Ref));
+        grid.update_cell(at("D1"), "=OFFSET(A1, 0, 0, 0)".into())
+            .unwrap();
+        assert!(is_err(value(&grid, "D1"), LeadErrCode::Ref));
+    }
+}
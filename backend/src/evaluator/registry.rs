@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{LeadErr, LeadErrCode},
+    evaluator::{Eval, Precedents, builtins::builtins},
+    grid::Grid,
+    parser::Expr,
+};
+
+pub type EvalFn = fn(&[Expr], &mut Precedents, Option<&Grid>) -> Result<Eval, LeadErr>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Arity {
+    pub min: usize,
+    // None for variadic functions
+    pub max: Option<usize>,
+}
+
+impl Arity {
+    pub const fn exact(n: usize) -> Arity {
+        Arity {
+            min: n,
+            max: Some(n),
+        }
+    }
+
+    pub const fn between(min: usize, max: usize) -> Arity {
+        Arity {
+            min,
+            max: Some(max),
+        }
+    }
+
+    pub const fn at_least(min: usize) -> Arity {
+        Arity { min, max: None }
+    }
+
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.min && self.max.is_none_or(|max| n <= max)
+    }
+}
+
+// What a function expects of an argument. Purely descriptive, functions coerce their own
+// arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ArgKind {
+    Number,
+    Text,
+    Date,
+    // A range or a single value
+    Range,
+    // A criterion such as ">10", see evaluator/criteria.rs
+    Criteria,
+    Any,
+}
+
+// A spreadsheet function. Implement this to add functions to a Grid, see
+// Grid::with_functions.
+pub trait Function: Send + Sync {
+    // Upper case name used in formulas
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> Arity;
+
+    // Kinds of the parameters in order, variadic functions repeat the last kind
+    fn arg_kinds(&self) -> &[ArgKind];
+
+    // Volatile functions, such as NOW, depend on more than their arguments
+    fn volatile(&self) -> bool {
+        false
+    }
+
+    fn description(&self) -> &str;
+
+    fn eval(
+        &self,
+        args: &[Expr],
+        precs: &mut Precedents,
+        grid: Option<&Grid>,
+    ) -> Result<Eval, LeadErr>;
+}
+
+// A function built from static data and a plain function pointer. Used for the built-in
+// functions and handy for embedders whose functions need no state.
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub arg_kinds: &'static [ArgKind],
+    pub volatile: bool,
+    pub description: &'static str,
+    pub eval: EvalFn,
+}
+
+impl Function for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn arg_kinds(&self) -> &[ArgKind] {
+        self.arg_kinds
+    }
+
+    fn volatile(&self) -> bool {
+        self.volatile
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn eval(
+        &self,
+        args: &[Expr],
+        precs: &mut Precedents,
+        grid: Option<&Grid>,
+    ) -> Result<Eval, LeadErr> {
+        (self.eval)(args, precs, grid)
+    }
+}
+
+// Function signature sent to clients, e.g. for autocompletion
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub arity: Arity,
+    pub arg_kinds: Vec<ArgKind>,
+    pub volatile: bool,
+    pub description: String,
+}
+
+// -------------------------------------------------- //
+
+static BUILTINS: LazyLock<FunctionRegistry> = LazyLock::new(|| {
+    let mut registry = FunctionRegistry::new();
+    for function in builtins() {
+        registry.register(function);
+    }
+    registry
+});
+
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, Arc<dyn Function>>,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    // The registry used when evaluating without a grid
+    pub fn builtin() -> &'static FunctionRegistry {
+        &BUILTINS
+    }
+
+    // Adds a function, replacing any existing function of the same name. Names are case
+    // insensitive.
+    pub fn register(&mut self, function: impl Function + 'static) {
+        self.functions
+            .insert(function.name().to_uppercase(), Arc::new(function));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Function> {
+        self.functions
+            .get(&name.to_uppercase())
+            .map(|it| it.as_ref())
+    }
+
+    // Sorted by name
+    pub fn infos(&self) -> Vec<FunctionInfo> {
+        let mut infos: Vec<FunctionInfo> = self
+            .functions
+            .values()
+            .map(|it| FunctionInfo {
+                name: it.name().to_uppercase(),
+                arity: it.arity(),
+                arg_kinds: it.arg_kinds().to_vec(),
+                volatile: it.volatile(),
+                description: it.description().into(),
+            })
+            .collect();
+
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    // Looks up and evaluates a function, checking the number of arguments first
+    pub fn call(
+        &self,
+        name: &str,
+        args: &[Expr],
+        precs: &mut Precedents,
+        grid: Option<&Grid>,
+    ) -> Result<Eval, LeadErr> {
+        let Some(function) = self.get(name) else {
+            return Err(LeadErr {
+                title: "Evaluation error.".into(),
+                desc: format!("Unsupported function {:?}", name),
+                code: LeadErrCode::Unsupported,
+            });
+        };
+
+        let arity = function.arity();
+        if !arity.accepts(args.len()) {
+            let expected = match arity.max {
+                Some(max) if max == arity.min => format!("{max}"),
+                Some(max) => format!("{} to {max}", arity.min),
+                None => format!("at least {}", arity.min),
+            };
+
+            return Err(LeadErr {
+                title: "Evaluation error.".into(),
+                desc: format!(
+                    "{} function requires {expected} argument(s), found {}.",
+                    function.name(),
+                    args.len()
+                ),
+                code: LeadErrCode::Invalid,
+            });
+        }
+
+        function.eval(args, precs, grid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::Literal,
+        evaluator::{
+            evaluate,
+            utils::{num, set_cells, value},
+        },
+    };
+
+    fn eval_double(
+        args: &[Expr],
+        precs: &mut Precedents,
+        grid: Option<&Grid>,
+    ) -> Result<Eval, LeadErr> {
+        let n = crate::evaluator::utils::eval_number(&args[0], precs, grid, "DOUBLE")?;
+        Ok(Eval::Literal(Literal::Number(n * 2.0)))
+    }
+
+    #[test]
+    fn test_registry() {
+        let builtins = FunctionRegistry::builtin();
+        let sum = builtins.get("sum").unwrap();
+        assert_eq!(sum.arity(), Arity::at_least(1));
+        assert!(!sum.volatile());
+        assert!(builtins.get("NOW").unwrap().volatile());
+
+        let infos = builtins.infos();
+        assert!(infos.windows(2).all(|it| it[0].name < it[1].name));
+        assert!(infos.iter().all(|it| !it.description.is_empty()));
+        assert!(
+            infos
+                .iter()
+                .all(|it| !it.arg_kinds.is_empty() || it.arity.max == Some(0))
+        );
+
+        let eval = |s: &str| evaluate(s.into(), None).0;
+        assert!(matches!(
+            eval("ROUND(1, 2, 3)"),
+            Eval::Err(LeadErr {
+                code: LeadErrCode::Invalid,
+                ..
+            })
+        ));
+        assert!(matches!(
+            eval("NOPE(1)"),
+            Eval::Err(LeadErr {
+                code: LeadErrCode::Unsupported,
+                ..
+            })
+        ));
+        assert_eq!(eval("sum(1, 2)"), num(3.0));
+
+        // Functions index their arguments directly, relying on the arity to have checked them
+        for info in &infos {
+            let max = info.arity.max.unwrap_or(info.arity.min + 2);
+            for n in info.arity.min..=max {
+                let args = vec!["1"; n].join(", ");
+                evaluate(format!("{}({args})", info.name), None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_functions() {
+        let mut functions = FunctionRegistry::builtin().clone();
+        functions.register(Builtin {
+            name: "DOUBLE",
+            arity: Arity::exact(1),
+            arg_kinds: &[ArgKind::Number],
+            volatile: false,
+            description: "Doubles a number.",
+            eval: eval_double,
+        });
+
+        let mut grid = Grid::new().with_functions(functions);
+        set_cells(&mut grid, &[("A1", "21"), ("B1", "=DOUBLE(A1) + SUM(A1)")]);
+        assert_eq!(value(&grid, "B1"), num(63.0));
+        assert!(matches!(
+            evaluate("DOUBLE(1)".into(), None).0,
+            Eval::Err(LeadErr {
+                code: LeadErrCode::Unsupported,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,470 @@
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Array, Eval, Precedents, stats::eval_paired_numbers, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+// Relative size below which a diagonal entry of R marks a rank deficient (collinear) design
+const RANK_TOL: f64 = 1e-10;
+
+// Least squares solution of min ||A b - y|| using Householder QR. A is a list of n rows with p
+// columns each. Solving the triangular system R b = Q^T y avoids forming A^T A, which squares
+// the condition number of the problem.
+#[allow(clippy::needless_range_loop)]
+pub fn least_squares(a: &[Vec<f64>], y: &[f64], func_name: &str) -> Result<Vec<f64>, LeadErr> {
+    let n = a.len();
+    let p = a.first().map_or(0, |row| row.len());
+
+    if n < p || p == 0 {
+        return Err(eval_err(
+            format!("{func_name} has insufficient data for {p} coefficient(s)."),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    let mut r: Vec<Vec<f64>> = a.to_vec();
+    let mut qty: Vec<f64> = y.to_vec();
+    let mut max_diag: f64 = 0.0;
+
+    for j in 0..p {
+        let norm = (j..n).map(|i| r[i][j] * r[i][j]).sum::<f64>().sqrt();
+        let alpha = if r[j][j] > 0.0 { -norm } else { norm };
+
+        let mut v: Vec<f64> = (j..n).map(|i| r[i][j]).collect();
+        v[0] -= alpha;
+        let v_norm2: f64 = v.iter().map(|x| x * x).sum();
+
+        if v_norm2 > 0.0 {
+            for col in j..p {
+                let s: f64 = (j..n).map(|i| v[i - j] * r[i][col]).sum::<f64>() * 2.0 / v_norm2;
+                (j..n).for_each(|i| r[i][col] -= s * v[i - j]);
+            }
+
+            let s: f64 = (j..n).map(|i| v[i - j] * qty[i]).sum::<f64>() * 2.0 / v_norm2;
+            (j..n).for_each(|i| qty[i] -= s * v[i - j]);
+        }
+
+        max_diag = max_diag.max(r[j][j].abs());
+    }
+
+    if (0..p).any(|j| r[j][j].abs() <= RANK_TOL * max_diag) || max_diag == 0.0 {
+        return Err(eval_err(
+            format!("{func_name} found collinear data, the coefficients are not unique."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    // Back substitution
+    let mut b = vec![0.0; p];
+    for j in (0..p).rev() {
+        let s: f64 = (j + 1..p).map(|k| r[j][k] * b[k]).sum();
+        b[j] = (qty[j] - s) / r[j][j];
+    }
+
+    Ok(b)
+}
+
+// Design matrix with an optional leading column of ones for the intercept
+fn design(xs: &[Vec<f64>], constant: bool) -> Vec<Vec<f64>> {
+    xs.iter()
+        .map(|row| {
+            let mut res = Vec::with_capacity(row.len() + 1);
+            if constant {
+                res.push(1.0);
+            }
+            res.extend(row);
+            res
+        })
+        .collect()
+}
+
+// Fits y = b + m_1 x_1 + ... + m_k x_k and returns [b, m_1, ..., m_k], b is 0 without a constant
+fn fit(xs: &[Vec<f64>], ys: &[f64], constant: bool, func_name: &str) -> Result<Vec<f64>, LeadErr> {
+    let mut coefs = least_squares(&design(xs, constant), ys, func_name)?;
+    if !constant {
+        coefs.insert(0, 0.0);
+    }
+
+    Ok(coefs)
+}
+
+fn predict(coefs: &[f64], x: &[f64]) -> f64 {
+    coefs[0] + coefs[1..].iter().zip(x).map(|(m, x)| m * x).sum::<f64>()
+}
+
+fn fit_line(xs: &[f64], ys: &[f64], func_name: &str) -> Result<(f64, f64), LeadErr> {
+    if xs.len() < 2 {
+        return Err(eval_err(
+            format!("{func_name} requires at least 2 data points."),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    let rows: Vec<Vec<f64>> = xs.iter().map(|x| vec![*x]).collect();
+    let coefs = fit(&rows, ys, true, func_name)?;
+
+    Ok((coefs[1], coefs[0]))
+}
+
+pub fn slope(ys: &[f64], xs: &[f64]) -> Result<f64, LeadErr> {
+    Ok(fit_line(xs, ys, "SLOPE")?.0)
+}
+
+pub fn intercept(ys: &[f64], xs: &[f64]) -> Result<f64, LeadErr> {
+    Ok(fit_line(xs, ys, "INTERCEPT")?.1)
+}
+
+fn sum_sq_err(ys: &[f64], xs: &[f64], func_name: &str) -> Result<f64, LeadErr> {
+    let (m, b) = fit_line(xs, ys, func_name)?;
+    Ok(xs
+        .iter()
+        .zip(ys)
+        .map(|(x, y)| (y - (m * x + b)).powi(2))
+        .sum())
+}
+
+pub fn rsq(ys: &[f64], xs: &[f64]) -> Result<f64, LeadErr> {
+    let sse = sum_sq_err(ys, xs, "RSQ")?;
+    let my = ys.iter().sum::<f64>() / ys.len() as f64;
+    let sst: f64 = ys.iter().map(|y| (y - my).powi(2)).sum();
+
+    if sst == 0.0 {
+        return Err(eval_err(
+            "RSQ requires known_y values that are not all equal.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    Ok(1.0 - sse / sst)
+}
+
+pub fn steyx(ys: &[f64], xs: &[f64]) -> Result<f64, LeadErr> {
+    if xs.len() < 3 {
+        return Err(eval_err(
+            "STEYX requires at least 3 data points.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    Ok((sum_sq_err(ys, xs, "STEYX")? / (xs.len() - 2) as f64).sqrt())
+}
+
+// FUNC(known_y, known_x) for single variable fits
+pub fn eval_line_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: fn(&[f64], &[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let (ys, xs) = eval_paired_numbers(&args[0], &args[1], precs, grid, func_name)?;
+    Ok(Eval::Literal(Literal::Number(func(&ys, &xs)?)))
+}
+
+// FORECAST.LINEAR(x, known_y, known_x)
+pub fn eval_forecast(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let x = eval_number(&args[0], precs, grid, "FORECAST.LINEAR")?;
+    let (ys, xs) = eval_paired_numbers(&args[1], &args[2], precs, grid, "FORECAST.LINEAR")?;
+    let (m, b) = fit_line(&xs, &ys, "FORECAST.LINEAR")?;
+
+    Ok(Eval::Literal(Literal::Number(m * x + b)))
+}
+
+// -------------------------------------------------- //
+
+fn strict_numbers(values: Vec<Eval>, func_name: &str) -> Result<Vec<f64>, LeadErr> {
+    values
+        .into_iter()
+        .map(|it| match it {
+            Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => Ok(n),
+            Eval::Err(e) => Err(e),
+            _ => Err(eval_err(
+                format!("{func_name} requires numeric ranges."),
+                LeadErrCode::TypeErr,
+            )),
+        })
+        .collect()
+}
+
+// Splits a (rows, cols) range into observations. A range with one value per observation is a
+// single variable, otherwise each column (or each row when the data runs across) is a variable.
+fn observations(
+    (rows, cols, values): (usize, usize, Vec<f64>),
+    n: Option<usize>,
+    by_column: bool,
+    func_name: &str,
+) -> Result<Vec<Vec<f64>>, LeadErr> {
+    if n.is_none_or(|n| n == values.len()) && (rows == 1 || cols == 1) {
+        return Ok(values.into_iter().map(|x| vec![x]).collect());
+    }
+
+    if by_column && n.is_none_or(|n| n == rows) {
+        Ok(values.chunks(cols).map(|it| it.to_vec()).collect())
+    } else if !by_column && n.is_none_or(|n| n == cols) {
+        Ok((0..cols)
+            .map(|c| (0..rows).map(|r| values[r * cols + c]).collect())
+            .collect())
+    } else {
+        Err(eval_err(
+            format!("{func_name} requires known_x to line up with known_y."),
+            LeadErrCode::Invalid,
+        ))
+    }
+}
+
+struct Known {
+    ys: Vec<f64>,
+    xs: Vec<Vec<f64>>,
+    by_column: bool,
+}
+
+fn eval_known(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<Known, LeadErr> {
+    let (y_rows, _, ys) = eval_range_shape(&args[0], precs, grid)?;
+    let ys = strict_numbers(ys, func_name)?;
+    let by_column = y_rows == ys.len();
+
+    let xs = match args.get(1) {
+        Some(arg) => {
+            let (rows, cols, xs) = eval_range_shape(arg, precs, grid)?;
+            let xs = strict_numbers(xs, func_name)?;
+            observations((rows, cols, xs), Some(ys.len()), by_column, func_name)?
+        }
+        None => (1..=ys.len()).map(|x| vec![x as f64]).collect(),
+    };
+
+    Ok(Known { ys, xs, by_column })
+}
+
+fn eval_const_arg(
+    arg: Option<&Expr>,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<bool, LeadErr> {
+    match arg {
+        Some(arg) => Ok(eval_number(arg, precs, grid, func_name)? != 0.0),
+        None => Ok(true),
+    }
+}
+
+fn array(rows: usize, cols: usize, values: Vec<f64>) -> Eval {
+    Eval::Range(Array::new(
+        rows,
+        cols,
+        values
+            .into_iter()
+            .map(|x| Eval::Literal(Literal::Number(x)))
+            .collect(),
+    ))
+}
+
+// LINEST(known_y, [known_x], [const]) returns the coefficients in Excel's order:
+// m_k, ..., m_1, b
+pub fn eval_linest(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let known = eval_known(&args[..args.len().min(2)], precs, grid, "LINEST")?;
+    let constant = eval_const_arg(args.get(2), precs, grid, "LINEST")?;
+
+    let mut coefs = fit(&known.xs, &known.ys, constant, "LINEST")?;
+    coefs.reverse();
+
+    Ok(array(1, coefs.len(), coefs))
+}
+
+// TREND(known_y, [known_x], [new_x], [const]) and GROWTH, which fits ln(y) and returns
+// exponentiated predictions
+fn eval_trend_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    growth: bool,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let mut known = eval_known(&args[..args.len().min(2)], precs, grid, func_name)?;
+    let constant = eval_const_arg(args.get(3), precs, grid, func_name)?;
+
+    if growth {
+        if known.ys.iter().any(|y| *y <= 0.0) {
+            return Err(eval_err(
+                format!("{func_name} requires positive known_y values."),
+                LeadErrCode::Invalid,
+            ));
+        }
+        known.ys.iter_mut().for_each(|y| *y = y.ln());
+    }
+
+    let coefs = fit(&known.xs, &known.ys, constant, func_name)?;
+
+    // Predictions for a single variable take the shape of new_x, otherwise they follow the
+    // direction of known_y
+    let mut shape = None;
+    let new_xs = match args.get(2) {
+        Some(arg) => {
+            let (rows, cols, xs) = eval_range_shape(arg, precs, grid)?;
+            let xs = strict_numbers(xs, func_name)?;
+            if known.xs[0].len() == 1 {
+                shape = Some((rows, cols));
+                xs.into_iter().map(|x| vec![x]).collect()
+            } else {
+                observations((rows, cols, xs), None, known.by_column, func_name)?
+            }
+        }
+        None => known.xs,
+    };
+
+    if new_xs.iter().any(|x| x.len() != coefs.len() - 1) {
+        return Err(eval_err(
+            format!("{func_name} requires new_x to have the same variables as known_x."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let preds: Vec<f64> = new_xs
+        .iter()
+        .map(|x| predict(&coefs, x))
+        .map(|y| if growth { y.exp() } else { y })
+        .collect();
+
+    let (rows, cols) = match shape {
+        Some(it) => it,
+        None if known.by_column => (preds.len(), 1),
+        None => (1, preds.len()),
+    };
+
+    if preds.len() == 1 {
+        Ok(Eval::Literal(Literal::Number(preds[0])))
+    } else {
+        Ok(array(rows, cols, preds))
+    }
+}
+
+pub fn eval_trend(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    eval_trend_func(args, precs, grid, false, "TREND")
+}
+
+pub fn eval_growth(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    eval_trend_func(args, precs, grid, true, "GROWTH")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluator::evaluate;
+
+    // Reference values are the outputs of Excel on the same data
+    #[test]
+    fn test_regression_table() {
+        let ys = [2.0, 3.0, 9.0, 1.0, 8.0, 7.0, 5.0];
+        let xs = [6.0, 5.0, 11.0, 7.0, 5.0, 4.0, 4.0];
+
+        assert_close("SLOPE", slope(&ys, &xs), 0.305555555556);
+        assert_close("INTERCEPT", intercept(&ys[..5], &xs[..5]), 0.048387096774);
+        assert_close("RSQ", rsq(&ys, &xs), 0.057950191571);
+        assert_close("STEYX", steyx(&ys, &xs), 3.305718950210);
+
+        let (m, b) = fit_line(
+            &[20.0, 28.0, 31.0, 38.0, 40.0],
+            &[6.0, 7.0, 9.0, 15.0, 21.0],
+            "FORECAST",
+        )
+        .unwrap();
+        assert_close("FORECAST", Ok(m * 30.0 + b), 10.607253086420);
+    }
+
+    #[test]
+    fn test_regression_errors() {
+        assert_eq!(
+            slope(&[1.0], &[1.0]).unwrap_err().code,
+            LeadErrCode::DivZero
+        );
+        assert_eq!(
+            slope(&[1.0, 2.0, 3.0], &[2.0, 2.0, 2.0]).unwrap_err().code,
+            LeadErrCode::Invalid
+        );
+        assert_eq!(
+            rsq(&[1.0, 1.0, 1.0], &[1.0, 2.0, 3.0]).unwrap_err().code,
+            LeadErrCode::DivZero
+        );
+    }
+
+    #[test]
+    fn test_linest_trend_growth() {
+        // y = 3 + 2 x1 - x2, x1, x2, 2 x1 and 2 * 3^x1
+        let grid = grid_from(&[
+            &["1", "1", "4", "2", "6"],
+            &["6", "2", "1", "4", "18"],
+            &["2", "3", "7", "6", "54"],
+            &["9", "4", "2", "8", "162"],
+            &["10", "5", "3", "10", "486"],
+        ]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+        let numbers = |eval: Eval| match eval {
+            Eval::Range(it) => it
+                .values
+                .into_iter()
+                .map(|it| match it {
+                    Eval::Literal(Literal::Number(n)) => n,
+                    it => panic!("Expected number, found {it:?}"),
+                })
+                .collect::<Vec<f64>>(),
+            Eval::Literal(Literal::Number(n)) => vec![n],
+            it => panic!("Expected numbers, found {it:?}"),
+        };
+
+        let coefs = numbers(eval("LINEST(A1:A5, B1:C5)"));
+        for (res, expected) in coefs.iter().zip([-1.0, 2.0, 3.0]) {
+            assert_close("LINEST", Ok(*res), expected);
+        }
+
+        let coefs = numbers(eval("LINEST(B1:B5)"));
+        for (res, expected) in coefs.iter().zip([1.0, 0.0]) {
+            assert_close("LINEST", Ok(*res), expected);
+        }
+
+        let Eval::Err(err) = eval("LINEST(A1:A5, B1:D5)") else {
+            panic!("Expected a collinear error");
+        };
+        assert_eq!(err.code, LeadErrCode::Invalid);
+
+        let preds = numbers(eval("TREND(A1:A5, B1:C5, B1:C2)"));
+        for (res, expected) in preds.iter().zip([1.0, 6.0]) {
+            assert_close("TREND", Ok(*res), expected);
+        }
+
+        // Through the origin, m = sum(xy) / sum(x^2) = 50 / 79
+        let preds = numbers(eval("TREND(B1:B5, C1:C5, 10, 0)"));
+        assert_eq!(preds.len(), 1);
+        assert_close("TREND", Ok(preds[0]), 6.329113924051);
+
+        let preds = numbers(eval("GROWTH(E1:E5, B1:B5, 0)"));
+        assert_close("GROWTH", Ok(preds[0]), 2.0);
+
+        assert_close(
+            "FORECAST.LINEAR",
+            Ok(numbers(eval("FORECAST.LINEAR(6, A1:A5, B1:B5)"))[0]),
+            numbers(eval("TREND(A1:A5, B1:B5, 6)"))[0],
+        );
+    }
+}
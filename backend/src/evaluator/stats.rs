@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+use crate::{
+    common::{LeadErr, LeadErrCode, Literal},
+    evaluator::{Eval, Precedents, utils::*},
+    grid::Grid,
+    parser::Expr,
+};
+
+fn require_len(nums: &[f64], n: usize, func_name: &str) -> Result<(), LeadErr> {
+    if nums.len() < n {
+        return Err(eval_err(
+            format!("{func_name} requires at least {n} numeric value(s)."),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    Ok(())
+}
+
+fn sorted(nums: &[f64]) -> Vec<f64> {
+    let mut res = nums.to_vec();
+    res.sort_by(|a, b| a.total_cmp(b));
+    res
+}
+
+fn mean(nums: &[f64]) -> f64 {
+    nums.iter().sum::<f64>() / nums.len() as f64
+}
+
+// Sum of squared deviations from the mean, computed in two passes for stability
+fn sum_sq_dev(nums: &[f64]) -> f64 {
+    let m = mean(nums);
+    nums.iter().map(|x| (x - m) * (x - m)).sum()
+}
+
+// -------------------------------------------------- //
+
+pub fn median(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 1, "MEDIAN")?;
+    let s = sorted(nums);
+    let mid = s.len() / 2;
+
+    if s.len().is_multiple_of(2) {
+        Ok((s[mid - 1] + s[mid]) / 2.0)
+    } else {
+        Ok(s[mid])
+    }
+}
+
+// The most frequent value, ties are broken by the first value to appear (like MODE.SNGL)
+pub fn mode(nums: &[f64]) -> Result<f64, LeadErr> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for &x in nums {
+        *counts.entry((x + 0.0).to_bits()).or_insert(0) += 1;
+    }
+
+    let mut best: Option<(f64, usize)> = None;
+    for &x in nums {
+        let count = counts[&(x + 0.0).to_bits()];
+        if count > 1 && best.is_none_or(|(_, c)| count > c) {
+            best = Some((x, count));
+        }
+    }
+
+    best.map(|(x, _)| x).ok_or(eval_err(
+        "MODE found no repeated values.".into(),
+        LeadErrCode::Invalid,
+    ))
+}
+
+pub fn var_s(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 2, "VAR.S")?;
+    Ok(sum_sq_dev(nums) / (nums.len() - 1) as f64)
+}
+
+pub fn var_p(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 1, "VAR.P")?;
+    Ok(sum_sq_dev(nums) / nums.len() as f64)
+}
+
+pub fn stdev_s(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 2, "STDEV.S")?;
+    Ok(var_s(nums)?.sqrt())
+}
+
+pub fn stdev_p(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 1, "STDEV.P")?;
+    Ok(var_p(nums)?.sqrt())
+}
+
+// Sample skewness, as computed by Excel's SKEW
+pub fn skew(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 3, "SKEW")?;
+    let n = nums.len() as f64;
+    let m = mean(nums);
+    let s = stdev_s(nums)?;
+
+    if s == 0.0 {
+        return Err(eval_err(
+            "SKEW requires values with a non-zero standard deviation.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    let sum: f64 = nums.iter().map(|x| ((x - m) / s).powi(3)).sum();
+    Ok(n / ((n - 1.0) * (n - 2.0)) * sum)
+}
+
+// Sample excess kurtosis, as computed by Excel's KURT
+pub fn kurt(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_len(nums, 4, "KURT")?;
+    let n = nums.len() as f64;
+    let m = mean(nums);
+    let s = stdev_s(nums)?;
+
+    if s == 0.0 {
+        return Err(eval_err(
+            "KURT requires values with a non-zero standard deviation.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    let sum: f64 = nums.iter().map(|x| ((x - m) / s).powi(4)).sum();
+    Ok(n * (n + 1.0) / ((n - 1.0) * (n - 2.0) * (n - 3.0)) * sum
+        - 3.0 * (n - 1.0).powi(2) / ((n - 2.0) * (n - 3.0)))
+}
+
+fn require_positive(nums: &[f64], func_name: &str) -> Result<(), LeadErr> {
+    require_len(nums, 1, func_name)?;
+    if nums.iter().any(|x| *x <= 0.0) {
+        return Err(eval_err(
+            format!("{func_name} requires positive values."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn geomean(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_positive(nums, "GEOMEAN")?;
+    Ok((nums.iter().map(|x| x.ln()).sum::<f64>() / nums.len() as f64).exp())
+}
+
+pub fn harmean(nums: &[f64]) -> Result<f64, LeadErr> {
+    require_positive(nums, "HARMEAN")?;
+    Ok(nums.len() as f64 / nums.iter().map(|x| 1.0 / x).sum::<f64>())
+}
+
+// -------------------------------------------------- //
+
+fn k_err(func_name: &str) -> LeadErr {
+    eval_err(
+        format!("{func_name} argument is out of range."),
+        LeadErrCode::Invalid,
+    )
+}
+
+// Linear interpolation at the zero-indexed fractional rank h of a sorted slice
+fn interpolate(s: &[f64], h: f64) -> f64 {
+    let lo = h.floor() as usize;
+    if lo + 1 >= s.len() {
+        return s[s.len() - 1];
+    }
+
+    s[lo] + (h - lo as f64) * (s[lo + 1] - s[lo])
+}
+
+pub fn percentile_inc(nums: &[f64], k: f64) -> Result<f64, LeadErr> {
+    require_len(nums, 1, "PERCENTILE.INC")?;
+    if !(0.0..=1.0).contains(&k) {
+        return Err(k_err("PERCENTILE.INC"));
+    }
+
+    let s = sorted(nums);
+    Ok(interpolate(&s, (s.len() - 1) as f64 * k))
+}
+
+pub fn percentile_exc(nums: &[f64], k: f64) -> Result<f64, LeadErr> {
+    require_len(nums, 1, "PERCENTILE.EXC")?;
+    let s = sorted(nums);
+    let h = (s.len() + 1) as f64 * k - 1.0;
+
+    if k <= 0.0 || k >= 1.0 || h < 0.0 || h > (s.len() - 1) as f64 {
+        return Err(k_err("PERCENTILE.EXC"));
+    }
+
+    Ok(interpolate(&s, h))
+}
+
+pub fn quartile_inc(nums: &[f64], q: f64) -> Result<f64, LeadErr> {
+    let q = q.trunc();
+    if !(0.0..=4.0).contains(&q) {
+        return Err(k_err("QUARTILE.INC"));
+    }
+
+    percentile_inc(nums, q / 4.0)
+}
+
+pub fn quartile_exc(nums: &[f64], q: f64) -> Result<f64, LeadErr> {
+    let q = q.trunc();
+    if !(1.0..=3.0).contains(&q) {
+        return Err(k_err("QUARTILE.EXC"));
+    }
+
+    percentile_exc(nums, q / 4.0)
+}
+
+// k-th largest value, a fractional k is rounded up
+pub fn large(nums: &[f64], k: f64) -> Result<f64, LeadErr> {
+    let k = k.ceil();
+    if k < 1.0 || k > nums.len() as f64 {
+        return Err(k_err("LARGE"));
+    }
+
+    Ok(sorted(nums)[nums.len() - k as usize])
+}
+
+// k-th smallest value, a fractional k is rounded up
+pub fn small(nums: &[f64], k: f64) -> Result<f64, LeadErr> {
+    let k = k.ceil();
+    if k < 1.0 || k > nums.len() as f64 {
+        return Err(k_err("SMALL"));
+    }
+
+    Ok(sorted(nums)[k as usize - 1])
+}
+
+// FUNC(array, k) where array follows the eval_numeric_func collection rules
+pub fn eval_array_k_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: fn(&[f64], f64) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let nums = collect_numbers(&args[..1], precs, grid, NonNumeric::Coerce, func_name)?;
+    let k = eval_number(&args[1], precs, grid, func_name)?;
+
+    Ok(Eval::Literal(Literal::Number(func(&nums, k)?)))
+}
+
+// RANK(number, ref, [order]), descending unless order is non-zero. Ties share the best rank.
+pub fn eval_rank(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Eval, LeadErr> {
+    let x = eval_number(&args[0], precs, grid, "RANK")?;
+    let nums = collect_numbers(&args[1..2], precs, grid, NonNumeric::Coerce, "RANK")?;
+    let ascending = match args.get(2) {
+        Some(arg) => eval_number(arg, precs, grid, "RANK")? != 0.0,
+        None => false,
+    };
+
+    if !nums.contains(&x) {
+        return Err(eval_err(
+            format!("RANK could not find {x} in the reference."),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let before = nums
+        .iter()
+        .filter(|n| if ascending { **n < x } else { **n > x })
+        .count();
+
+    Ok(Eval::Literal(Literal::Number((before + 1) as f64)))
+}
+
+// -------------------------------------------------- //
+
+fn paired_sum(xs: &[f64], ys: &[f64]) -> f64 {
+    let (mx, my) = (mean(xs), mean(ys));
+    xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum()
+}
+
+pub fn covariance_p(xs: &[f64], ys: &[f64]) -> Result<f64, LeadErr> {
+    require_len(xs, 1, "COVARIANCE.P")?;
+    Ok(paired_sum(xs, ys) / xs.len() as f64)
+}
+
+pub fn covariance_s(xs: &[f64], ys: &[f64]) -> Result<f64, LeadErr> {
+    require_len(xs, 2, "COVARIANCE.S")?;
+    Ok(paired_sum(xs, ys) / (xs.len() - 1) as f64)
+}
+
+pub fn correl(xs: &[f64], ys: &[f64]) -> Result<f64, LeadErr> {
+    require_len(xs, 2, "CORREL")?;
+    let (sx, sy) = (sum_sq_dev(xs), sum_sq_dev(ys));
+
+    if sx == 0.0 || sy == 0.0 {
+        return Err(eval_err(
+            "CORREL requires values with a non-zero standard deviation.".into(),
+            LeadErrCode::DivZero,
+        ));
+    }
+
+    Ok(paired_sum(xs, ys) / (sx * sy).sqrt())
+}
+
+// Collects two equally sized ranges into pairs of numbers. Pairs where either value is not a
+// number are skipped, like Excel does.
+pub fn eval_paired_numbers(
+    xs: &Expr,
+    ys: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<(Vec<f64>, Vec<f64>), LeadErr> {
+    let xs = eval_range_values(xs, precs, grid)?;
+    let ys = eval_range_values(ys, precs, grid)?;
+
+    if xs.len() != ys.len() {
+        return Err(eval_err(
+            format!(
+                "{func_name} requires ranges of the same size, found {} and {} cells.",
+                xs.len(),
+                ys.len()
+            ),
+            LeadErrCode::Invalid,
+        ));
+    }
+
+    let mut res = (Vec::new(), Vec::new());
+    for (x, y) in xs.into_iter().zip(ys) {
+        match (x, y) {
+            (
+                Eval::Literal(Literal::Number(x) | Literal::DateTime(x)),
+                Eval::Literal(Literal::Number(y) | Literal::DateTime(y)),
+            ) => {
+                res.0.push(x);
+                res.1.push(y);
+            }
+            (Eval::Err(e), _) | (_, Eval::Err(e)) => return Err(e),
+            _ => {}
+        }
+    }
+
+    Ok(res)
+}
+
+pub fn eval_paired_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: fn(&[f64], &[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let (xs, ys) = eval_paired_numbers(&args[0], &args[1], precs, grid, func_name)?;
+    Ok(Eval::Literal(Literal::Number(func(&xs, &ys)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values are the outputs of Excel and LibreOffice Calc on the same data
+    #[test]
+    fn test_statistics_table() {
+        let a = [
+            1345.0, 1301.0, 1368.0, 1322.0, 1310.0, 1370.0, 1318.0, 1350.0, 1303.0, 1299.0,
+        ];
+        let b = [3.0, 4.0, 5.0, 2.0, 3.0, 4.0, 5.0, 6.0, 4.0, 7.0];
+        let c = [4.0, 5.0, 8.0, 7.0, 11.0, 4.0, 3.0];
+        let d = [1.0, 2.0, 3.0, 6.0, 6.0, 6.0, 7.0, 8.0, 9.0];
+        let e = [
+            6.0, 7.0, 15.0, 36.0, 39.0, 40.0, 41.0, 42.0, 43.0, 47.0, 49.0,
+        ];
+        let f = [1.0, 2.0, 4.0, 7.0, 8.0, 9.0, 10.0, 12.0];
+
+        let table: Vec<(&str, Result<f64, LeadErr>, f64)> = vec![
+            ("MEDIAN", median(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]), 3.5),
+            ("MEDIAN", median(&[3.0, 1.0, 2.0]), 2.0),
+            ("MODE", mode(&[5.6, 4.0, 4.0, 3.0, 2.0, 4.0]), 4.0),
+            ("MODE", mode(&[1.0, 2.0, 2.0, 1.0]), 1.0),
+            ("STDEV.S", stdev_s(&a), 27.463915719843),
+            ("STDEV.P", stdev_p(&a), 26.054558142482),
+            ("VAR.S", var_s(&a), 754.266666666667),
+            ("VAR.P", var_p(&a), 678.84),
+            ("SKEW", skew(&b), 0.359543071407),
+            ("KURT", kurt(&b), -0.151799637208),
+            ("GEOMEAN", geomean(&c), 5.476986969656),
+            ("HARMEAN", harmean(&c), 5.028375962062),
+            (
+                "PERCENTILE.INC",
+                percentile_inc(&[1.0, 3.0, 2.0, 4.0], 0.3),
+                1.9,
+            ),
+            ("PERCENTILE.INC", percentile_inc(&d, 1.0), 9.0),
+            ("PERCENTILE.EXC", percentile_exc(&d, 0.25), 2.5),
+            ("PERCENTILE.EXC", percentile_exc(&d, 0.75), 7.5),
+            ("QUARTILE.INC", quartile_inc(&f, 1.0), 3.5),
+            ("QUARTILE.INC", quartile_inc(&f, 3.0), 9.25),
+            ("QUARTILE.EXC", quartile_exc(&e, 1.0), 15.0),
+            ("QUARTILE.EXC", quartile_exc(&e, 3.0), 43.0),
+            ("LARGE", large(&b, 3.0), 5.0),
+            ("SMALL", small(&b, 4.0), 4.0),
+            (
+                "CORREL",
+                correl(&[3.0, 2.0, 4.0, 5.0, 6.0], &[9.0, 7.0, 12.0, 15.0, 17.0]),
+                0.997054485502,
+            ),
+            (
+                "COVARIANCE.P",
+                covariance_p(&[3.0, 2.0, 4.0, 5.0, 6.0], &[9.0, 7.0, 12.0, 15.0, 17.0]),
+                5.2,
+            ),
+            (
+                "COVARIANCE.S",
+                covariance_s(&[2.0, 4.0, 8.0], &[5.0, 11.0, 12.0]),
+                9.666666666667,
+            ),
+        ];
+
+        for (name, res, expected) in table {
+            assert_close(name, res, expected);
+        }
+    }
+
+    #[test]
+    fn test_statistics_errors() {
+        assert_eq!(stdev_s(&[1.0]).unwrap_err().code, LeadErrCode::DivZero);
+        assert_eq!(mode(&[1.0, 2.0]).unwrap_err().code, LeadErrCode::Invalid);
+        assert_eq!(
+            geomean(&[1.0, -2.0]).unwrap_err().code,
+            LeadErrCode::Invalid
+        );
+        assert!(percentile_inc(&[1.0], 1.5).is_err());
+        assert!(percentile_exc(&[1.0, 2.0, 3.0], 0.1).is_err());
+        assert!(quartile_exc(&[1.0, 2.0, 3.0], 0.0).is_err());
+        assert!(large(&[1.0, 2.0], 3.0).is_err());
+        assert!(correl(&[1.0, 1.0], &[2.0, 3.0]).is_err());
+    }
+
+    #[test]
+    fn test_rank() {
+        use crate::evaluator::evaluate;
+
+        let grid = grid_from(&[&["7"], &["3.5"], &["3.5"], &["1"], &["2"]]);
+
+        let eval = |s: &str| evaluate(s.into(), Some(&grid)).0;
+
+        assert_eq!(eval("RANK(A3, A1:A5, 1)"), num(3.0));
+        assert_eq!(eval("RANK(A2, A1:A5)"), num(2.0));
+        assert_eq!(eval("RANK(7, A1:A5)"), num(1.0));
+        assert!(matches!(eval("RANK(8, A1:A5)"), Eval::Err(_)));
+        assert_eq!(
+            eval("STDEV.S(A1:A5)"),
+            num(stdev_s(&[7.0, 3.5, 3.5, 1.0, 2.0]).unwrap())
+        );
+        assert_eq!(eval("PERCENTILE(A1:A5, 0.5)"), num(3.5));
+    }
+}
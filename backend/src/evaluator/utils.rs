@@ -1,107 +1,334 @@
-use std::collections::HashSet;
+use std::cmp::Ordering;
 
 use crate::{
-    cell::CellRef,
     common::{LeadErr, LeadErrCode, Literal},
-    evaluator::{Eval, evaluate_expr},
+    evaluator::{Array, Eval, Precedents, datetime::parse_datetime, evaluate_expr},
     grid::Grid,
     parser::Expr,
 };
 
+#[cfg(test)]
+use crate::cell::CellRef;
 
+// Evaluates a function of a fixed list of scalar numeric arguments. The first `required`
+// arguments are always given, the registry checks the count, the remaining ones are optional
+// and take their value from `defaults` when omitted. Arguments are coerced like eval_number.
 pub fn eval_n_arg_numeric(
-    n: usize,
-    args: &Vec<Expr>,
-    precs: &mut HashSet<CellRef>,
+    required: usize,
+    defaults: &[f64],
+    args: &[Expr],
+    precs: &mut Precedents,
     grid: Option<&Grid>,
-    func: fn(Vec<f64>) -> f64,
-    func_name: String,
+    func: fn(&[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
 ) -> Result<Eval, LeadErr> {
-    if args.len() != n {
-        return Err(LeadErr {
-            title: "Evaluation error.".into(),
-            desc: format!("{func_name} function requires {n} argument(s)."),
-            code: LeadErrCode::Invalid,
-        });
+    let mut numbers = Vec::with_capacity(required + defaults.len());
+
+    for arg in args {
+        numbers.push(eval_number(arg, precs, grid, func_name)?);
     }
+    numbers.extend_from_slice(&defaults[args.len() - required..]);
 
-    let err = LeadErr {
-        title: "Evaluation error.".into(),
-        desc: format!("{func_name} function requires numeric argument(s)."),
-        code: LeadErrCode::TypeErr,
-    };
+    Ok(Eval::Literal(Literal::Number(func(&numbers)?)))
+}
 
-    let mut numbers = Vec::with_capacity(n);
+// How a function treats values that are not numbers. Like Excel, values read through a
+// reference (a range or a single cell) are treated differently to values passed directly:
+//
+// Function                  | Referenced values            | Direct arguments
+// --------------------------|------------------------------|------------------------------------
+// SUM, AVG, MIN, MAX, PROD  | numbers only, rest skipped   | numbers, booleans (1/0) and numeric
+// and the IF/IFS family     | errors are propagated        | text, any other text is an error
+// COUNT                     | numbers only, rest skipped   | numbers, booleans and numeric text,
+//                           | errors are skipped           | anything else is skipped
+// COUNTA                    | any non-blank value          | any value
+// COUNTBLANK                | blanks and empty strings     | blanks and empty strings
+// COUNTUNIQUE               | distinct non-blank values    | distinct non-blank values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonNumeric {
+    // Coerce direct arguments and reject anything that can't be coerced
+    Coerce,
+    // Coerce direct arguments where possible and skip everything else
+    Skip,
+}
 
-    for arg in args {
-        match evaluate_expr(arg, precs, grid)? {
-            Eval::Literal(Literal::Number(num)) => numbers.push(num),
-            Eval::CellRef { eval, .. } => match *eval {
-                Eval::Literal(Literal::Number(num)) => numbers.push(num),
-                _ => return Err(err.clone()),
-            },
-            _ => return Err(err.clone()),
-        }
+// Text is numeric when it parses as a finite number or a date, so "nan" and "inf" are text
+pub fn coerce_number(lit: &Literal) -> Option<f64> {
+    match lit {
+        Literal::Number(n) | Literal::DateTime(n) => Some(*n),
+        Literal::Boolean(b) => Some(if *b { 1.0 } else { 0.0 }),
+        Literal::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .or(parse_datetime(s)),
     }
-
-    Ok(Eval::Literal(Literal::Number(func(numbers))))
 }
 
-// This is a utility function that filters out and error handles all non literal numbers or unset
-// eval types and handles ranges
-pub fn eval_numeric_func(
-    args: &Vec<Expr>,
-    precs: &mut HashSet<CellRef>,
+// Collects the numbers of the arguments following the given NonNumeric policy
+pub fn collect_numbers(
+    args: &[Expr],
+    precs: &mut Precedents,
     grid: Option<&Grid>,
-    func: impl Fn(&[f64]) -> Result<f64, LeadErr>,
+    policy: NonNumeric,
     func_name: &str,
-) -> Result<Eval, LeadErr> {
+) -> Result<Vec<f64>, LeadErr> {
     let mut numbers = Vec::new();
 
     for arg in args {
-        let eval = evaluate_expr(arg, precs, grid)?;
-
-        match eval {
-            Eval::Literal(Literal::Number(n)) => numbers.push(n),
-            Eval::Unset => {} // skip
-            Eval::Range(range) => {
-                for cell in range {
-                    match cell {
-                        Eval::CellRef { eval: boxed, .. } => match *boxed {
-                            Eval::Literal(Literal::Number(n)) => numbers.push(n),
-                            Eval::Unset => {}
-                            _ => {
-                                return Err(LeadErr {
-                                    title: "Evaluation error.".into(),
-                                    desc: format!(
-                                        "Expected numeric types for {func_name} function."
-                                    ),
-                                    code: LeadErrCode::Unsupported,
-                                });
-                            }
-                        },
-                        _ => {
-                            return Err(LeadErr {
-                                title: "Evaluation error.".into(),
-                                desc: format!(
-                                    "Found non-cellref in RANGE during {func_name} evaluation."
-                                ),
-                                code: LeadErrCode::Server,
-                            });
-                        }
+        let referenced = match evaluate_expr(arg, precs, grid)? {
+            Eval::Range(range) => range.values,
+            it @ Eval::CellRef { .. } => vec![it],
+            Eval::Literal(lit) => {
+                match (coerce_number(&lit), policy) {
+                    (Some(n), _) => numbers.push(n),
+                    (None, NonNumeric::Skip) => {}
+                    (None, NonNumeric::Coerce) => {
+                        return Err(LeadErr {
+                            title: "Evaluation error.".into(),
+                            desc: format!("Expected numeric types for {func_name} function."),
+                            code: LeadErrCode::TypeErr,
+                        });
                     }
                 }
+                continue;
             }
-            _ => {
-                return Err(LeadErr {
-                    title: "Evaluation error.".into(),
-                    desc: format!("Expected numeric types for {func_name} function."),
-                    code: LeadErrCode::Unsupported,
-                });
+            Eval::Err(e) => return Err(e),
+            Eval::Unset => continue,
+        };
+
+        for cell in referenced {
+            let value = match cell {
+                Eval::CellRef { eval, .. } => *eval,
+                it => it,
+            };
+
+            match value {
+                Eval::Literal(Literal::Number(n) | Literal::DateTime(n)) => numbers.push(n),
+                Eval::Err(e) if policy == NonNumeric::Coerce => return Err(e),
+                _ => {} // skip text, booleans and blanks
             }
         }
     }
 
+    Ok(numbers)
+}
+
+// This is a utility function that collects the numbers of all arguments, expanding ranges, and
+// applies func to them. Non numeric values follow the NonNumeric::Coerce policy.
+pub fn eval_numeric_func(
+    args: &[Expr],
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func: impl Fn(&[f64]) -> Result<f64, LeadErr>,
+    func_name: &str,
+) -> Result<Eval, LeadErr> {
+    let numbers = collect_numbers(args, precs, grid, NonNumeric::Coerce, func_name)?;
+
     let res = func(&numbers)?;
     Ok(Eval::Literal(Literal::Number(res)))
 }
+
+// Evaluates an argument into the values of its cells, a single cell or value becomes a one
+// element list
+pub fn eval_range_values(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Vec<Eval>, LeadErr> {
+    let values = match evaluate_expr(arg, precs, grid)? {
+        Eval::Range(range) => range.unwrapped().values,
+        Eval::CellRef { eval, .. } => vec![*eval],
+        it => vec![it],
+    };
+
+    Ok(values)
+}
+
+pub fn average(nums: &[f64]) -> Result<f64, LeadErr> {
+    if nums.is_empty() {
+        Err(LeadErr {
+            title: "Evaluation error.".into(),
+            desc: "Attempted to divide by zero.".into(),
+            code: LeadErrCode::DivZero,
+        })
+    } else {
+        Ok(nums.iter().sum::<f64>() / nums.len() as f64)
+    }
+}
+
+// Evaluates a single scalar argument to a number. Booleans and numeric text are coerced.
+pub fn eval_number(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+    func_name: &str,
+) -> Result<f64, LeadErr> {
+    let value = match evaluate_expr(arg, precs, grid)? {
+        Eval::CellRef { eval, .. } => *eval,
+        it => it,
+    };
+
+    match value {
+        Eval::Literal(lit) => coerce_number(&lit),
+        Eval::Unset => Some(0.0),
+        Eval::Err(e) => return Err(e),
+        Eval::Range(_) | Eval::CellRef { .. } => None,
+    }
+    .ok_or(LeadErr {
+        title: "Evaluation error.".into(),
+        desc: format!("{func_name} function requires numeric argument(s)."),
+        code: LeadErrCode::TypeErr,
+    })
+}
+
+// Evaluates an argument into its cell values along with its (rows, cols) shape. Values that
+// are not ranges are treated as a single column.
+pub fn eval_range_shape(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<(usize, usize, Vec<Eval>), LeadErr> {
+    match evaluate_expr(arg, precs, grid)? {
+        Eval::Range(range) => {
+            let range = range.unwrapped();
+            Ok((range.rows, range.cols, range.values))
+        }
+        Eval::CellRef { eval, .. } => Ok((1, 1, vec![*eval])),
+        it => Ok((1, 1, vec![it])),
+    }
+}
+
+// Evaluates an argument into an array of plain values, a single value becomes a 1x1 array
+pub fn eval_array(
+    arg: &Expr,
+    precs: &mut Precedents,
+    grid: Option<&Grid>,
+) -> Result<Array, LeadErr> {
+    match evaluate_expr(arg, precs, grid)? {
+        Eval::Range(range) => Ok(range.unwrapped()),
+        Eval::CellRef { eval, .. } => Ok(Array::new(1, 1, vec![*eval])),
+        Eval::Err(e) => Err(e),
+        it => Ok(Array::new(1, 1, vec![it])),
+    }
+}
+
+// Order of values used for sorting and comparisons: numbers, then text (case-insensitive),
+// booleans and errors
+pub fn compare_values(a: &Eval, b: &Eval) -> Ordering {
+    fn rank(value: &Eval) -> u8 {
+        match value {
+            Eval::Literal(Literal::Number(_) | Literal::DateTime(_)) => 0,
+            Eval::Literal(Literal::String(_)) => 1,
+            Eval::Literal(Literal::Boolean(_)) => 2,
+            _ => 3,
+        }
+    }
+
+    match (a, b) {
+        (
+            Eval::Literal(Literal::Number(x) | Literal::DateTime(x)),
+            Eval::Literal(Literal::Number(y) | Literal::DateTime(y)),
+        ) => x.total_cmp(y),
+        (Eval::Literal(Literal::String(x)), Eval::Literal(Literal::String(y))) => {
+            x.to_lowercase().cmp(&y.to_lowercase())
+        }
+        (Eval::Literal(Literal::Boolean(x)), Eval::Literal(Literal::Boolean(y))) => x.cmp(y),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+// Value of the cells an array has no value for, e.g. padding when stacking arrays of different
+// sizes
+pub fn not_available() -> Eval {
+    Eval::Err(LeadErr {
+        title: "Evaluation error.".into(),
+        desc: "No value available.".into(),
+        code: LeadErrCode::NotAvailable,
+    })
+}
+
+pub fn eval_err(desc: String, code: LeadErrCode) -> LeadErr {
+    LeadErr {
+        title: "Evaluation error.".into(),
+        desc,
+        code,
+    }
+}
+
+// Largest array a function or operator may build, the number of rows in an Excel sheet
+pub const MAX_CELLS: usize = 1 << 20;
+
+// Rejects an array of rows x cols before it is built. The product is taken as u128 so it can't
+// overflow.
+pub fn check_cells(rows: usize, cols: usize, name: &str) -> Result<(), LeadErr> {
+    if (rows as u128) * (cols as u128) > MAX_CELLS as u128 {
+        return Err(eval_err(
+            format!("{name} of {rows}x{cols} is too large."),
+            LeadErrCode::Invalid,
+        ));
+    }
+    Ok(())
+}
+
+// -------------------------------------------------- //
+
+// Relative tolerance of the test helpers, reference values are given to 12 significant digits
+#[cfg(test)]
+const TOL: f64 = 1e-9;
+
+#[cfg(test)]
+pub fn assert_close(name: &str, res: Result<f64, LeadErr>, expected: f64) {
+    let res = res.unwrap_or_else(|e| panic!("{name} failed: {e:?}"));
+    assert!(
+        (res - expected).abs() <= TOL * expected.abs().max(1.0),
+        "{name}: expected {expected}, got {res}"
+    );
+}
+
+#[cfg(test)]
+pub fn is_err(eval: Eval, code: LeadErrCode) -> bool {
+    matches!(eval, Eval::Err(e) if e.code == code)
+}
+
+#[cfg(test)]
+pub fn num(n: f64) -> Eval {
+    Eval::Literal(Literal::Number(n))
+}
+
+#[cfg(test)]
+pub fn at(s: &str) -> CellRef {
+    CellRef::new(s.into()).unwrap()
+}
+
+// The value of a cell, blank when it is not stored
+#[cfg(test)]
+pub fn value(grid: &Grid, s: &str) -> Eval {
+    match grid.get_cell(at(s)).map(|it| it.eval()) {
+        Ok(Eval::CellRef { eval, .. }) => *eval,
+        Ok(it) => it,
+        Err(_) => Eval::Unset,
+    }
+}
+
+// Sets the raw text of the given cells in order
+#[cfg(test)]
+pub fn set_cells(grid: &mut Grid, cells: &[(&str, &str)]) {
+    for (cell, raw) in cells {
+        grid.update_cell(at(cell), raw.to_string()).unwrap();
+    }
+}
+
+// A grid holding the rows of raw text from A1, empty text leaves the cell blank
+#[cfg(test)]
+pub fn grid_from(rows: &[&[&str]]) -> Grid {
+    let mut grid = Grid::new();
+    for (row, raws) in rows.iter().enumerate() {
+        for (col, raw) in raws.iter().enumerate() {
+            let cell_ref = CellRef { row, col };
+            grid.update_cell(cell_ref, raw.to_string()).unwrap();
+        }
+    }
+    grid
+}
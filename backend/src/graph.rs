@@ -0,0 +1,495 @@
+use std::collections::{HashMap, HashSet, VecDeque, hash_map::Entry};
+
+use crate::{
+    cell::{CellRange, CellRef},
+    evaluator::Precedents,
+};
+
+// The level of cells in or downstream of a circular reference
+pub const CYCLIC: usize = usize::MAX;
+
+// A circular reference as "A1 → B3 → A1"
+pub fn format_cycle(cycle: &[CellRef]) -> String {
+    cycle
+        .iter()
+        .chain(&cycle[..1.min(cycle.len())])
+        .map(|it| it.to_string())
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+// The edges between cells, kept apart from the cells so that reading a value does not copy them.
+// Every cell also has a level, one more than the highest level of its precedents, and dirty
+// cells are recalculated in increasing level. Cells without edges are not stored.
+#[derive(Debug, Default)]
+pub struct DepGraph {
+    precedents: HashMap<CellRef, Precedents>,
+    // Cells read on their own, readers of ranges are in the range index
+    dependents: HashMap<CellRef, HashSet<CellRef>>,
+    ranges: RangeIndex,
+    // Only levels above 0 are stored
+    levels: HashMap<CellRef, usize>,
+}
+
+impl DepGraph {
+    pub fn precedents(&self, cell: CellRef) -> Option<&Precedents> {
+        self.precedents.get(&cell)
+    }
+
+    // The cells reading cell, on its own or in a range
+    pub fn dependents(&self, cell: CellRef) -> impl Iterator<Item = CellRef> + '_ {
+        self.dependents
+            .get(&cell)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(self.ranges.dependents(cell))
+    }
+
+    pub fn level(&self, cell: CellRef) -> usize {
+        self.levels.get(&cell).cloned().unwrap_or(0)
+    }
+
+    // Replaces the precedents of a cell, returns whether they changed
+    pub fn set_precedents(&mut self, cell: CellRef, precs: Precedents) -> bool {
+        let old = self.precedents.remove(&cell).unwrap_or_default();
+        if old == precs {
+            if !precs.is_empty() {
+                self.precedents.insert(cell, precs);
+            }
+            return false;
+        }
+
+        for p in old.cells().difference(precs.cells()) {
+            self.remove_dependent(*p, cell);
+        }
+        for p in precs.cells().difference(old.cells()) {
+            self.add_dependent(*p, cell);
+        }
+        for range in old.ranges().difference(precs.ranges()) {
+            self.ranges.remove(*range, cell);
+        }
+        for range in precs.ranges().difference(old.ranges()) {
+            self.ranges.insert(*range, cell);
+        }
+
+        if !precs.is_empty() {
+            self.precedents.insert(cell, precs);
+        }
+        self.relevel(cell);
+        true
+    }
+
+    // An edge from prec to cell on top of the precedents of the formula, e.g. from an anchor to
+    // the cells it spilled into
+    pub fn insert_edge(&mut self, prec: CellRef, cell: CellRef) {
+        self.precedents.entry(cell).or_default().insert(prec);
+        self.add_dependent(prec, cell);
+        self.relevel(cell);
+    }
+
+    pub fn remove_edge(&mut self, prec: CellRef, cell: CellRef) {
+        if let Some(precs) = self.precedents.get_mut(&cell) {
+            precs.remove(&prec);
+            if precs.is_empty() {
+                self.precedents.remove(&cell);
+            }
+        }
+        self.remove_dependent(prec, cell);
+        self.relevel(cell);
+    }
+
+    // Recalculates cell whenever prec changes without cell reading it, e.g. an anchor whose
+    // spill is blocked by prec
+    pub fn add_dependent(&mut self, prec: CellRef, cell: CellRef) {
+        self.dependents.entry(prec).or_default().insert(cell);
+    }
+
+    pub fn remove_dependent(&mut self, prec: CellRef, cell: CellRef) {
+        if let Some(deps) = self.dependents.get_mut(&prec) {
+            deps.remove(&cell);
+            if deps.is_empty() {
+                self.dependents.remove(&prec);
+            }
+        }
+    }
+
+    // Updates the levels of a cell whose precedents changed and of its dependents. A new cycle
+    // can only go through the cell, which is then found directly and marked CYCLIC along with
+    // everything downstream of it.
+    fn relevel(&mut self, from: CellRef) {
+        let old = self.level(from);
+        let level = self.precedents_level(from);
+        if level == old {
+            return;
+        }
+        self.set_level(from, level);
+        if level != CYCLIC && old != CYCLIC && level > old && self.on_cycle(from) {
+            self.set_level(from, CYCLIC);
+        }
+
+        let mut stack: Vec<CellRef> = self.dependents(from).collect();
+        while let Some(cell) = stack.pop() {
+            let level = self.precedents_level(cell);
+            if level != self.level(cell) {
+                stack.extend(self.dependents(cell));
+                self.set_level(cell, level);
+            }
+        }
+    }
+
+    // One more than the highest level of the precedents of cell, CYCLIC saturates
+    fn precedents_level(&self, cell: CellRef) -> usize {
+        self.precedents.get(&cell).map_or(0, |precs| {
+            precs
+                .cells()
+                .iter()
+                .map(|it| self.level(*it))
+                .chain(precs.ranges().iter().map(|it| self.range_level(it)))
+                .map(|it| it.saturating_add(1))
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    fn set_level(&mut self, cell: CellRef, level: usize) {
+        if level == 0 {
+            self.levels.remove(&cell);
+        } else {
+            self.levels.insert(cell, level);
+        }
+    }
+
+    // Whether cell reads itself. Its new level is above every other cell of a cycle closed by its
+    // new precedents, whose levels are still those of before, so only lower cells are searched.
+    fn on_cycle(&self, cell: CellRef) -> bool {
+        let level = self.level(cell);
+        let mut seen = HashSet::new();
+        let mut stack = vec![cell];
+
+        while let Some(current) = stack.pop() {
+            for dep in self.dependents(current) {
+                // Dependents that don't read current, e.g. blocked anchors, have no level from it
+                if !self
+                    .precedents
+                    .get(&dep)
+                    .is_some_and(|it| it.contains(&current))
+                {
+                    continue;
+                }
+                if dep == cell {
+                    return true;
+                }
+                if self.level(dep) < level && seen.insert(dep) {
+                    stack.push(dep);
+                }
+            }
+        }
+        false
+    }
+
+    // Whether cell reads any of cells, directly or through other cells. Only cells on a lower
+    // level can be read, which prunes the search unless cell is in a cycle.
+    pub fn reads_any(&self, cell: CellRef, cells: impl IntoIterator<Item = CellRef>) -> bool {
+        let level = self.level(cell);
+        let below = |it: CellRef| level == CYCLIC || self.level(it) < level;
+
+        let mut seen = HashSet::new();
+        let mut stack: Vec<CellRef> = cells.into_iter().filter(|it| below(*it)).collect();
+        while let Some(current) = stack.pop() {
+            if !seen.insert(current) {
+                continue;
+            }
+            for dep in self.dependents(current) {
+                if dep == cell {
+                    return true;
+                }
+                if below(dep) {
+                    stack.push(dep);
+                }
+            }
+        }
+        false
+    }
+
+    // A shortest circular reference through cell, each cell reading the next and the last one
+    // reading cell again
+    pub fn cycle_through(&self, cell: CellRef) -> Option<Vec<CellRef>> {
+        let mut parents: HashMap<CellRef, CellRef> = HashMap::new();
+        let mut queue = VecDeque::from([cell]);
+
+        while let Some(current) = queue.pop_front() {
+            for prec in self.cyclic_precedents(current) {
+                if prec == cell {
+                    let mut path = vec![current];
+                    while let Some(parent) = parents.get(path.last().unwrap()) {
+                        path.push(*parent);
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if let Entry::Vacant(it) = parents.entry(prec) {
+                    it.insert(current);
+                    queue.push_back(prec);
+                }
+            }
+        }
+        None
+    }
+
+    // The nearest cell in or downstream of a cycle read by cell, directly or not, for which
+    // found holds
+    pub fn find_upstream(
+        &self,
+        cell: CellRef,
+        mut found: impl FnMut(CellRef) -> bool,
+    ) -> Option<CellRef> {
+        let mut seen = HashSet::from([cell]);
+        let mut queue = VecDeque::from([cell]);
+
+        while let Some(current) = queue.pop_front() {
+            for prec in self.cyclic_precedents(current) {
+                if !seen.insert(prec) {
+                    continue;
+                }
+                if found(prec) {
+                    return Some(prec);
+                }
+                queue.push_back(prec);
+            }
+        }
+        None
+    }
+
+    // The cells read by cell that are in or downstream of a cycle
+    fn cyclic_precedents(&self, cell: CellRef) -> Vec<CellRef> {
+        let Some(precs) = self.precedents.get(&cell) else {
+            return Vec::new();
+        };
+        let is_cyclic = |it: &CellRef| self.level(*it) == CYCLIC;
+
+        let mut res: Vec<CellRef> = precs.cells().iter().cloned().filter(is_cyclic).collect();
+        for range in precs.ranges() {
+            if range.len() < self.levels.len() {
+                res.extend(range.cells().filter(is_cyclic));
+            } else {
+                res.extend(
+                    self.levels
+                        .keys()
+                        .filter(|it| range.contains(**it) && is_cyclic(it)),
+                );
+            }
+        }
+        // In order so that the same cycle is found every time
+        res.sort_by_key(|it| (it.row, it.col));
+        res.dedup();
+        res
+    }
+
+    // The highest level of the cells in a range
+    fn range_level(&self, range: &CellRange) -> usize {
+        if range.len() < self.levels.len() {
+            range.cells().map(|it| self.level(it)).max().unwrap_or(0)
+        } else {
+            self.levels
+                .iter()
+                .filter(|(it, _)| range.contains(**it))
+                .map(|(_, level)| *level)
+                .max()
+                .unwrap_or(0)
+        }
+    }
+}
+
+// Ranges read by formulas and the formulas reading them. Every range is stored once, in the
+// smallest block containing it out of the blocks of 1, 2, 4, ... rows aligned to multiples of
+// their size, and likewise for columns. The ranges containing a cell are then in one of the
+// blocks containing the cell, one per pair of block sizes in use.
+#[derive(Debug, Default)]
+pub struct RangeIndex {
+    blocks: HashMap<Block, HashMap<CellRange, HashSet<CellRef>>>,
+    // Number of ranges stored at each (row level, column level), a block at level k has 2^k
+    // rows or columns
+    levels: HashMap<(u32, u32), usize>,
+}
+
+// (row level, row block, column level, column block)
+type Block = (u32, usize, u32, usize);
+
+// The smallest k such that start and end are in the same aligned block of 2^k
+fn level(start: usize, end: usize) -> u32 {
+    usize::BITS - (start ^ end).leading_zeros()
+}
+
+fn block_of(index: usize, level: u32) -> usize {
+    index.checked_shr(level).unwrap_or(0)
+}
+
+fn block(range: &CellRange) -> Block {
+    let row_level = level(range.start.row, range.end.row);
+    let col_level = level(range.start.col, range.end.col);
+    (
+        row_level,
+        block_of(range.start.row, row_level),
+        col_level,
+        block_of(range.start.col, col_level),
+    )
+}
+
+impl RangeIndex {
+    pub fn insert(&mut self, range: CellRange, dependent: CellRef) {
+        let (row_level, _, col_level, _) = block(&range);
+        let ranges = self.blocks.entry(block(&range)).or_default();
+
+        if !ranges.contains_key(&range) {
+            *self.levels.entry((row_level, col_level)).or_default() += 1;
+        }
+        ranges.entry(range).or_default().insert(dependent);
+    }
+
+    pub fn remove(&mut self, range: CellRange, dependent: CellRef) {
+        let key = block(&range);
+        let Some(ranges) = self.blocks.get_mut(&key) else {
+            return;
+        };
+        let Some(dependents) = ranges.get_mut(&range) else {
+            return;
+        };
+
+        dependents.remove(&dependent);
+        if !dependents.is_empty() {
+            return;
+        }
+
+        ranges.remove(&range);
+        if ranges.is_empty() {
+            self.blocks.remove(&key);
+        }
+
+        let (row_level, _, col_level, _) = key;
+        if let Some(count) = self.levels.get_mut(&(row_level, col_level)) {
+            *count -= 1;
+            if *count == 0 {
+                self.levels.remove(&(row_level, col_level));
+            }
+        }
+    }
+
+    // The cells reading a range containing cell
+    pub fn dependents(&self, cell: CellRef) -> impl Iterator<Item = CellRef> + '_ {
+        self.levels
+            .keys()
+            .filter_map(move |&(row_level, col_level)| {
+                self.blocks.get(&(
+                    row_level,
+                    block_of(cell.row, row_level),
+                    col_level,
+                    block_of(cell.col, col_level),
+                ))
+            })
+            .flatten()
+            .filter(move |(range, _)| range.contains(cell))
+            .flat_map(|(_, dependents)| dependents.iter().cloned())
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.blocks.values().map(|it| it.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> CellRef {
+        CellRef::new(s.into()).unwrap()
+    }
+
+    fn range(s: &str) -> CellRange {
+        let (a, b) = s.split_once(':').unwrap();
+        CellRange::new(at(a), at(b))
+    }
+
+    fn dependents(index: &RangeIndex, s: &str) -> Vec<CellRef> {
+        let mut res: Vec<CellRef> = index.dependents(at(s)).collect();
+        res.sort_by_key(|it| (it.row, it.col));
+        res
+    }
+
+    #[test]
+    fn test_range_index() {
+        let mut index = RangeIndex::default();
+        index.insert(range("A1:A100000"), at("B1"));
+        index.insert(range("A1:A100000"), at("B2"));
+        index.insert(range("A1024:C1025"), at("B3"));
+        index.insert(range("C3:A1"), at("B4"));
+        assert_eq!(index.len(), 3);
+
+        assert_eq!(dependents(&index, "A1"), [at("B1"), at("B2"), at("B4")]);
+        assert_eq!(dependents(&index, "A99999"), [at("B1"), at("B2")]);
+        assert_eq!(dependents(&index, "A1025"), [at("B1"), at("B2"), at("B3")]);
+        assert_eq!(dependents(&index, "C1024"), [at("B3")]);
+        assert_eq!(dependents(&index, "C3"), [at("B4")]);
+        assert!(dependents(&index, "A100001").is_empty());
+        assert!(dependents(&index, "D1").is_empty());
+
+        index.remove(range("A1:A100000"), at("B1"));
+        assert_eq!(dependents(&index, "A5"), [at("B2")]);
+        index.remove(range("A1:A100000"), at("B2"));
+        index.remove(range("A1024:C1025"), at("B3"));
+        assert!(dependents(&index, "A1025").is_empty());
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.levels.len(), 1);
+    }
+
+    #[test]
+    fn test_levels() {
+        let precs = |cells: &[CellRef]| {
+            let mut precs = Precedents::new();
+            for cell in cells {
+                precs.insert(*cell);
+            }
+            precs
+        };
+        let mut graph = DepGraph::default();
+
+        // A long chain below A2, B1 reads A2 and every other B cell the one above it
+        graph.set_precedents(at("A2"), precs(&[at("A1")]));
+        graph.set_precedents(at("B1"), precs(&[at("A2")]));
+        for row in 1..5000 {
+            graph.set_precedents(
+                CellRef { row, col: 1 },
+                precs(&[CellRef {
+                    row: row - 1,
+                    col: 1,
+                }]),
+            );
+        }
+        assert_eq!(graph.level(at("B5000")), 5001);
+
+        // Closing a cycle above the chain marks it and everything below it at once
+        graph.set_precedents(at("A1"), precs(&[at("A2")]));
+        for cell in ["A1", "A2", "B1", "B5000"] {
+            assert_eq!(graph.level(at(cell)), CYCLIC, "{cell}");
+        }
+        assert_eq!(
+            graph.cycle_through(at("A1")),
+            Some(vec![at("A1"), at("A2")])
+        );
+
+        graph.set_precedents(at("A1"), Precedents::new());
+        assert_eq!(graph.level(at("A2")), 1);
+        assert_eq!(graph.level(at("B5000")), 5001);
+
+        // Reading itself, directly or through a range
+        graph.set_precedents(at("C1"), precs(&[at("C1")]));
+        assert_eq!(graph.level(at("C1")), CYCLIC);
+        let mut range = Precedents::new();
+        range.insert_range(CellRange::new(at("D1"), at("D2")));
+        graph.set_precedents(at("D2"), range);
+        assert_eq!(graph.level(at("D2")), CYCLIC);
+        graph.set_precedents(at("D2"), Precedents::new());
+        assert_eq!(graph.level(at("D2")), 0);
+    }
+}
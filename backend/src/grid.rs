@@ -1,79 +1,786 @@
-use std::collections::{HashMap, HashSet};
-
-use log::info;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+    sync::Mutex,
+    thread,
+};
 
 use crate::{
-    cell::{Cell, CellRef},
+    cell::{Cell, CellRange, CellRef, Spill},
     common::{LeadErr, LeadErrCode, Literal},
-    evaluator::{Eval, evaluate, evaluate_literal},
+    evaluator::{
+        Clock, Eval, FunctionRegistry, Precedents, RegexCache, Rng, SystemClock, evaluate,
+        evaluate_literal,
+    },
+    graph::{CYCLIC, DepGraph, format_cycle},
+    messages::EvalConfig,
+    shift::Shift,
 };
 
 pub struct Grid {
     cells: HashMap<CellRef, Cell>,
+    clock: Box<dyn Clock>,
+    functions: FunctionRegistry,
+    regexes: RegexCache,
+    rng: Mutex<Rng>,
+    // Cells whose formula calls a volatile function, recalculated after every edit
+    volatile: HashSet<CellRef>,
+    // Precedents, dependents and recalculation order of the cells
+    graph: DepGraph,
+    // Cells waiting to be recalculated
+    dirty: HashSet<CellRef>,
+    // Cells out of date because a precedent changed while propagation was off
+    stale: HashSet<CellRef>,
+    // Evaluates circular references repeatedly instead of failing when set
+    iteration: Option<Iteration>,
+    // The rounds of iteration made by the last recalculation
+    iterations: Option<Iterations>,
+    // Circular references met by the last recalculation, and for the cells in or downstream of
+    // them the index of their cycle and whether they are part of it
+    cycles: Vec<Vec<CellRef>>,
+    cycle_of: HashMap<CellRef, (usize, bool)>,
+    // Levels with at least this many dirty cells are evaluated on worker threads
+    parallel_threshold: usize,
+    // Threads evaluating such a level, one per core
+    workers: usize,
+}
+
+// The tolerance of iteration when a request only gives the number of iterations
+const DEFAULT_TOLERANCE: f64 = 0.001;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Iteration {
+    pub max_iterations: usize,
+    pub tolerance: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Iterations {
+    Converged(usize),
+    NotConverged(usize),
 }
 
 impl Grid {
     pub fn new() -> Grid {
-        return Grid {
+        Grid {
             cells: HashMap::new(),
-        };
+            clock: Box::new(SystemClock),
+            functions: FunctionRegistry::builtin().clone(),
+            regexes: RegexCache::default(),
+            rng: Mutex::new(Rng::from_time()),
+            volatile: HashSet::new(),
+            graph: DepGraph::default(),
+            dirty: HashSet::new(),
+            stale: HashSet::new(),
+            iteration: None,
+            iterations: None,
+            cycles: Vec::new(),
+            cycle_of: HashMap::new(),
+            parallel_threshold: 1024,
+            workers: thread::available_parallelism().map_or(1, |it| it.get()),
+        }
+    }
+
+    // TODAY and NOW read the time from the given clock
+    #[allow(dead_code)]
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Grid {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    // Replaces the functions available to formulas, start from FunctionRegistry::builtin() to
+    // add to the built-in functions
+    #[allow(dead_code)]
+    pub fn with_functions(mut self, functions: FunctionRegistry) -> Grid {
+        self.functions = functions;
+        self
+    }
+
+    // Seeds the random numbers of RAND and friends, e.g. to reproduce a shared session
+    #[allow(dead_code)]
+    pub fn with_seed(mut self, seed: u64) -> Grid {
+        self.seed(seed);
+        self
+    }
+
+    // The number of dirty cells of a level from which they are evaluated on worker threads,
+    // usize::MAX to always recalculate on the calling thread
+    #[allow(dead_code)]
+    pub fn with_parallel_threshold(mut self, threshold: usize) -> Grid {
+        self.set_parallel_threshold(threshold);
+        self
+    }
+
+    // Evaluates circular references up to max_iterations times, until no value moves by more
+    // than tolerance. No iterations leave circular references as errors.
+    #[allow(dead_code)]
+    pub fn with_iteration(mut self, max_iterations: usize, tolerance: f64) -> Grid {
+        self.set_iteration(max_iterations, tolerance);
+        self
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Mutex::new(Rng::new(seed));
+    }
+
+    pub fn set_parallel_threshold(&mut self, threshold: usize) {
+        self.parallel_threshold = threshold.max(1);
+    }
+
+    pub fn set_iteration(&mut self, max_iterations: usize, tolerance: f64) {
+        self.iteration = (max_iterations > 0).then_some(Iteration {
+            max_iterations,
+            tolerance,
+        });
     }
 
+    // Applies the settings of a request, those left out keep their value. Circular references
+    // are recalculated by the next evaluation when iteration changes.
+    pub fn configure(&mut self, config: &EvalConfig) {
+        if let Some(threshold) = config.parallel_threshold {
+            self.set_parallel_threshold(threshold);
+        }
+        if config.max_iterations.is_some() || config.tolerance.is_some() {
+            let current = self.iteration;
+            let max_iterations = config
+                .max_iterations
+                .or(current.map(|it| it.max_iterations))
+                .unwrap_or(0);
+            let tolerance = config
+                .tolerance
+                .or(current.map(|it| it.tolerance))
+                .unwrap_or(DEFAULT_TOLERANCE);
+            self.set_iteration(max_iterations, tolerance);
+
+            if self.iteration != current {
+                let cyclic: Vec<CellRef> = (self.cells.keys())
+                    .filter(|it| self.graph.level(**it) == CYCLIC)
+                    .cloned()
+                    .collect();
+                for cell_ref in cyclic {
+                    // Iterating starts over from blank cells rather than the circular errors
+                    if current.is_none() {
+                        self.set_result(cell_ref, Eval::Unset);
+                    }
+                    self.dirty.insert(cell_ref);
+                }
+            }
+        }
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn functions(&self) -> &FunctionRegistry {
+        &self.functions
+    }
+
+    // Compiled patterns of the regex functions
+    pub fn regexes(&self) -> &RegexCache {
+        &self.regexes
+    }
+
+    pub fn rng(&self) -> &Mutex<Rng> {
+        &self.rng
+    }
+
+    #[allow(dead_code)]
     pub fn update_cell(
         &mut self,
         cell_ref: CellRef,
         raw_val: String,
     ) -> Result<Vec<CellRef>, String> {
-        if self.cells.contains_key(&cell_ref) && self.cells[&cell_ref].raw() == raw_val {
-            return Ok(Vec::new());
+        self.update_cell_with(cell_ref, raw_val, &EvalConfig::default())
+    }
+
+    // Sets the raw text of a cell and evaluates it. Without propagation the cells reading it
+    // are marked stale until the next recalculation, and forcing propagation recalculates
+    // every cell of the grid.
+    pub fn update_cell_with(
+        &mut self,
+        cell_ref: CellRef,
+        raw_val: String,
+        config: &EvalConfig,
+    ) -> Result<Vec<CellRef>, String> {
+        // A cell that is not stored is blank
+        let unchanged = self
+            .cells
+            .get(&cell_ref)
+            .map_or(raw_val.is_empty(), |it| it.raw() == raw_val);
+        if unchanged {
+            return Ok(if config.force_propagation {
+                self.recalculate_all()
+            } else {
+                Vec::new()
+            });
         }
 
-        let eval: Eval;
-        let mut precs: HashSet<CellRef> = HashSet::new();
-        let mut updated_cells = vec![cell_ref];
+        if let Some(anchor) = self.cells.get(&cell_ref).and_then(|it| it.spill_anchor()) {
+            return Err(format!(
+                "{cell_ref} is part of the array spilled from {anchor} and can't be edited."
+            ));
+        }
 
-        if raw_val.chars().nth(0) != Some('=') {
-            eval = evaluate_literal(raw_val.to_owned());
+        let cell = self
+            .cells
+            .entry(cell_ref)
+            .or_insert_with(|| Cell::new(cell_ref, Eval::Unset, String::new()));
+        cell.set_raw(raw_val);
+
+        if cell
+            .formula()
+            .is_some_and(|it| it.is_volatile(&self.functions))
+        {
+            self.volatile.insert(cell_ref);
         } else {
-            // Evaluate raw expr and get precedents
-            let (res_eval, res_precs) = evaluate(raw_val[1..].to_owned(), Some(&self));
-            eval = res_eval;
-            precs = res_precs;
+            self.volatile.remove(&cell_ref);
         }
 
-        if self.cells.contains_key(&cell_ref) {
-            updated_cells = self
-                .update_exisiting_cell(raw_val, eval, precs, cell_ref)
-                .into_iter()
-                .chain(updated_cells)
+        if config.force_propagation {
+            return Ok(self.recalculate_all());
+        }
+
+        self.dirty.insert(cell_ref);
+        if config.do_propagation {
+            self.dirty.extend(self.volatile.iter().cloned());
+        }
+        Ok(self.recalculate_dirty(&[cell_ref], config.do_propagation))
+    }
+
+    // Clears every cell of a range and recalculates the cells reading them. Arrays spilled from
+    // the range are cleared with their anchor, other arrays can't be cleared in part.
+    #[allow(dead_code)]
+    pub fn clear(&mut self, range: CellRange) -> Result<Vec<CellRef>, String> {
+        self.clear_with(range, &EvalConfig::default())
+    }
+
+    pub fn clear_with(
+        &mut self,
+        range: CellRange,
+        config: &EvalConfig,
+    ) -> Result<Vec<CellRef>, String> {
+        let mut cleared: Vec<CellRef> = if range.len() < self.cells.len() {
+            range
+                .cells()
+                .filter(|it| self.cells.contains_key(it))
+                .collect()
+        } else {
+            self.cells
+                .keys()
+                .filter(|it| range.contains(**it))
+                .cloned()
+                .collect()
+        };
+        cleared.sort_by_key(|it| (it.row, it.col));
+
+        for cell_ref in &cleared {
+            if let Some(anchor) = self.cells[cell_ref].spill_anchor()
+                && !range.contains(anchor)
+            {
+                return Err(format!(
+                    "{cell_ref} is part of the array spilled from {anchor} and can't be cleared."
+                ));
+            }
+        }
+
+        cleared.retain(|it| self.cells[it].spill_anchor().is_none());
+        for cell_ref in &cleared {
+            self.cells.get_mut(cell_ref).unwrap().set_raw(String::new());
+            self.volatile.remove(cell_ref);
+            self.dirty.insert(*cell_ref);
+        }
+
+        if config.force_propagation {
+            return Ok(self.recalculate_all());
+        }
+        if config.do_propagation {
+            self.dirty.extend(self.volatile.iter().cloned());
+        }
+        Ok(self.recalculate_dirty(&cleared, config.do_propagation))
+    }
+
+    #[allow(dead_code)]
+    pub fn shift(&mut self, shift: Shift) -> Vec<CellRef> {
+        self.shift_with(shift, &EvalConfig::default())
+    }
+
+    // Inserts or deletes rows or columns. The cells after the edit move, every formula is
+    // rewritten to refer to the same cells as before and the grid is recalculated with the new
+    // edges. Without propagation the cells keep their values instead, see shift_manual. Returns
+    // the cells whose raw text, value or staleness changed, including the places cells moved
+    // away from.
+    pub fn shift_with(&mut self, shift: Shift, config: &EvalConfig) -> Vec<CellRef> {
+        let before: HashMap<CellRef, (String, Eval, bool)> = self
+            .cells
+            .iter()
+            .map(|(cell_ref, cell)| {
+                let stale = self.stale.contains(cell_ref);
+                (*cell_ref, (cell.raw(), cell.eval(), stale))
+            })
+            .collect();
+
+        // Spilled values are recalculated or spilled again from their anchor
+        let cells = std::mem::take(&mut self.cells);
+        let stale = std::mem::take(&mut self.stale);
+        self.graph = DepGraph::default();
+        self.volatile.clear();
+        self.dirty.clear();
+
+        let mut results = Vec::new();
+        for (cell_ref, cell) in cells {
+            if cell.raw().is_empty() {
+                continue;
+            }
+            let Some(moved) = shift.cell(cell_ref) else {
+                continue;
+            };
+
+            let mut moved_cell = Cell::new(moved, Eval::Unset, shift.formula(&cell.raw()));
+            if moved_cell
+                .formula()
+                .is_some_and(|it| it.is_volatile(&self.functions))
+            {
+                self.volatile.insert(moved);
+            }
+            if !config.do_propagation {
+                moved_cell.set_eval(cell.eval());
+                if stale.contains(&cell_ref) {
+                    self.stale.insert(moved);
+                }
+                if moved_cell.formula().is_some() {
+                    results.push((moved, cell.result()));
+                }
+            }
+            self.cells.insert(moved, moved_cell);
+            self.dirty.insert(moved);
+        }
+
+        if config.force_propagation {
+            self.recalculate_all();
+        } else if config.do_propagation {
+            self.recalculate_dirty(&[], true);
+        } else {
+            results.sort_by_key(|(it, _)| (it.row, it.col));
+            self.shift_manual(results);
+        }
+
+        let mut updated: Vec<CellRef> = before
+            .keys()
+            .chain(self.cells.keys())
+            .filter(|it| {
+                let after = self
+                    .cells
+                    .get(it)
+                    .map(|cell| (cell.raw(), cell.eval(), self.stale.contains(it)));
+                before.get(it) != after.as_ref()
+            })
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        updated.sort_by_key(|it| (it.row, it.col));
+        updated
+    }
+
+    // The edges of moved formulas are found by evaluating them, but with calculation being manual
+    // their values are kept. Arrays spill their previous result again, and formulas whose value
+    // would change, e.g. from a deleted reference, become stale along with their dependents.
+    fn shift_manual(&mut self, results: Vec<(CellRef, Eval)>) {
+        self.dirty.clear();
+        for (cell_ref, result) in &results {
+            if matches!(result, Eval::Range(_)) {
+                self.set_result(*cell_ref, result.to_owned());
+            }
+        }
+
+        for (cell_ref, result) in results {
+            let (eval, precs) = self.evaluate_cell(cell_ref);
+            self.graph.set_precedents(cell_ref, precs);
+
+            let eval = match eval {
+                Eval::Range(array) if !array.is_empty() => Eval::Range(array.unwrapped()),
+                it => it,
+            };
+            if !same_value(&eval, &result) {
+                self.stale.insert(cell_ref);
+            } else if !matches!(eval, Eval::Range(_)) {
+                // The same value read through the moved references
+                self.cells.get_mut(&cell_ref).unwrap().set_eval(eval);
+            }
+        }
+
+        let mut stack: Vec<CellRef> = self.stale.iter().cloned().collect();
+        while let Some(cell_ref) = stack.pop() {
+            for dep in self.graph.dependents(cell_ref).collect::<Vec<_>>() {
+                if self.stale.insert(dep) {
+                    stack.push(dep);
+                }
+            }
+        }
+    }
+
+    // Recalculates the stale cells and the cells with volatile formulas, such as NOW or RAND,
+    // and their dependents
+    pub fn recalculate(&mut self) -> Vec<CellRef> {
+        self.dirty.extend(self.stale.iter().cloned());
+        self.dirty.extend(self.volatile.iter().cloned());
+        self.recalculate_dirty(&[], true)
+    }
+
+    // Recalculates every cell from its raw text and reports all of them
+    pub fn recalculate_all(&mut self) -> Vec<CellRef> {
+        let mut cells: Vec<CellRef> = self.cells.keys().cloned().collect();
+        cells.sort_by_key(|it| (it.row, it.col));
+        self.dirty.extend(cells.iter().cloned());
+        self.recalculate_dirty(&cells, true)
+    }
+
+    pub fn is_stale(&self, cell_ref: CellRef) -> bool {
+        self.stale.contains(&cell_ref)
+    }
+
+    // Recalculates the dirty cells in increasing level, marking the dependents of every cell
+    // whose value changed as dirty in turn, or as stale without propagation. Dependents of edited
+    // cells are always recalculated, e.g. an anchor blocked by a cell that is now empty. Returns
+    // the edited cells, the cells whose value changed and the cells that became or stopped being
+    // stale.
+    fn recalculate_dirty(&mut self, edited: &[CellRef], propagate: bool) -> Vec<CellRef> {
+        self.cycles.clear();
+        self.cycle_of.clear();
+
+        let mut queue: BinaryHeap<Reverse<(usize, usize, usize)>> = self
+            .dirty
+            .iter()
+            .map(|it| queued(&self.graph, *it))
+            .collect();
+        let mut updated = edited.to_vec();
+        let mut reported: HashSet<CellRef> = edited.iter().cloned().collect();
+        let mut edited = reported.clone();
+
+        let mut rounds = 0;
+        self.iterations = None;
+
+        while let Some(&Reverse((level, ..))) = queue.peek() {
+            // The cells of a level don't read each other and are evaluated together
+            let mut batch = Vec::new();
+            while let Some(&Reverse((l, row, col))) = queue.peek()
+                && l == level
+            {
+                queue.pop();
+                batch.push(CellRef { row, col });
+            }
+
+            // Iterating, every round evaluates the cells of circular references that read a value
+            // which moved in the round before
+            let iterating = level == CYCLIC && self.iteration.is_some();
+            if let Some(iteration) = self.iteration
+                && level == CYCLIC
+            {
+                if rounds == iteration.max_iterations {
+                    for cell_ref in batch {
+                        self.dirty.remove(&cell_ref);
+                    }
+                    self.iterations = Some(Iterations::NotConverged(rounds));
+                    continue;
+                }
+                rounds += 1;
+                self.iterations = Some(Iterations::Converged(rounds));
+            }
+
+            let mut evals = if batch.len() >= self.parallel_threshold && !iterating {
+                self.evaluate_parallel(&batch)
+            } else {
+                HashMap::new()
+            };
+
+            for cell_ref in batch {
+                if !self.dirty.contains(&cell_ref) {
+                    continue;
+                }
+                // Queued before its precedents changed
+                if level != self.graph.level(cell_ref) {
+                    queue.push(queued(&self.graph, cell_ref));
+                    continue;
+                }
+
+                let evaluated = evals.remove(&cell_ref);
+                let Some(changed) =
+                    self.recalculate_cell(cell_ref, edited.contains(&cell_ref), evaluated)
+                else {
+                    queue.push(queued(&self.graph, cell_ref));
+                    continue;
+                };
+                // Only once, a cell reading itself is dirty again
+                edited.remove(&cell_ref);
+                // Evaluated before it was known to be circular, which is the first round
+                if self.iteration.is_some()
+                    && level != CYCLIC
+                    && self.graph.level(cell_ref) == CYCLIC
+                    && rounds == 0
+                {
+                    rounds = 1;
+                    self.iterations = Some(Iterations::Converged(rounds));
+                }
+                if self.stale.remove(&cell_ref) && reported.insert(cell_ref) {
+                    updated.push(cell_ref);
+                }
+
+                let Grid {
+                    graph,
+                    dirty,
+                    stale,
+                    ..
+                } = self;
+                for it in changed {
+                    if reported.insert(it) {
+                        updated.push(it);
+                    }
+                    if propagate {
+                        for dep in graph.dependents(it) {
+                            if dirty.insert(dep) {
+                                queue.push(queued(graph, dep));
+                            }
+                        }
+                        continue;
+                    }
+
+                    // Everything downstream is out of date
+                    let mut stack: Vec<CellRef> = graph.dependents(it).collect();
+                    while let Some(dep) = stack.pop() {
+                        if stale.insert(dep) {
+                            if reported.insert(dep) {
+                                updated.push(dep);
+                            }
+                            stack.extend(graph.dependents(dep));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Cells left blank, e.g. cleared or released by an array, keep only their dependents,
+        // which are in the graph
+        for cell_ref in &updated {
+            if self.cells.get(cell_ref).is_some_and(|it| it.is_blank()) {
+                self.cells.remove(cell_ref);
+                self.stale.remove(cell_ref);
+            }
+        }
+
+        updated
+    }
+
+    // Evaluates the formulas of a level on worker threads, leaving the results to be stored in
+    // order. Volatile formulas are left to be evaluated in order too, so that a seed reproduces
+    // the same random numbers.
+    fn evaluate_parallel(&self, batch: &[CellRef]) -> HashMap<CellRef, (Eval, Precedents)> {
+        if self.workers < 2 {
+            return HashMap::new();
+        }
+
+        let cells: Vec<CellRef> = batch
+            .iter()
+            .filter(|it| {
+                self.cells
+                    .get(it)
+                    .is_some_and(|cell| cell.formula().is_some() && cell.spill_anchor().is_none())
+                    && !self.volatile.contains(it)
+            })
+            .cloned()
+            .collect();
+        let chunk = cells.len().div_ceil(self.workers).max(1);
+
+        thread::scope(|scope| {
+            let handles: Vec<_> = cells
+                .chunks(chunk)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|it| (*it, self.evaluate_cell(*it)))
+                            .collect::<Vec<_>>()
+                    })
+                })
                 .collect();
+            handles
+                .into_iter()
+                .flat_map(|it| it.join().unwrap())
+                .collect()
+        })
+    }
+
+    // Evaluates a dirty cell, unless it was evaluated ahead, and stores the result. Returns the
+    // cells whose dependents must be recalculated, or None when the cell now reads a dirty cell
+    // found at runtime, e.g. through INDIRECT, and has to wait for it.
+    fn recalculate_cell(
+        &mut self,
+        cell_ref: CellRef,
+        edited: bool,
+        evaluated: Option<(Eval, Precedents)>,
+    ) -> Option<Vec<CellRef>> {
+        let Some(cell) = self.cells.get(&cell_ref) else {
+            self.dirty.remove(&cell_ref);
+            return Some(Vec::new());
+        };
+
+        // The anchor already set the value of the cells it spilled into
+        if cell.spill_anchor().is_some() {
+            self.dirty.remove(&cell_ref);
+            return Some(vec![cell_ref]);
+        }
+
+        // An anchor that was blocked has the same result but shows a different value
+        let old = (cell.eval(), cell.result());
+        let (eval, precs) = evaluated.unwrap_or_else(|| self.evaluate_cell(cell_ref));
+        let waiting = self.reads_new_dirty(cell_ref, &precs);
+        self.graph.set_precedents(cell_ref, precs);
+
+        let cyclic = self.graph.level(cell_ref) == CYCLIC;
+        let eval = if cyclic && self.iteration.is_none() {
+            Eval::Err(self.circular_err(cell_ref))
+        } else if waiting {
+            return None;
         } else {
-            self.create_cell(raw_val, eval, precs, cell_ref);
+            eval
+        };
+
+        self.dirty.remove(&cell_ref);
+        let mut changed = self.set_result(cell_ref, eval);
+        let cell = &self.cells[&cell_ref];
+        let new = (cell.eval(), cell.result());
+        // Circular references are evaluated until no value moves by more than the tolerance
+        let moved = match self.iteration {
+            Some(iteration) if cyclic => {
+                !within(&old.0, &new.0, iteration.tolerance)
+                    || !within(&old.1, &new.1, iteration.tolerance)
+            }
+            _ => new != old,
+        };
+        if edited || moved {
+            changed.push(cell_ref);
+        }
+        Some(changed)
+    }
+
+    // The error of a cell in or downstream of a circular reference, e.g. "A1 → B3 → A1"
+    fn circular_err(&mut self, cell_ref: CellRef) -> LeadErr {
+        let desc = match self.find_cycle(cell_ref) {
+            Some((index, true)) => {
+                let mut cycle = self.cycles[index].clone();
+                let start = cycle.iter().position(|it| *it == cell_ref).unwrap_or(0);
+                cycle.rotate_left(start);
+                format!("Circular reference {}.", format_cycle(&cycle))
+            }
+            Some((index, false)) => format!(
+                "Reads the circular reference {}.",
+                format_cycle(&self.cycles[index])
+            ),
+            None => "Circular dependencies detected.".into(),
+        };
+
+        LeadErr {
+            title: "Propagation error.".into(),
+            desc,
+            code: LeadErrCode::Ref,
+        }
+    }
+
+    // The index in cycles of a cycle through the cell, or of the nearest one upstream of it, and
+    // whether the cell is part of it
+    fn find_cycle(&mut self, cell_ref: CellRef) -> Option<(usize, bool)> {
+        if let Some(found) = self.cycle_of.get(&cell_ref) {
+            return Some(*found);
+        }
+
+        if let Some(cycle) = self.graph.cycle_through(cell_ref) {
+            let index = self.add_cycle(cycle);
+            return Some((index, true));
+        }
+
+        let mut new_cycle = None;
+        let upstream = self.graph.find_upstream(cell_ref, |it| {
+            if self.cycle_of.contains_key(&it) {
+                return true;
+            }
+            new_cycle = self.graph.cycle_through(it);
+            new_cycle.is_some()
+        })?;
+
+        let index = match new_cycle {
+            Some(cycle) => self.add_cycle(cycle),
+            None => self.cycle_of[&upstream].0,
+        };
+        self.cycle_of.insert(cell_ref, (index, false));
+        Some((index, false))
+    }
+
+    fn add_cycle(&mut self, cycle: Vec<CellRef>) -> usize {
+        let index = self.cycles.len();
+        for cell in &cycle {
+            self.cycle_of.entry(*cell).or_insert((index, true));
         }
+        self.cycles.push(cycle);
+        index
+    }
+
+    // How many rounds the last recalculation evaluated circular references for, when iterating
+    pub fn iterations(&self) -> Option<Iterations> {
+        self.iterations
+    }
 
-        Ok(updated_cells)
+    // The circular references met by the last recalculation, each cell reading the next and the
+    // last reading the first
+    pub fn cycles(&self) -> &[Vec<CellRef>] {
+        &self.cycles
+    }
+
+    // Whether a cell now reads a dirty cell it did not read before. Dirty cells it already read
+    // have a lower level and were recalculated first.
+    fn reads_new_dirty(&self, cell_ref: CellRef, precs: &Precedents) -> bool {
+        let none = Precedents::new();
+        let old = self.graph.precedents(cell_ref).unwrap_or(&none);
+        let is_dirty = |it: &CellRef| *it != cell_ref && self.dirty.contains(it);
+
+        precs.cells().difference(old.cells()).any(is_dirty)
+            || precs.ranges().difference(old.ranges()).any(|range| {
+                if range.len() < self.dirty.len() {
+                    range.cells().any(|it| is_dirty(&it))
+                } else {
+                    self.dirty
+                        .iter()
+                        .any(|it| range.contains(*it) && is_dirty(it))
+                }
+            })
+    }
+
+    // The value of a cell from its cached formula, or from its raw text when it has none. An
+    // empty cell has no value.
+    fn evaluate_cell(&self, cell_ref: CellRef) -> (Eval, Precedents) {
+        let cell = &self.cells[&cell_ref];
+        match cell.formula() {
+            Some(formula) => formula.evaluate(Some(self)),
+            None if cell.raw().is_empty() => (Eval::Unset, Precedents::new()),
+            None => (evaluate_literal(cell.raw()), Precedents::new()),
+        }
     }
 
     pub fn quick_eval(&mut self, raw_val: String) -> Eval {
         if raw_val.chars().nth(0) != Some('=') {
             Eval::Literal(Literal::String(raw_val.to_owned()))
         } else {
-            let (res_eval, ..) = evaluate(raw_val[1..].to_owned(), Some(&self));
+            let (res_eval, ..) = evaluate(raw_val[1..].to_owned(), Some(self));
             res_eval
         }
     }
 
-    pub fn get_cell(&self, cell_ref: CellRef) -> Result<Cell, String> {
-        if !self.cells.contains_key(&cell_ref) {
-            return Err(format!("Cell at {:?} not found.", cell_ref));
-        }
-
-        let cell = &self.cells[&cell_ref];
-
-        // Ok((cell.raw(), cell.eval()))
-        Ok(cell.to_owned())
+    pub fn get_cell(&self, cell_ref: CellRef) -> Result<&Cell, String> {
+        self.cells
+            .get(&cell_ref)
+            .ok_or_else(|| format!("Cell at {:?} not found.", cell_ref))
     }
 
+    #[allow(dead_code)]
     pub fn get_cell_mut(&mut self, cell_ref: CellRef) -> Result<&mut Cell, String> {
         if let Some(res) = self.cells.get_mut(&cell_ref) {
             Ok(res)
@@ -82,193 +789,875 @@ impl Grid {
         }
     }
 
-    // This is a topological order on the precedents graph
-    // i.e. if a requires b (e.g. a = 1 + b) then a -> b
-    // so a comes before b in the topo order
-    fn topo_order(&self, from: CellRef) -> (Vec<CellRef>, bool) {
-        let mut res = Vec::new();
-        let mut temp = HashSet::new();
-        let mut perm = HashSet::new();
-        let mut cycle_detected = false;
-
-        self.topo_visit(
-            from,
-            &mut temp,
-            &mut perm,
-            &mut res,
-            &mut cycle_detected,
-            from,
-        );
+    // Stores the result of a cell's formula. An array spills into the cells below and to the
+    // right of the anchor unless one of them is occupied, in which case the anchor shows a spill
+    // error and is recalculated once the blocking cells change. Returns the cells that joined or
+    // left the spill area, which the caller must propagate from.
+    fn set_result(&mut self, anchor: CellRef, result: Eval) -> Vec<CellRef> {
+        if !self.cells.contains_key(&anchor) {
+            return Vec::new();
+        }
 
-        (res, cycle_detected)
-    }
+        let old_area = match self.cells[&anchor].spill() {
+            Some(spill) if spill.is_blocked() => {
+                for blocker in &spill.blocked_by {
+                    if self
+                        .graph
+                        .precedents(anchor)
+                        .is_none_or(|it| !it.contains(blocker))
+                    {
+                        self.graph.remove_dependent(*blocker, anchor);
+                    }
+                }
+                None
+            }
+            Some(spill) => Some(spill_area(anchor, spill.array.rows, spill.array.cols)),
+            None => None,
+        };
 
-    fn topo_visit(
-        &self,
-        cell: CellRef,
-        temp: &mut HashSet<CellRef>,
-        perm: &mut HashSet<CellRef>,
-        res: &mut Vec<CellRef>,
-        cycle_detected: &mut bool,
-        caller: CellRef,
-    ) {
-        if perm.contains(&cell) {
-            return;
-        }
+        let array = match result {
+            Eval::Range(array) if !array.is_empty() => array.unwrapped(),
+            it => {
+                let cell = self.cells.get_mut(&anchor).unwrap();
+                cell.set_eval(it);
+                cell.set_spill(None);
+                return self.release_spill_children(anchor, old_area, None);
+            }
+        };
 
-        if !temp.insert(cell) {
-            *cycle_detected = true;
-            return;
+        // Spilling into a cell the formula reads would make it read its own result
+        let area = spill_area(anchor, array.rows, array.cols);
+        if self.graph.reads_any(anchor, spill_children(area)) {
+            let cell = self.cells.get_mut(&anchor).unwrap();
+            cell.set_eval(Eval::Err(LeadErr {
+                title: "Propagation error.".into(),
+                desc: format!("Circular reference, {anchor} spills into {area} which it reads."),
+                code: LeadErrCode::Ref,
+            }));
+            cell.set_spill(None);
+            return self.release_spill_children(anchor, old_area, None);
         }
 
-        if !self.cells.contains_key(&cell) {
-            perm.insert(cell);
-            res.push(cell);
-            return;
-        }
+        let blocked_by: Vec<CellRef> = spill_children(area)
+            .filter(|it| {
+                self.cells.get(it).is_some_and(|cell| {
+                    !cell.raw().is_empty() || cell.spill_anchor().is_some_and(|a| a != anchor)
+                })
+            })
+            .collect();
 
-        // Walk dependencies if this cell exists; otherwise treat as leaf/external ref.
-        if let Some(cell_data) = self.cells.get(&cell) {
-            for &dep in cell_data.deps().iter() {
-                self.topo_visit(dep, temp, perm, res, cycle_detected, caller);
+        if !blocked_by.is_empty() {
+            for blocker in &blocked_by {
+                self.graph.add_dependent(*blocker, anchor);
             }
+
+            let spill = Spill { array, blocked_by };
+            let cell = self.cells.get_mut(&anchor).unwrap();
+            cell.set_eval(Eval::Err(spill.err()));
+            cell.set_spill(Some(spill));
+            return self.release_spill_children(anchor, old_area, None);
         }
 
-        // Done exploring this node
-        temp.remove(&cell);
-        perm.insert(cell);
-        if cell != caller {
-            res.push(cell);
+        for (i, child) in spill_children(area).enumerate() {
+            // The anchor is the first cell of the area and is skipped by spill_children
+            let value = array.values[i + 1].to_owned();
+
+            let cell = self
+                .cells
+                .entry(child)
+                .or_insert_with(|| Cell::new(child, Eval::Unset, String::new()));
+            cell.set_eval(value);
+            cell.set_spill_anchor(Some(anchor));
+            self.graph.insert_edge(anchor, child);
         }
+
+        let cell = self.cells.get_mut(&anchor).unwrap();
+        cell.set_eval(array.values[0].to_owned());
+        cell.set_spill(Some(Spill {
+            array,
+            blocked_by: Vec::new(),
+        }));
+
+        let mut resized = self.release_spill_children(anchor, old_area, Some(area));
+        resized.extend(
+            spill_children(area).filter(|it| old_area.is_none_or(|old| !old.contains(*it))),
+        );
+        resized
     }
 
-    fn update_exisiting_cell(
+    // Resets the cells of old_area that are no longer part of the anchor's spill area. Both are
+    // rectangles so membership is checked arithmetically, keeping a resize linear in its size.
+    fn release_spill_children(
         &mut self,
-        raw: String,
-        new_eval: Eval,
-        new_precs: HashSet<CellRef>,
-        cell_ref: CellRef,
+        anchor: CellRef,
+        old_area: Option<CellRange>,
+        area: Option<CellRange>,
     ) -> Vec<CellRef> {
-        let (old_precs, old_eval) = match self.cells.get_mut(&cell_ref) {
-            Some(cell) => {
-                cell.set_raw(raw);
-                (cell.precs().clone(), cell.eval().clone())
-            }
-            None => return Vec::new(),
+        let Some(old_area) = old_area else {
+            return Vec::new();
         };
 
-        // diffs (outside any borrow)
-        let removed: Vec<_> = old_precs.difference(&new_precs).cloned().collect(); // old \ new
-        let added: Vec<_> = new_precs.difference(&old_precs).cloned().collect(); // new \ old
-        let eval_changed = old_eval != new_eval;
+        let released: Vec<CellRef> = spill_children(old_area)
+            .filter(|it| area.is_none_or(|area| !area.contains(*it)))
+            .collect();
 
-        // ---- phase 2: apply (fresh borrows) ----
-        for p in &removed {
-            if let Some(c) = self.cells.get_mut(p) {
-                c.remove_dep(&cell_ref);
+        for child in &released {
+            if let Some(cell) = self.cells.get_mut(child) {
+                cell.set_eval(Eval::Unset);
+                cell.set_spill_anchor(None);
             }
+            self.graph.remove_edge(anchor, *child);
         }
-        for p in &added {
-            if let Some(c) = self.cells.get_mut(p) {
-                c.add_dep(cell_ref);
-            } else {
-                self.cells.insert(
-                    *p,
-                    Cell::new_all(
-                        *p,
-                        Eval::Unset,
-                        "".into(),
-                        HashSet::new(),
-                        HashSet::from([cell_ref]),
-                    ),
-                );
-            }
+
+        released
+    }
+}
+
+// Dirty cells are recalculated in increasing level, then by position so that a seed reproduces
+// the same random numbers in the same cells
+fn queued(graph: &DepGraph, cell_ref: CellRef) -> Reverse<(usize, usize, usize)> {
+    Reverse((graph.level(cell_ref), cell_ref.row, cell_ref.col))
+}
+
+// Whether two values are equal, numbers up to the tolerance
+fn within(a: &Eval, b: &Eval, tolerance: f64) -> bool {
+    let number = |mut it: &Eval| {
+        while let Eval::CellRef { eval, .. } = it {
+            it = eval;
+        }
+        match it {
+            Eval::Literal(Literal::Number(n)) => Some(*n),
+            _ => None,
         }
+    };
 
-        let cell = self.cells.get_mut(&cell_ref).unwrap(); // Should be impossible to crash
-        cell.set_precs(new_precs);
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => (a - b).abs() <= tolerance,
+        _ => a == b,
+    }
+}
 
-        if eval_changed {
-            cell.set_eval(new_eval);
-            match self.propagate(cell_ref) {
-                Ok(affected_cells) => affected_cells,
-                Err(affected_cells) => {
-                    let cell = self.cells.get_mut(&cell_ref).unwrap();
-                    cell.set_eval(Eval::Err(LeadErr {
-                        title: "Propagation error.".into(),
-                        desc: "Circular dependencies detected.".into(),
-                        code: LeadErrCode::Ref,
-                    }));
+// Whether two results are the same value, ignoring the references they were read through
+fn same_value(a: &Eval, b: &Eval) -> bool {
+    let value = |mut it: &Eval| {
+        while let Eval::CellRef { eval, .. } = it {
+            it = eval;
+        }
+        it.to_owned()
+    };
 
-                    affected_cells
-                }
-            }
-        } else {
-            Vec::new()
+    value(a) == value(b)
+}
+
+// The cells covered by a rows x cols array anchored at anchor
+fn spill_area(anchor: CellRef, rows: usize, cols: usize) -> CellRange {
+    CellRange::new(
+        anchor,
+        CellRef {
+            row: anchor.row + rows - 1,
+            col: anchor.col + cols - 1,
+        },
+    )
+}
+
+// The cells of a spill area the array spills into, row by row, excluding the anchor itself
+fn spill_children(area: CellRange) -> impl Iterator<Item = CellRef> {
+    area.cells().skip(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> CellRef {
+        CellRef::new(s.into()).unwrap()
+    }
+
+    fn value(grid: &Grid, s: &str) -> Eval {
+        match grid.get_cell(at(s)).map(|it| it.eval()) {
+            Ok(Eval::CellRef { eval, .. }) => *eval,
+            Ok(it) => it,
+            Err(_) => Eval::Unset,
         }
     }
 
-    fn create_cell(&mut self, raw: String, eval: Eval, precs: HashSet<CellRef>, cell_ref: CellRef) {
-        for prec in &precs {
-            if let Some(it) = self.cells.get_mut(&prec) {
-                it.add_dep(cell_ref);
-            } else {
-                self.cells.insert(
-                    *prec,
-                    Cell::new_all(
-                        *prec,
-                        Eval::Unset,
-                        "".into(),
-                        HashSet::new(),
-                        HashSet::from([cell_ref]),
-                    ),
-                );
+    fn num(n: f64) -> Eval {
+        Eval::Literal(Literal::Number(n))
+    }
 
-                info!("{:?}", self.cells.get(&prec));
-            }
+    #[test]
+    fn test_spill() {
+        let mut grid = Grid::new();
+        for (cell, raw) in [("A1", "1"), ("A2", "2"), ("A3", "3")] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+
+        let updated = grid.update_cell(at("C1"), "=A1:A3".into()).unwrap();
+        assert!(updated.contains(&at("C2")) && updated.contains(&at("C3")));
+        assert_eq!(value(&grid, "C1"), num(1.0));
+        assert_eq!(value(&grid, "C3"), num(3.0));
+        assert!(grid.update_cell(at("C2"), "5".into()).is_err());
+
+        grid.update_cell(at("E1"), "=SUM(C1#)".into()).unwrap();
+        grid.update_cell(at("F1"), "=C3".into()).unwrap();
+        assert_eq!(value(&grid, "E1"), num(6.0));
+
+        grid.update_cell(at("A3"), "4".into()).unwrap();
+        assert_eq!(value(&grid, "C3"), num(4.0));
+        assert_eq!(value(&grid, "E1"), num(7.0));
+        assert_eq!(value(&grid, "F1"), num(4.0));
+
+        // Shrinking releases C3 and recalculates its dependents
+        let updated = grid.update_cell(at("C1"), "=A1:A2".into()).unwrap();
+        assert!(updated.contains(&at("C3")) && updated.contains(&at("F1")));
+        assert_eq!(value(&grid, "C3"), Eval::Unset);
+        assert_eq!(value(&grid, "E1"), num(3.0));
+        assert_eq!(value(&grid, "F1"), Eval::Unset);
+        grid.update_cell(at("C3"), "9".into()).unwrap();
+        assert_eq!(value(&grid, "F1"), num(9.0));
+
+        // C3 now blocks the spill until it is cleared
+        grid.update_cell(at("C1"), "=A1:A3".into()).unwrap();
+        assert!(matches!(
+            value(&grid, "C1"),
+            Eval::Err(LeadErr {
+                code: LeadErrCode::Spill,
+                ..
+            })
+        ));
+
+        grid.update_cell(at("C3"), "".into()).unwrap();
+        assert_eq!(value(&grid, "C3"), num(4.0));
+        assert_eq!(value(&grid, "E1"), num(7.0));
+        assert_eq!(value(&grid, "F1"), num(4.0));
+
+        // A scalar result removes the spill
+        grid.update_cell(at("C1"), "=A1".into()).unwrap();
+        assert_eq!(value(&grid, "C2"), Eval::Unset);
+        assert!(matches!(
+            value(&grid, "E1"),
+            Eval::Err(LeadErr {
+                code: LeadErrCode::Ref,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_spill_shape() {
+        let mut grid = Grid::new();
+        for (cell, raw) in [("A1", "1"), ("B1", "2"), ("A2", "3"), ("B2", "4")] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+
+        grid.update_cell(at("D4"), "=A1:B2".into()).unwrap();
+        assert_eq!(value(&grid, "E4"), num(2.0));
+        assert_eq!(value(&grid, "D5"), num(3.0));
+        assert_eq!(value(&grid, "E5"), num(4.0));
+
+        let (eval, _) = evaluate("D4#".into(), Some(&grid));
+        let Eval::Range(array) = eval else {
+            panic!("Expected a range, found {eval:?}");
+        };
+        assert_eq!((array.rows, array.cols), (2, 2));
+        assert_eq!(evaluate("SUM(D4#)".into(), Some(&grid)).0, num(10.0));
+    }
+
+    #[test]
+    fn test_spill_resize() {
+        let mut grid = Grid::new();
+        grid.update_cell(at("A1"), "=SEQUENCE(3)".into()).unwrap();
+        assert_eq!(value(&grid, "A3"), num(3.0));
+
+        // Reshaping releases the cells left behind and fills the new ones
+        grid.update_cell(at("A1"), "=SEQUENCE(2, 2)".into())
+            .unwrap();
+        assert_eq!(value(&grid, "B2"), num(4.0));
+        assert_eq!(value(&grid, "A3"), Eval::Unset);
+        assert!(grid.get_cell(at("A3")).is_err());
+
+        // Large resizes compare the areas as rectangles rather than cell by cell
+        grid.update_cell(at("A1"), "=SEQUENCE(50000)".into())
+            .unwrap();
+        let updated = grid
+            .update_cell(at("A1"), "=SEQUENCE(50001)".into())
+            .unwrap();
+        assert!(updated.contains(&at("A50001")));
+        assert_eq!(value(&grid, "A50001"), num(50001.0));
+    }
+
+    #[test]
+    fn test_spill_into_precedent() {
+        let mut grid = Grid::new();
+        let is_circular = |grid: &Grid, s: &str| matches!(value(grid, s), Eval::Err(e) if e.code == LeadErrCode::Ref);
+
+        // Reading a cell of its own spill area, directly or through another cell
+        grid.update_cell(at("A1"), "=SEQUENCE(2) + A2".into())
+            .unwrap();
+        assert!(is_circular(&grid, "A1"));
+        assert_eq!(value(&grid, "A2"), Eval::Unset);
+
+        grid.update_cell(at("C1"), "=B2".into()).unwrap();
+        grid.update_cell(at("B1"), "=SEQUENCE(2) + C1".into())
+            .unwrap();
+        assert!(is_circular(&grid, "B1"));
+        assert_eq!(value(&grid, "B2"), Eval::Unset);
+
+        // An array that already spilled releases its cells
+        grid.update_cell(at("D1"), "=SEQUENCE(2)".into()).unwrap();
+        assert_eq!(value(&grid, "D2"), num(2.0));
+        grid.update_cell(at("D1"), "=SEQUENCE(2) + D2".into())
+            .unwrap();
+        assert!(is_circular(&grid, "D1"));
+        assert_eq!(value(&grid, "D2"), Eval::Unset);
+
+        // Reading outside the area spills again
+        grid.update_cell(at("D1"), "=SEQUENCE(2) + E1".into())
+            .unwrap();
+        assert_eq!(value(&grid, "D2"), num(2.0));
+    }
+
+    #[test]
+    fn test_volatile() {
+        let mut grid = Grid::new().with_seed(1);
+        grid.update_cell(at("A1"), "=RAND()".into()).unwrap();
+        grid.update_cell(at("B1"), "=A1 * 2".into()).unwrap();
+        grid.update_cell(at("C1"), "=1 + 1".into()).unwrap();
+
+        // Any edit recalculates volatile cells and their dependents
+        let before = value(&grid, "A1");
+        let updated = grid.update_cell(at("D1"), "5".into()).unwrap();
+        assert_eq!(updated, [at("D1"), at("A1"), at("B1")]);
+        assert_ne!(value(&grid, "A1"), before);
+
+        let Eval::Literal(Literal::Number(a)) = value(&grid, "A1") else {
+            panic!("expected a number");
+        };
+        assert_eq!(value(&grid, "B1"), num(a * 2.0));
+
+        let updated = grid.recalculate();
+        assert_eq!(updated, [at("A1"), at("B1")]);
+
+        // The same seed gives the same values
+        let mut other = Grid::new().with_seed(1);
+        for (cell, raw) in [
+            ("A1", "=RAND()"),
+            ("B1", "=A1 * 2"),
+            ("C1", "=1 + 1"),
+            ("D1", "5"),
+        ] {
+            other.update_cell(at(cell), raw.into()).unwrap();
+        }
+        assert_eq!(value(&other, "A1"), num(a));
+
+        // Recalculated values that did not change are not reported
+        grid.update_cell(at("A1"), "=NOW() * 0".into()).unwrap();
+        assert!(grid.volatile.contains(&at("A1")));
+        assert!(grid.recalculate().is_empty());
+        grid.update_cell(at("A1"), "1".into()).unwrap();
+        assert!(grid.recalculate().is_empty());
+    }
+
+    #[test]
+    fn test_recalculation() {
+        let mut grid = Grid::new();
+        // D1 reads A1 through B1 and C1, and is recalculated after both
+        for (cell, raw) in [
+            ("B1", "=A1 + 1"),
+            ("C1", "=B1 * A1"),
+            ("D1", "=B1 + C1"),
+            ("E1", "=A1 > 0"),
+            ("F1", "=E1 * 1"),
+        ] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+
+        // Typing into an empty cell recalculates the cells reading it
+        let updated = grid.update_cell(at("A1"), "2".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(9.0));
+        assert_eq!(value(&grid, "F1"), num(1.0));
+        assert!(updated.contains(&at("F1")));
+
+        // E1 stays true, so F1 is not recalculated
+        let updated = grid.update_cell(at("A1"), "3".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(16.0));
+        assert!(updated.contains(&at("D1")));
+        assert!(!updated.contains(&at("E1")) && !updated.contains(&at("F1")));
+
+        // Clearing goes through the same path
+        grid.update_cell(at("A1"), "".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(1.0));
+        assert_eq!(value(&grid, "F1"), num(0.0));
+
+        let is_cyclic = |grid: &Grid, s: &str| matches!(value(grid, s), Eval::Err(e) if e.desc.to_lowercase().contains("circular"));
+        grid.update_cell(at("A1"), "=D1".into()).unwrap();
+        for cell in ["A1", "B1", "C1", "D1", "F1"] {
+            assert!(is_cyclic(&grid, cell));
+        }
+        grid.update_cell(at("G1"), "=G1 + 1".into()).unwrap();
+        assert!(is_cyclic(&grid, "G1"));
+
+        // Breaking the cycle recalculates every cell that was in it
+        grid.update_cell(at("A1"), "1".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(4.0));
+        assert_eq!(value(&grid, "F1"), num(1.0));
+        assert!(is_cyclic(&grid, "G1"));
+    }
+
+    #[test]
+    fn test_circular_reference() {
+        let mut grid = Grid::new();
+        for (cell, raw) in [
+            ("A1", "=B3 + 1"),
+            ("B3", "=C2 * 2"),
+            ("C2", "=Z9"),
+            ("D1", "=SUM(A1:A2)"),
+            ("D2", "=D1"),
+            ("E1", "=Z9"),
+        ] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
         }
+        let desc = |grid: &Grid, s: &str| match value(grid, s) {
+            Eval::Err(e) => e.desc,
+            it => it.to_string(),
+        };
 
-        self.cells.insert(
-            cell_ref,
-            Cell::new_all(cell_ref, eval, raw, precs, HashSet::new()),
+        grid.update_cell(at("Z9"), "=A1".into()).unwrap();
+        assert_eq!(
+            grid.cycles(),
+            [vec![at("Z9"), at("A1"), at("B3"), at("C2")]]
+        );
+        assert_eq!(
+            desc(&grid, "A1"),
+            "Circular reference A1 → B3 → C2 → Z9 → A1."
+        );
+        assert_eq!(
+            desc(&grid, "C2"),
+            "Circular reference C2 → Z9 → A1 → B3 → C2."
         );
+        // Cells downstream name the cycle they read, cells upstream are unaffected
+        assert_eq!(
+            desc(&grid, "D2"),
+            "Reads the circular reference Z9 → A1 → B3 → C2 → Z9."
+        );
+        assert!(desc(&grid, "E1").starts_with("Reads"));
+
+        grid.update_cell(at("F1"), "=F1".into()).unwrap();
+        assert_eq!(desc(&grid, "F1"), "Circular reference F1 → F1.");
+        assert_eq!(grid.cycles(), [vec![at("F1")]]);
+
+        grid.update_cell(at("Z9"), "2".into()).unwrap();
+        assert!(grid.cycles().is_empty());
+        assert_eq!(value(&grid, "D2"), num(5.0));
     }
 
-    fn propagate(&mut self, from: CellRef) -> Result<Vec<CellRef>, Vec<CellRef>> {
-        let (topo, cycle_detected) = self.topo_order(from);
+    #[test]
+    fn test_iteration() {
+        let mut grid = Grid::new().with_iteration(100, 0.001);
+        grid.update_cell(at("A1"), "=10 + B1 / 2".into()).unwrap();
+        grid.update_cell(at("C1"), "=A1 * 3".into()).unwrap();
+        assert_eq!(grid.iterations(), None);
 
-        if !cycle_detected {
-            for cell_ref in topo.to_owned() {
-                let raw = if let Some(cell) = self.cells.get(&cell_ref) {
-                    let s = cell.raw();
-                    if let Some(rest) = s.strip_prefix('=') {
-                        rest.to_owned()
-                    } else {
-                        continue;
-                    }
-                } else {
-                    continue;
-                };
+        // A1 = 10 + A1 / 4 settles at 40 / 3
+        let updated = grid.update_cell(at("B1"), "=A1 / 2".into()).unwrap();
+        let Some(Iterations::Converged(rounds)) = grid.iterations() else {
+            panic!("{:?}", grid.iterations());
+        };
+        assert!(rounds > 2 && rounds < 20, "{rounds}");
+        assert!(updated.contains(&at("C1")));
+        assert!(grid.cycles().is_empty());
+        for (cell, expected) in [("A1", 40.0 / 3.0), ("B1", 20.0 / 3.0), ("C1", 40.0)] {
+            let Eval::Literal(Literal::Number(n)) = value(&grid, cell) else {
+                panic!("{cell} is not a number");
+            };
+            assert!((n - expected).abs() < 0.01, "{cell} = {n}");
+        }
 
-                let (e, _) = evaluate(raw, Some(self));
+        grid.update_cell(at("D1"), "=D1 + 1".into()).unwrap();
+        assert_eq!(grid.iterations(), Some(Iterations::NotConverged(100)));
+        // Evaluated once before its reference to itself was known, which is the first round
+        assert_eq!(value(&grid, "D1"), num(100.0));
 
-                if let Some(cell) = self.cells.get_mut(&cell_ref) {
-                    cell.set_eval(e);
-                }
-            }
-            Ok(topo)
-        } else {
-            let err = LeadErr {
-                title: "Propagation error.".into(),
-                desc: "Circular dependencies detected.".into(),
+        let mut grid = Grid::new().with_iteration(1, 0.001);
+        grid.update_cell(at("D1"), "=D1 + 1".into()).unwrap();
+        assert_eq!(grid.iterations(), Some(Iterations::NotConverged(1)));
+        assert_eq!(value(&grid, "D1"), num(1.0));
+
+        // No iterations are the same as iteration being off
+        let mut grid = Grid::new().with_iteration(0, 0.001);
+        grid.update_cell(at("D1"), "=D1 + 1".into()).unwrap();
+        assert!(matches!(value(&grid, "D1"), Eval::Err(_)));
+        assert_eq!(grid.iterations(), None);
+
+        // Without iteration the same references are errors
+        let mut grid = Grid::new();
+        grid.update_cell(at("D1"), "=D1 + 1".into()).unwrap();
+        assert!(matches!(value(&grid, "D1"), Eval::Err(_)));
+        assert_eq!(grid.iterations(), None);
+
+        // Turned on and off by a request, circular references being evaluated again
+        let config = |max_iterations, tolerance| EvalConfig {
+            max_iterations,
+            tolerance,
+            ..EvalConfig::default()
+        };
+        grid.configure(&config(Some(5), None));
+        assert_eq!(grid.recalculate(), [at("D1")]);
+        assert_eq!(grid.iterations(), Some(Iterations::NotConverged(5)));
+        assert_eq!(value(&grid, "D1"), num(5.0));
+
+        // Settings left out are kept
+        grid.configure(&config(None, Some(0.5)));
+        grid.configure(&EvalConfig::default());
+        let kept = Iteration {
+            max_iterations: 5,
+            tolerance: 0.5,
+        };
+        assert_eq!(grid.iteration, Some(kept));
+
+        grid.configure(&config(Some(0), None));
+        grid.recalculate();
+        assert!(matches!(value(&grid, "D1"), Eval::Err(_)));
+        assert_eq!(grid.iterations(), None);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut grid = Grid::new();
+        for (cell, raw) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("B1", "=SUM(A1:A2)"),
+            ("B2", "=A1"),
+            ("C1", "=A1:A2 * 2"),
+            ("D1", "=C2"),
+        ] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+        let range = |s: &str| CellRange::parse(s).unwrap();
+
+        // Dependents see blank cells rather than empty text
+        let updated = grid.clear(range("A1:A2")).unwrap();
+        assert!(updated.contains(&at("A1")) && updated.contains(&at("D1")));
+        assert!(!grid.cells.contains_key(&at("A1")) && !grid.cells.contains_key(&at("A2")));
+        assert_eq!(value(&grid, "B1"), num(0.0));
+        assert_eq!(value(&grid, "D1"), num(0.0));
+        assert_eq!(value(&grid, "B2"), Eval::Unset);
+
+        // Part of an array can only be cleared with its anchor
+        assert!(grid.clear(range("C2")).is_err());
+        let updated = grid.clear(range("C1:C2")).unwrap();
+        assert!(updated.contains(&at("C2")));
+        assert_eq!(value(&grid, "D1"), Eval::Unset);
+        assert!(!grid.cells.contains_key(&at("C2")));
+
+        // Clearing removes the edges of the cleared cells
+        grid.update_cell(at("B1"), "".into()).unwrap();
+        grid.update_cell(at("B2"), "".into()).unwrap();
+        grid.update_cell(at("D1"), "".into()).unwrap();
+        assert!(grid.cells.is_empty());
+        assert_eq!(grid.graph.dependents(at("A1")).count(), 0);
+        assert!(grid.graph.precedents(at("B1")).is_none());
+
+        // Setting an empty cell to nothing stores nothing
+        assert!(grid.update_cell(at("E1"), "".into()).unwrap().is_empty());
+        assert!(grid.cells.is_empty());
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut grid = Grid::new();
+        for (cell, raw) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("A3", "3"),
+            ("B1", "=SUM(A1:A3)"),
+            ("B2", "=$A$3 * 2"),
+            ("C1", "=A1:A2"),
+            ("D1", "=A2 + 1"),
+        ] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+        let raw = |grid: &Grid, s: &str| grid.get_cell(at(s)).map(|it| it.raw()).unwrap();
+
+        // Inserting a row moves the cells below it and expands the ranges across it
+        let updated = grid.shift(Shift::parse("2", true).unwrap());
+        assert_eq!(raw(&grid, "B1"), "=SUM(A1:A4)");
+        assert_eq!(raw(&grid, "B3"), "=$A$4 * 2");
+        assert_eq!(raw(&grid, "C1"), "=A1:A3");
+        assert_eq!(raw(&grid, "D1"), "=A3 + 1");
+        for cell in ["A2", "B1", "B2", "A4", "B3", "C3"] {
+            assert!(updated.contains(&at(cell)), "{cell}");
+        }
+        assert!(!updated.contains(&at("A1")));
+        assert_eq!(value(&grid, "A2"), Eval::Unset);
+        assert_eq!(value(&grid, "C3"), num(2.0));
+
+        // The edges follow the cells
+        grid.update_cell(at("A4"), "10".into()).unwrap();
+        assert_eq!(value(&grid, "B1"), num(13.0));
+        assert_eq!(value(&grid, "B3"), num(20.0));
+        grid.update_cell(at("A2"), "5".into()).unwrap();
+        assert_eq!(value(&grid, "B1"), num(18.0));
+        assert_eq!(value(&grid, "D1"), num(3.0));
+
+        // Deleting rows shrinks ranges and breaks references to the deleted cells
+        grid.shift(Shift::parse("2:3", false).unwrap());
+        assert_eq!(raw(&grid, "B1"), "=SUM(A1:A2)");
+        assert_eq!(raw(&grid, "D1"), "=#REF! + 1");
+        assert_eq!(value(&grid, "B1"), num(11.0));
+        assert!(matches!(
+            value(&grid, "D1"),
+            Eval::Err(LeadErr {
                 code: LeadErrCode::Ref,
-            };
-            topo.iter().for_each(|cell_ref| {
-                if let Some(cell) = self.cells.get_mut(&cell_ref) {
-                    cell.set_eval(Eval::Err(err.to_owned()));
-                }
-            });
-            Err(topo)
+                ..
+            })
+        ));
+        assert!(grid.get_cell(at("A3")).is_err());
+
+        grid.shift(Shift::parse("A", true).unwrap());
+        assert_eq!(raw(&grid, "C1"), "=SUM(B1:B2)");
+        assert_eq!(value(&grid, "C1"), num(11.0));
+        grid.shift(Shift::parse("B", false).unwrap());
+        assert_eq!(raw(&grid, "B1"), "=SUM(#REF!)");
+    }
+
+    #[test]
+    fn test_shift_manual() {
+        let mut grid = Grid::new();
+        let manual = EvalConfig {
+            do_propagation: false,
+            ..EvalConfig::default()
+        };
+        for (cell, raw) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("B1", "=A1:A2 * 10"),
+            ("C1", "=A2"),
+            ("D1", "=SUM(A1:A2)"),
+            ("E1", "=C1 + 1"),
+        ] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+        grid.update_cell_with(at("A1"), "5".into(), &manual)
+            .unwrap();
+        assert!(grid.is_stale(at("D1")));
+
+        // Values move as they are, arrays spill again and stale cells stay stale
+        let updated = grid.shift_with(Shift::parse("1", true).unwrap(), &manual);
+        assert!(updated.contains(&at("D2")) && updated.contains(&at("B3")));
+        assert_eq!(value(&grid, "B3"), num(20.0));
+        assert_eq!(value(&grid, "C2"), num(2.0));
+        assert_eq!(value(&grid, "D2"), num(3.0));
+        assert!(grid.is_stale(at("D2")) && !grid.is_stale(at("D1")));
+        assert!(!grid.is_stale(at("C2")) && !grid.is_stale(at("E2")));
+
+        // A formula whose value would change becomes stale with its dependents
+        grid.shift_with(Shift::parse("3", false).unwrap(), &manual);
+        assert_eq!(grid.get_cell(at("C2")).unwrap().raw(), "=#REF!");
+        assert_eq!(value(&grid, "C2"), num(2.0));
+        assert!(grid.is_stale(at("C2")) && grid.is_stale(at("E2")));
+
+        // The edges follow the cells
+        grid.recalculate();
+        assert_eq!(value(&grid, "D2"), num(5.0));
+        assert!(!grid.is_stale(at("E2")));
+        grid.update_cell(at("A2"), "7".into()).unwrap();
+        assert_eq!(value(&grid, "B2"), num(70.0));
+        assert_eq!(value(&grid, "D2"), num(7.0));
+    }
+
+    #[test]
+    fn test_large_range() {
+        let mut grid = Grid::new();
+        grid.update_cell(at("B1"), "=SUM(A1:A100000)".into())
+            .unwrap();
+        grid.update_cell(at("B2"), "=B1 + SUM(C1:D3)".into())
+            .unwrap();
+
+        // The range is one entry rather than a placeholder for every cell
+        assert_eq!(grid.cells.len(), 2);
+        assert!(grid.graph.precedents(at("B1")).unwrap().cells().is_empty());
+
+        let updated = grid.update_cell(at("A50000"), "5".into()).unwrap();
+        assert_eq!(value(&grid, "B1"), num(5.0));
+        assert_eq!(value(&grid, "B2"), num(5.0));
+        assert!(updated.contains(&at("B1")) && updated.contains(&at("B2")));
+
+        // A formula inside the range is recalculated before the cells reading the range
+        grid.update_cell(at("A3"), "=C1 * 2".into()).unwrap();
+        grid.update_cell(at("C1"), "4".into()).unwrap();
+        assert_eq!(value(&grid, "B1"), num(13.0));
+        assert_eq!(value(&grid, "B2"), num(17.0));
+
+        grid.update_cell(at("B1"), "0".into()).unwrap();
+        grid.update_cell(at("A50000"), "6".into()).unwrap();
+        assert_eq!(value(&grid, "B2"), num(4.0));
+        assert_eq!(grid.graph.dependents(at("A50000")).count(), 0);
+    }
+
+    #[test]
+    fn test_manual_calculation() {
+        let mut grid = Grid::new();
+        let manual = EvalConfig {
+            do_propagation: false,
+            ..EvalConfig::default()
+        };
+        for (cell, raw) in [("A1", "1"), ("B1", "=A1 * 2"), ("C1", "=B1 + 1")] {
+            grid.update_cell(at(cell), raw.into()).unwrap();
+        }
+
+        // The edited cell is evaluated, the cells downstream only marked stale
+        let updated = grid
+            .update_cell_with(at("A1"), "5".into(), &manual)
+            .unwrap();
+        assert_eq!(updated, [at("A1"), at("B1"), at("C1")]);
+        assert_eq!(value(&grid, "A1"), num(5.0));
+        assert_eq!(value(&grid, "C1"), num(3.0));
+        assert!(grid.is_stale(at("B1")) && grid.is_stale(at("C1")));
+
+        grid.update_cell_with(at("D1"), "=C1".into(), &manual)
+            .unwrap();
+        assert_eq!(value(&grid, "D1"), num(3.0));
+        assert!(!grid.is_stale(at("D1")));
+
+        // Recalculating brings every stale cell up to date
+        let updated = grid.recalculate();
+        assert!(updated.contains(&at("B1")) && updated.contains(&at("D1")));
+        assert_eq!(value(&grid, "D1"), num(11.0));
+        assert!(!grid.is_stale(at("B1")) && !grid.is_stale(at("C1")));
+
+        // Editing with propagation also clears stale cells on the way
+        grid.update_cell_with(at("A1"), "1".into(), &manual)
+            .unwrap();
+        grid.update_cell(at("A1"), "2".into()).unwrap();
+        assert_eq!(value(&grid, "D1"), num(5.0));
+        assert!(!grid.is_stale(at("C1")));
+
+        // Forcing propagation recalculates and reports every cell
+        let force = EvalConfig {
+            force_propagation: true,
+            ..EvalConfig::default()
+        };
+        let updated = grid.update_cell_with(at("A1"), "2".into(), &force).unwrap();
+        assert_eq!(updated.len(), 4);
+    }
+
+    #[test]
+    fn test_parallel_recalculation() {
+        let mut sequential = Grid::new().with_seed(7).with_parallel_threshold(usize::MAX);
+        let mut parallel = Grid::new().with_seed(7).with_parallel_threshold(1);
+        parallel.workers = 4;
+
+        let mut edits: Vec<(String, String)> = (1..=20)
+            .flat_map(|row| {
+                [
+                    (format!("A{row}"), row.to_string()),
+                    (
+                        format!("B{row}"),
+                        format!("=A{row} * 2 + RANDBETWEEN(0, {row} % 5)"),
+                    ),
+                ]
+            })
+            .collect();
+        for (cell, raw) in [
+            ("C1", "=SUM(B1:B20)"),
+            ("D1", "=A1:A3"),
+            ("E1", "=D2 + C1"),
+            ("F1", "=INDIRECT(\"B\" & A1)"),
+            ("G1", "=G2 + 1"),
+            ("G2", "=G1"),
+            ("H1", "=1 / 0 + A2"),
+            ("A1", "3"),
+            ("D3", "blocked"),
+            ("A1", "5"),
+        ] {
+            edits.push((cell.into(), raw.into()));
+        }
+
+        for (cell, raw) in edits {
+            assert_eq!(
+                sequential.update_cell(at(&cell), raw.clone()),
+                parallel.update_cell(at(&cell), raw)
+            );
+        }
+        assert_eq!(sequential.recalculate(), parallel.recalculate());
+        assert_eq!(sequential.cells.len(), parallel.cells.len());
+        for (cell_ref, cell) in &sequential.cells {
+            assert_eq!(cell.eval(), parallel.cells[cell_ref].eval());
+        }
+
+        // Set by a request, which leaves it alone when not given
+        let config = EvalConfig {
+            parallel_threshold: Some(0),
+            ..EvalConfig::default()
+        };
+        sequential.configure(&config);
+        assert_eq!(sequential.parallel_threshold, 1);
+        sequential.configure(&EvalConfig::default());
+        assert_eq!(sequential.parallel_threshold, 1);
+    }
+
+    // cargo test --release bench_propagation -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_propagation() {
+        use std::time::Instant;
+
+        // 50k formulas reading A1 through a layer of 1k cells
+        const N: usize = 50_000;
+        const MID: usize = 1_000;
+
+        let mut grid = Grid::new();
+        grid.update_cell(at("A1"), "1".into()).unwrap();
+        for row in 0..MID {
+            grid.update_cell(CellRef { row, col: 1 }, format!("=A1 + {row}"))
+                .unwrap();
+        }
+        for row in 0..N {
+            let raw = format!(
+                "=ROUND(B{0} / 3, 2) * 2 + MAX(1, 10, 100) - MIN(B{0}, 0)",
+                row % MID + 1
+            );
+            grid.update_cell(CellRef { row, col: 2 }, raw).unwrap();
+        }
+
+        let start = Instant::now();
+        let updated = grid.update_cell(at("A1"), "7".into()).unwrap();
+        let cached = start.elapsed();
+        assert_eq!(updated.len(), MID + N + 1);
+
+        grid.parallel_threshold = usize::MAX;
+        let start = Instant::now();
+        grid.update_cell(at("A1"), "8".into()).unwrap();
+        let sequential = start.elapsed();
+        grid.parallel_threshold = 1024;
+        grid.update_cell(at("A1"), "7".into()).unwrap();
+
+        // Evaluating every dependent from its cached formula, and from its raw text as
+        // propagation did before formulas were cached
+        let cells: Vec<&Cell> = (0..N)
+            .map(|row| grid.get_cell(CellRef { row, col: 2 }).unwrap())
+            .collect();
+
+        let start = Instant::now();
+        for cell in &cells {
+            let (eval, _) = cell.formula().unwrap().evaluate(Some(&grid));
+            assert_eq!(eval, cell.eval());
+        }
+        let compiled = start.elapsed();
+
+        let start = Instant::now();
+        for cell in &cells {
+            let (eval, _) = evaluate(cell.raw()[1..].to_owned(), Some(&grid));
+            assert_eq!(eval, cell.eval());
         }
+        let reparsed = start.elapsed();
+
+        println!(
+            "{N} dependents: propagation {cached:?} ({sequential:?} sequential), evaluating \
+             cached {compiled:?}, reparsing {reparsed:?} ({:.1}x)",
+            reparsed.as_secs_f64() / compiled.as_secs_f64()
+        );
     }
 }
@@ -1,9 +1,11 @@
 mod cell;
 mod common;
 mod evaluator;
+mod graph;
 mod grid;
 mod messages;
 mod parser;
+mod shift;
 mod tokenizer;
 
 use futures_util::{SinkExt, StreamExt, TryStreamExt};
@@ -12,8 +14,12 @@ use std::{env, io::Error};
 use tokio::net::{TcpListener, TcpStream};
 
 use crate::{
-    grid::Grid,
+    cell::{CellRange, CellRef},
+    evaluator::Eval,
+    graph::format_cycle,
+    grid::{Grid, Iterations},
     messages::{LeadMsg, MsgType},
+    shift::Shift,
 };
 
 #[tokio::main]
@@ -57,58 +63,27 @@ async fn accept_connection(stream: TcpStream) {
         if msg.is_text() {
             let input = msg.to_text().unwrap();
 
-            if let Ok(req) = serde_json::from_str::<LeadMsg>(&input) {
+            if let Ok(req) = serde_json::from_str::<LeadMsg>(input) {
+                if let Some(config) = &req.eval_config {
+                    grid.configure(config);
+                }
+
                 match req.msg_type {
                     MsgType::Set => {
                         let Some(cell_ref) = req.cell else { continue };
                         let Some(raw) = req.raw else { continue };
-                        // let config = req.eval_config.unwrap_or_default();
+                        let config = req.eval_config.unwrap_or_default();
 
-                        match grid.update_cell(cell_ref.clone(), raw.to_owned()) {
+                        match grid.update_cell_with(cell_ref, raw.to_owned(), &config) {
                             Ok(updates) => {
-                                let mut msgs = Vec::new();
-
-                                for update in &updates {
-                                    if let Ok(cell) = grid.get_cell(*update) {
-                                        msgs.push(LeadMsg {
-                                            msg_type: MsgType::Set,
-                                            cell: Some(*update),
-                                            raw: Some(cell.raw()),
-                                            eval: Some(cell.eval()),
-                                            bulk_msgs: None,
-                                            eval_config: None,
-                                        });
-                                    }
-                                }
-
-                                if msgs.len() == 1 {
-                                    let _ = write
-                                        .send(serde_json::to_string(&msgs.get(0)).unwrap().into())
-                                        .await;
-                                } else if msgs.len() > 1 {
-                                    let msg = LeadMsg {
-                                        cell: None,
-                                        raw: None,
-                                        eval: None,
-                                        eval_config: None,
-                                        bulk_msgs: Some(msgs),
-                                        msg_type: MsgType::Bulk,
-                                    };
-
+                                if let Some(msg) = updates_msg(&grid, &updates) {
                                     let _ = write
                                         .send(serde_json::to_string(&msg).unwrap().into())
                                         .await;
                                 }
                             }
                             Err(e) => {
-                                let res = LeadMsg {
-                                    msg_type: MsgType::Error,
-                                    cell: Some(cell_ref),
-                                    raw: Some(e.to_string()),
-                                    eval: None,
-                                    eval_config: None,
-                                    bulk_msgs: None,
-                                };
+                                let res = LeadMsg::error(Some(cell_ref), e.to_string());
                                 let _ = write
                                     .send(serde_json::to_string(&res).unwrap().into())
                                     .await;
@@ -122,18 +97,81 @@ async fn accept_connection(stream: TcpStream) {
                         let eval = grid.quick_eval(raw.to_owned());
 
                         let msg = LeadMsg {
-                            msg_type: MsgType::Eval,
                             cell: Some(cell_ref),
                             raw: Some(raw),
                             eval: Some(eval),
-                            bulk_msgs: None,
-                            eval_config: None,
+                            ..LeadMsg::new(MsgType::Eval)
                         };
 
                         let _ = write
                             .send(serde_json::to_string(&msg).unwrap().into())
                             .await;
                     }
+                    MsgType::Clear => {
+                        let Some(cell_ref) = req.cell else { continue };
+                        let config = req.eval_config.unwrap_or_default();
+                        // The range to clear as text, e.g. "A1:C3", or just the cell
+                        let range = match req.raw.as_deref().map(CellRange::parse) {
+                            Some(Ok(range)) => Ok(range),
+                            Some(Err(e)) => Err(e.desc),
+                            None => Ok(CellRange::new(cell_ref, cell_ref)),
+                        };
+
+                        let msg = match range.and_then(|it| grid.clear_with(it, &config)) {
+                            Ok(updates) => updates_msg(&grid, &updates),
+                            Err(e) => Some(LeadMsg::error(Some(cell_ref), e)),
+                        };
+                        if let Some(msg) = msg {
+                            let _ = write
+                                .send(serde_json::to_string(&msg).unwrap().into())
+                                .await;
+                        }
+                    }
+                    MsgType::Insert | MsgType::Delete => {
+                        let Some(raw) = req.raw else { continue };
+                        let insert = matches!(req.msg_type, MsgType::Insert);
+                        let config = req.eval_config.unwrap_or_default();
+
+                        let msg = match Shift::parse(&raw, insert) {
+                            Ok(shift) => {
+                                let updates = grid.shift_with(shift, &config);
+                                updates_msg(&grid, &updates)
+                            }
+                            Err(e) => Some(LeadMsg::error(None, e)),
+                        };
+                        if let Some(msg) = msg {
+                            let _ = write
+                                .send(serde_json::to_string(&msg).unwrap().into())
+                                .await;
+                        }
+                    }
+                    MsgType::Functions => {
+                        let msg = LeadMsg {
+                            functions: Some(grid.functions().infos()),
+                            ..LeadMsg::new(MsgType::Functions)
+                        };
+
+                        let _ = write
+                            .send(serde_json::to_string(&msg).unwrap().into())
+                            .await;
+                    }
+                    MsgType::Recalc => {
+                        if let Some(seed) = req.seed {
+                            grid.seed(seed);
+                        }
+
+                        let config = req.eval_config.unwrap_or_default();
+                        let updates = if config.force_propagation {
+                            grid.recalculate_all()
+                        } else {
+                            grid.recalculate()
+                        };
+                        if let Some(msg) = updates_msg(&grid, &updates) {
+                            let _ = write
+                                .send(serde_json::to_string(&msg).unwrap().into())
+                                .await;
+                        }
+                    }
                     _ => {
                         continue; // handle other cases
                     }
@@ -146,3 +184,60 @@ async fn accept_connection(stream: TcpStream) {
 
     info!("Disconnected from {}", addr);
 }
+
+// The new values of updated cells and whether they are stale, followed by the circular
+// references met or the result of iterating over them, a single message or a Bulk message of
+// them
+fn updates_msg(grid: &Grid, updates: &[CellRef]) -> Option<LeadMsg> {
+    let mut msgs = Vec::new();
+
+    for update in updates {
+        // Cells that are no longer stored are blank
+        let (raw, eval) = match grid.get_cell(*update) {
+            Ok(cell) => (cell.raw(), cell.eval()),
+            Err(_) => (String::new(), Eval::Unset),
+        };
+
+        msgs.push(LeadMsg {
+            cell: Some(*update),
+            raw: Some(raw),
+            eval: Some(eval),
+            stale: Some(grid.is_stale(*update)),
+            ..LeadMsg::new(MsgType::Set)
+        });
+    }
+
+    if !grid.cycles().is_empty() {
+        let cycles = grid.cycles().to_vec();
+        let text: Vec<String> = cycles.iter().map(|it| format_cycle(it)).collect();
+
+        msgs.push(LeadMsg {
+            raw: Some(format!("Circular references: {}.", text.join(", "))),
+            cycles: Some(cycles),
+            ..LeadMsg::new(MsgType::Cycles)
+        });
+    }
+
+    if let Some(iterations) = grid.iterations() {
+        let raw = match iterations {
+            Iterations::Converged(rounds) => format!("Converged after {rounds} iterations."),
+            Iterations::NotConverged(rounds) => {
+                format!("Did not converge after {rounds} iterations.")
+            }
+        };
+
+        msgs.push(LeadMsg {
+            raw: Some(raw),
+            ..LeadMsg::new(MsgType::Iterations)
+        });
+    }
+
+    if msgs.len() > 1 {
+        Some(LeadMsg {
+            bulk_msgs: Some(msgs),
+            ..LeadMsg::new(MsgType::Bulk)
+        })
+    } else {
+        msgs.pop()
+    }
+}
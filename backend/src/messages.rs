@@ -1,21 +1,49 @@
 use serde::{Deserialize, Serialize};
 
-use crate::{cell::CellRef, evaluator::Eval};
+use crate::{
+    cell::CellRef,
+    evaluator::{Eval, FunctionInfo},
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum MsgType {
     Set,
+    // Clears the cell, or the range given as raw text such as "A1:C3"
+    Clear,
+    // Insert or delete the rows or columns given as raw text, e.g. "3:4" or "B:B", answered
+    // with the cells that moved and the rewritten formulas
+    Insert,
+    Delete,
     Eval,
     Get,
     Error,
     Bulk,
+    // Lists the functions available to formulas
+    Functions,
+    // Recalculates stale cells and volatile formulas, reseeding random numbers first when a seed
+    // is given, or every cell when the config forces propagation. Circular references are
+    // evaluated again when the config changes iteration.
+    Recalc,
+    // Lists the circular references met while recalculating
+    Cycles,
+    // Whether iterating over circular references converged, and after how many rounds
+    Iterations,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EvalConfig {
     pub do_propagation: bool,
     pub force_propagation: bool,
+    // Evaluates circular references up to this many times until no value moves by more than
+    // the tolerance, 0 to leave them as errors. Kept by the connection until changed.
+    #[serde(default)]
+    pub max_iterations: Option<usize>,
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+    // Levels with at least this many cells to recalculate are evaluated on worker threads
+    #[serde(default)]
+    pub parallel_threshold: Option<usize>,
 }
 
 impl Default for EvalConfig {
@@ -23,6 +51,9 @@ impl Default for EvalConfig {
         EvalConfig {
             do_propagation: true,
             force_propagation: false,
+            max_iterations: None,
+            tolerance: None,
+            parallel_threshold: None,
         }
     }
 }
@@ -35,4 +66,36 @@ pub struct LeadMsg {
     pub eval: Option<Eval>,
     pub eval_config: Option<EvalConfig>,
     pub bulk_msgs: Option<Vec<LeadMsg>>,
+    pub functions: Option<Vec<FunctionInfo>>,
+    pub seed: Option<u64>,
+    // Whether the value of the cell is out of date, calculation being manual
+    pub stale: Option<bool>,
+    // Each cell reading the next and the last reading the first
+    pub cycles: Option<Vec<Vec<CellRef>>>,
+}
+
+impl LeadMsg {
+    // A message with only its type set, the rest are filled in with struct update syntax
+    pub fn new(msg_type: MsgType) -> LeadMsg {
+        LeadMsg {
+            msg_type,
+            cell: None,
+            raw: None,
+            eval: None,
+            eval_config: None,
+            bulk_msgs: None,
+            functions: None,
+            seed: None,
+            stale: None,
+            cycles: None,
+        }
+    }
+
+    pub fn error(cell: Option<CellRef>, desc: String) -> LeadMsg {
+        LeadMsg {
+            cell,
+            raw: Some(desc),
+            ..LeadMsg::new(MsgType::Error)
+        }
+    }
 }
@@ -1,12 +1,11 @@
-use log::info;
-
 use crate::{
     cell::CellRef,
     common::{LeadErr, LeadErrCode, Literal},
-    tokenizer::{self, *},
+    tokenizer::*,
 };
 use std::{collections::HashSet, fmt};
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum PrefixOp {
     POS,
@@ -14,11 +13,14 @@ pub enum PrefixOp {
     NOT,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum PostfixOp {
     PERCENT,
+    SPILL,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum InfixOp {
     MUL,
@@ -28,6 +30,12 @@ pub enum InfixOp {
     AND,
     OR,
     RANGE,
+    EQ,
+    NE,
+    LT,
+    LE,
+    GT,
+    GE,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -64,23 +72,26 @@ pub trait Precedence {
 impl Precedence for InfixOp {
     fn prec(&self) -> (u8, u8) {
         match self {
-            InfixOp::RANGE => (7, 8),
-            InfixOp::MUL | InfixOp::DIV | InfixOp::AND => (3, 4),
-            InfixOp::ADD | InfixOp::SUB | InfixOp::OR => (1, 2),
+            InfixOp::RANGE => (9, 10),
+            InfixOp::MUL | InfixOp::DIV | InfixOp::AND => (5, 6),
+            InfixOp::ADD | InfixOp::SUB | InfixOp::OR => (3, 4),
+            InfixOp::EQ | InfixOp::NE | InfixOp::LT | InfixOp::LE | InfixOp::GT | InfixOp::GE => {
+                (1, 2)
+            }
         }
     }
 }
 impl Precedence for PrefixOp {
     fn prec(&self) -> (u8, u8) {
-        match self {
-            _it => (0, 5),
-        }
+        (0, 7)
     }
 }
 impl Precedence for PostfixOp {
     fn prec(&self) -> (u8, u8) {
         match self {
-            _it => (6, 0),
+            PostfixOp::PERCENT => (8, 0),
+            // A1# binds tighter than ranges
+            PostfixOp::SPILL => (11, 0),
         }
     }
 }
@@ -100,6 +111,8 @@ impl fmt::Display for Expr {
 }
 
 impl Expr {
+    // A tree drawing of the expression, for debugging
+    #[allow(dead_code)]
     pub fn pretty(&self) -> String {
         // entry point for users — root printed without └──
         let mut result = String::new();
@@ -166,7 +179,6 @@ pub fn parse(input: &str) -> Result<(Expr, HashSet<CellRef>), LeadErr> {
     let mut tokenizer = Tokenizer::new(input)?;
     let mut precs = HashSet::new();
     let expr = _parse(&mut tokenizer, 0, &mut precs)?;
-    info!("{}", expr.pretty());
     Ok((expr, precs))
 }
 
@@ -220,7 +232,7 @@ pub fn _parse(
                     if nxt == Token::CloseParen {
                         input.next();
                         break;
-                    } else if nxt != Token::Comma && args.len() != 0 {
+                    } else if nxt != Token::Comma && !args.is_empty() {
                         return Err(LeadErr {
                             title: "Parse error.".into(),
                             desc: format!(
@@ -230,7 +242,7 @@ pub fn _parse(
                         });
                     }
 
-                    if args.len() != 0 {
+                    if !args.is_empty() {
                         input.next(); // Skip comma
                     }
 
@@ -238,10 +250,7 @@ pub fn _parse(
                     args.push(arg);
                 }
 
-                Expr::Function {
-                    name: id,
-                    args: args,
-                }
+                Expr::Function { name: id, args }
             }
             _ => {
                 let cell_ref = CellRef::new(id)?;
@@ -250,6 +259,7 @@ pub fn _parse(
             }
         },
 
+        Token::Err(e) => return Err(e),
         it => {
             return Err(LeadErr {
                 title: "Parse error.".into(),
@@ -262,7 +272,30 @@ pub fn _parse(
     // In the reference article this is a loop with match
     // statement that breaks on Eof and closing paren but this is simpler and works as expected
     while let Token::Operator(op) = input.peek() {
-        if OPERATORS_STR.contains(op) {
+        if "%#".contains(op) {
+            let postfix_op = match op {
+                '%' => PostfixOp::PERCENT,
+                '#' => PostfixOp::SPILL,
+                it => {
+                    return Err(LeadErr {
+                        title: "Parse error.".into(),
+                        desc: format!("Unknown postfix operator {:?}.", it),
+                        code: LeadErrCode::Syntax,
+                    });
+                }
+            };
+
+            let (l_prec, _) = postfix_op.prec();
+            if l_prec < min_prec {
+                break;
+            }
+
+            input.next();
+            lhs = Expr::Postfix {
+                op: postfix_op,
+                expr: Box::new(lhs),
+            };
+        } else if OPERATORS_STR.contains(op) {
             let infix_op = match op {
                 '+' => InfixOp::ADD,
                 '-' => InfixOp::SUB,
@@ -271,6 +304,9 @@ pub fn _parse(
                 '&' => InfixOp::AND,
                 '|' => InfixOp::OR,
                 ':' => InfixOp::RANGE,
+                '=' => InfixOp::EQ,
+                '<' => InfixOp::LT,
+                '>' => InfixOp::GT,
                 it => {
                     return Err(LeadErr {
                         title: "Parse error.".into(),
@@ -286,34 +322,24 @@ pub fn _parse(
             }
 
             input.next();
+
+            // Two character comparisons <=, >= and <>
+            let infix_op = match (infix_op, input.peek()) {
+                (InfixOp::LT, Token::Operator('=')) => InfixOp::LE,
+                (InfixOp::LT, Token::Operator('>')) => InfixOp::NE,
+                (InfixOp::GT, Token::Operator('=')) => InfixOp::GE,
+                (it, _) => it,
+            };
+            if matches!(infix_op, InfixOp::LE | InfixOp::NE | InfixOp::GE) {
+                input.next();
+            }
+
             let rhs = _parse(input, r_prec, precedents)?;
             lhs = Expr::Infix {
                 op: infix_op,
                 lhs: Box::new(lhs),
                 rhs: Box::new(rhs),
             };
-        } else if "%".contains(op) {
-            let postfix_op = match op {
-                '%' => PostfixOp::PERCENT,
-                it => {
-                    return Err(LeadErr {
-                        title: "Parse error.".into(),
-                        desc: format!("Unknown postfix operator {:?}.", it),
-                        code: LeadErrCode::Syntax,
-                    });
-                }
-            };
-
-            let (l_prec, _) = postfix_op.prec();
-            if l_prec < min_prec {
-                break;
-            }
-
-            input.next();
-            lhs = Expr::Postfix {
-                op: postfix_op,
-                expr: Box::new(lhs),
-            };
         }
     }
 
@@ -0,0 +1,478 @@
+use crate::cell::{CellRange, CellRef, Line, MAX_COLS, MAX_ROWS, col_name};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    Rows,
+    Cols,
+}
+
+// Inserting count rows or columns before the one at index, or deleting count of them from it.
+// Cells after the edit move and the formulas referring to them are rewritten.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shift {
+    pub axis: Axis,
+    pub at: usize,
+    pub count: usize,
+    pub insert: bool,
+}
+
+impl Shift {
+    // Whole rows or columns in A1 notation, e.g. "3:4" for rows 3 and 4 or "B:B" for column B
+    pub fn parse(s: &str, insert: bool) -> Result<Shift, String> {
+        let invalid = || format!("Expected rows such as 3:4 or columns such as B:D, found {s:?}.");
+        let (a, b) = s.split_once(':').unwrap_or((s, s));
+        let (a, b) = (a.trim(), b.trim());
+
+        let (axis, a, b) = if let (Ok(a), Ok(b)) = (a.parse::<usize>(), b.parse::<usize>()) {
+            (Axis::Rows, a, b)
+        } else if let (Some(a), Some(b)) = (col_index(a), col_index(b)) {
+            (Axis::Cols, a + 1, b + 1)
+        } else {
+            return Err(invalid());
+        };
+        if a == 0 || b == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Shift {
+            axis,
+            at: a.min(b) - 1,
+            count: a.abs_diff(b) + 1,
+            insert,
+        })
+    }
+
+    // Number of rows or columns along the axis
+    fn limit(&self) -> usize {
+        match self.axis {
+            Axis::Rows => MAX_ROWS,
+            Axis::Cols => MAX_COLS,
+        }
+    }
+
+    // Where an index along the axis moves, None when it is deleted or pushed off the sheet
+    fn index(&self, index: usize) -> Option<usize> {
+        if index < self.at {
+            Some(index)
+        } else if self.insert {
+            index
+                .checked_add(self.count)
+                .filter(|it| *it < self.limit())
+        } else if index >= self.at + self.count {
+            Some(index - self.count)
+        } else {
+            None
+        }
+    }
+
+    // Where the cell moves, None when it is deleted
+    pub fn cell(&self, cell: CellRef) -> Option<CellRef> {
+        match self.axis {
+            Axis::Rows => Some(CellRef {
+                row: self.index(cell.row)?,
+                col: cell.col,
+            }),
+            Axis::Cols => Some(CellRef {
+                row: cell.row,
+                col: self.index(cell.col)?,
+            }),
+        }
+    }
+
+    // Where the range moves. Inserting inside the range expands it, up to the end of the sheet
+    // when its end is pushed off, and deleting some of its cells shrinks it. None when all of
+    // its cells are deleted or pushed off.
+    pub fn range(&self, range: CellRange) -> Option<CellRange> {
+        let (start, end) = match self.axis {
+            Axis::Rows => (range.start.row, range.end.row),
+            Axis::Cols => (range.start.col, range.end.col),
+        };
+
+        let (start, end) = if self.insert {
+            (
+                self.index(start)?,
+                self.index(end).unwrap_or(self.limit() - 1),
+            )
+        } else {
+            let start = self.index(start).unwrap_or(self.at);
+            let end = match self.index(end) {
+                Some(it) => it,
+                None => self.at.checked_sub(1)?,
+            };
+            if start > end {
+                return None;
+            }
+            (start, end)
+        };
+
+        Some(match self.axis {
+            Axis::Rows => CellRange {
+                start: CellRef {
+                    row: start,
+                    ..range.start
+                },
+                end: CellRef {
+                    row: end,
+                    ..range.end
+                },
+            },
+            Axis::Cols => CellRange {
+                start: CellRef {
+                    col: start,
+                    ..range.start
+                },
+                end: CellRef {
+                    col: end,
+                    ..range.end
+                },
+            },
+        })
+    }
+
+    // The raw text of a cell with the references of its formula moved, relative and absolute
+    // alike, and references to deleted cells replaced by #REF!
+    pub fn formula(&self, raw: &str) -> String {
+        let Some(formula) = raw.strip_prefix('=') else {
+            return raw.to_owned();
+        };
+        let chars: Vec<char> = formula.chars().collect();
+        let mut res = String::from("=");
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+
+            // Text is left as it is, with the escapes of the tokenizer
+            if c == '"' || c == '\'' {
+                let mut escapes = 0;
+                let mut end = i + 1;
+                while end < chars.len() {
+                    let ch = chars[end];
+                    end += 1;
+                    if ch == c && escapes % 2 == 0 {
+                        break;
+                    }
+                    escapes = if ch == '\\' { escapes + 1 } else { 0 };
+                }
+                res.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+
+            // Whole columns or rows such as C:C or 1:1
+            if let Some((a, b, lines_end)) = whole_lines(&chars, i) {
+                match self.lines(a.line, b.line) {
+                    Some(moved) if moved == (a.line, b.line) => res.extend(&chars[i..lines_end]),
+                    Some((start, end)) => {
+                        res.push_str(&a.moved(start));
+                        res.push(':');
+                        res.push_str(&b.moved(end));
+                    }
+                    None => res.push_str("#REF!"),
+                }
+                i = lines_end;
+                continue;
+            }
+
+            // Numbers such as 1e5 are not references
+            if c.is_ascii_digit() {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+                    i += 1;
+                }
+                res.extend(&chars[start..i]);
+                continue;
+            }
+
+            if !c.is_ascii_alphabetic() && c != '$' {
+                res.push(c);
+                i += 1;
+                continue;
+            }
+
+            let end = identifier_end(&chars, i);
+            let Some(a) = reference(&chars, i, end) else {
+                res.extend(&chars[i..end]);
+                i = end;
+                continue;
+            };
+
+            // A range of two references, e.g. A1:B3
+            let colon = skip_whitespace(&chars, end);
+            let second = skip_whitespace(&chars, colon + 1);
+            let b = match chars.get(colon) {
+                Some(':') => reference(&chars, second, identifier_end(&chars, second)),
+                _ => None,
+            };
+
+            let Some(b) = b else {
+                match self.cell(a.cell) {
+                    Some(cell) if cell == a.cell => res.extend(&chars[i..end]),
+                    Some(cell) => res.push_str(&a.moved(cell)),
+                    None => res.push_str("#REF!"),
+                }
+                i = end;
+                continue;
+            };
+
+            let range_end = identifier_end(&chars, second);
+            let range = CellRange::new(a.cell, b.cell);
+            match self.range(range) {
+                Some(moved) if moved == range => res.extend(&chars[i..range_end]),
+                Some(moved) => {
+                    res.push_str(&a.moved(moved.start));
+                    res.push(':');
+                    res.push_str(&b.moved(moved.end));
+                }
+                None => res.push_str("#REF!"),
+            }
+            i = range_end;
+        }
+
+        res
+    }
+}
+
+impl Shift {
+    // Where whole columns or rows move, lines along the other axis stay whole
+    fn lines(&self, a: Line, b: Line) -> Option<(Line, Line)> {
+        let (a_cell, b_cell, line): (CellRef, CellRef, fn(CellRef) -> Line) =
+            match (a, b, self.axis) {
+                (Line::Col(a), Line::Col(b), Axis::Cols) => (
+                    CellRef { row: 0, col: a },
+                    CellRef { row: 0, col: b },
+                    |it| Line::Col(it.col),
+                ),
+                (Line::Row(a), Line::Row(b), Axis::Rows) => (
+                    CellRef { row: a, col: 0 },
+                    CellRef { row: b, col: 0 },
+                    |it| Line::Row(it.row),
+                ),
+                _ => return Some((a, b)),
+            };
+
+        let moved = self.range(CellRange::new(a_cell, b_cell))?;
+        Some((line(moved.start), line(moved.end)))
+    }
+}
+
+// A whole column or row in a formula, with the $ marking it as absolute
+struct LineReference {
+    line: Line,
+    abs: bool,
+}
+
+impl LineReference {
+    fn moved(&self, line: Line) -> String {
+        format!("{}{line}", if self.abs { "$" } else { "" })
+    }
+}
+
+// The whole columns or rows of a range starting at chars[start] such as C:C, and its end
+fn whole_lines(chars: &[char], start: usize) -> Option<(LineReference, LineReference, usize)> {
+    let line = |start: usize| {
+        let mut end = start;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '$') {
+            end += 1;
+        }
+        let s: String = chars.get(start..end)?.iter().collect();
+        let reference = LineReference {
+            line: Line::parse(&s)?,
+            abs: s.starts_with('$'),
+        };
+        Some((reference, end))
+    };
+
+    let (a, end) = line(start)?;
+    let colon = skip_whitespace(chars, end);
+    if chars.get(colon) != Some(&':') {
+        return None;
+    }
+    let (b, end) = line(skip_whitespace(chars, colon + 1))?;
+    if chars.get(end).is_some_and(|it| *it == '_' || *it == '.') {
+        return None;
+    }
+    Line::range(a.line, b.line)?;
+
+    Some((a, b, end))
+}
+
+// A reference in a formula, with the $ marking its column or row as absolute
+struct Reference {
+    cell: CellRef,
+    col_abs: bool,
+    row_abs: bool,
+}
+
+impl Reference {
+    fn moved(&self, cell: CellRef) -> String {
+        let dollar = |abs: bool| if abs { "$" } else { "" };
+        format!(
+            "{}{}{}{}",
+            dollar(self.col_abs),
+            col_name(cell.col),
+            dollar(self.row_abs),
+            cell.row + 1
+        )
+    }
+}
+
+// The end of the identifier starting at start, as read by the tokenizer
+fn identifier_end(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && (chars[end].is_ascii_alphanumeric() || "_.$".contains(chars[end])) {
+        end += 1;
+    }
+    end
+}
+
+fn skip_whitespace(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && chars[end].is_whitespace() {
+        end += 1;
+    }
+    end
+}
+
+// The reference spelled by chars[start..end], unless it is something else such as a function
+// name
+fn reference(chars: &[char], start: usize, end: usize) -> Option<Reference> {
+    if chars.get(skip_whitespace(chars, end)) == Some(&'(') {
+        return None;
+    }
+
+    let s: String = chars.get(start..end)?.iter().collect();
+    let (col_abs, s) = match s.strip_prefix('$') {
+        Some(it) => (true, it),
+        None => (false, s.as_str()),
+    };
+    let letters = s.find(|c: char| !c.is_ascii_alphabetic())?;
+    let (col, row) = s.split_at(letters);
+    let (row_abs, row) = match row.strip_prefix('$') {
+        Some(it) => (true, it),
+        None => (false, row),
+    };
+
+    if col.is_empty() || !row.chars().all(|c| c.is_ascii_digit()) || row.parse::<usize>().ok()? == 0
+    {
+        return None;
+    }
+    Some(Reference {
+        cell: CellRef::new(format!("{col}{row}")).ok()?,
+        col_abs,
+        row_abs,
+    })
+}
+
+// Zero indexed column of letters such as B
+fn col_index(s: &str) -> Option<usize> {
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    CellRef::new(format!("{s}1")).ok().map(|it| it.col)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(s: &str, insert: bool) -> Shift {
+        let shift = Shift::parse(s, insert).unwrap();
+        assert_eq!(shift.axis, Axis::Rows);
+        shift
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Shift::parse("3:4", true),
+            Ok(Shift {
+                axis: Axis::Rows,
+                at: 2,
+                count: 2,
+                insert: true
+            })
+        );
+        assert_eq!(
+            Shift::parse("D:b", false),
+            Ok(Shift {
+                axis: Axis::Cols,
+                at: 1,
+                count: 3,
+                insert: false
+            })
+        );
+        assert_eq!(Shift::parse("AA", true).unwrap().at, 26);
+        assert!(Shift::parse("0:1", true).is_err());
+        assert!(Shift::parse("A1:B2", true).is_err());
+    }
+
+    #[test]
+    fn test_insert() {
+        let shift = rows("2:3", true);
+        assert_eq!(shift.formula("=A1 + A2 * $B$5"), "=A1 + A4 * $B$7");
+        // Ranges across the edit expand
+        assert_eq!(
+            shift.formula("=SUM(A1:B2, A2:A3, $C$1 : C1)"),
+            "=SUM(A1:B4, A4:A5, $C$1 : C1)"
+        );
+        // Text, functions and numbers are not references
+        assert_eq!(
+            shift.formula("=CONCAT(\"A2\", 'B\\'3', LOG10(1e2)) + 2"),
+            "=CONCAT(\"A2\", 'B\\'3', LOG10(1e2)) + 2"
+        );
+        assert_eq!(shift.formula("A2"), "A2");
+
+        let cols = Shift::parse("A", true).unwrap();
+        assert_eq!(cols.formula("=A$1 + Z9#"), "=B$1 + AA9#");
+
+        // Cells pushed off the sheet are lost like deleted ones
+        assert_eq!(shift.formula("=A1048575 + A1"), "=#REF! + A1");
+        assert_eq!(cols.formula("=XFD1"), "=#REF!");
+
+        // Ranges whose end is pushed off stop at the end of the sheet
+        assert_eq!(
+            rows("5", true).formula("=SUM(A1:A1048576)"),
+            "=SUM(A1:A1048576)"
+        );
+        assert_eq!(shift.formula("=SUM(A2:A1048575)"), "=SUM(A4:A1048576)");
+        assert_eq!(cols.formula("=SUM(XFC1:XFD1)"), "=SUM(XFD1:XFD1)");
+        assert_eq!(shift.formula("=SUM(A1048575:A1048576)"), "=SUM(#REF!)");
+    }
+
+    #[test]
+    fn test_delete() {
+        let shift = rows("2:3", false);
+        assert_eq!(shift.formula("=A1 + A2 + $A$4"), "=A1 + #REF! + $A$2");
+        // Ranges shrink, or are deleted with all their cells
+        assert_eq!(
+            shift.formula("=SUM(A1:A5) + SUM(A3:B6) + SUM(A2:A3)"),
+            "=SUM(A1:A3) + SUM(A2:B4) + SUM(#REF!)"
+        );
+        assert_eq!(rows("1", false).formula("=SUM(A1:A1)"), "=SUM(#REF!)");
+        assert_eq!(rows("1", false).formula("=SUM(A1:A2)"), "=SUM(A1:A1)");
+    }
+
+    #[test]
+    fn test_whole_lines() {
+        let cols = |s: &str, insert: bool| Shift::parse(s, insert).unwrap();
+
+        // Lines along the edited axis move, the others stay whole
+        assert_eq!(
+            cols("A", true).formula("=SUM(C:$D) + SUM(2:3)"),
+            "=SUM(D:$E) + SUM(2:3)"
+        );
+        assert_eq!(
+            rows("1", true).formula("=SUM(C:D) + SUM(2:$3)"),
+            "=SUM(C:D) + SUM(3:$4)"
+        );
+        assert_eq!(
+            rows("3", false).formula("=SUM(C:C) + SUM(2:4)"),
+            "=SUM(C:C) + SUM(2:3)"
+        );
+        assert_eq!(rows("2", false).formula("=SUM(2:2) + 1"), "=SUM(#REF!) + 1");
+        // The last column stays in the sheet
+        assert_eq!(cols("B", true).formula("=SUM(A:XFD)"), "=SUM(A:XFD)");
+        assert_eq!(rows("2", true).formula("=1 + 1e5"), "=1 + 1e5");
+    }
+}
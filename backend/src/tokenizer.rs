@@ -1,4 +1,9 @@
-use crate::common::{LeadErr, LeadErrCode, Literal};
+use std::{iter::Peekable, str::Chars};
+
+use crate::{
+    cell::Line,
+    common::{LeadErr, LeadErrCode, Literal},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -12,7 +17,7 @@ pub enum Token {
     Eof,
 }
 
-pub const OPERATORS_STR: &str = "+-*/^!%&|:";
+pub const OPERATORS_STR: &str = "+-*/^!%&|:#=<>";
 
 pub struct Tokenizer {
     pub tokens: Vec<Token>,
@@ -26,11 +31,23 @@ impl Tokenizer {
         while let Some(&c) = chars.peek() {
             if c.is_whitespace() {
                 chars.next();
-            } else if c.is_ascii_alphabetic() {
+            } else if chars.clone().take(5).eq("#REF!".chars()) {
+                // Left in place of a reference to a deleted cell
+                tokens.push(Token::Err(LeadErr {
+                    title: "Evaluation error.".into(),
+                    desc: "The formula refers to a deleted cell.".into(),
+                    code: LeadErrCode::Ref,
+                }));
+                chars.nth(4);
+            } else if let Some(range) = whole_lines(&mut chars) {
+                tokens.extend(range);
+            } else if c.is_ascii_alphabetic() || c == '$' {
                 // parse identifier
                 let mut ident = String::new();
                 while let Some(&ch) = chars.peek() {
-                    if ch.is_ascii_alphanumeric() || ch == '_' {
+                    // Dots allow for function names such as STDEV.S, dollars for absolute
+                    // references such as $A$1
+                    if ch.is_ascii_alphanumeric() || ch == '_' || ch == '.' || ch == '$' {
                         ident.push(ch);
                         chars.next();
                     } else {
@@ -130,6 +147,39 @@ impl Tokenizer {
     }
 }
 
+// Whole columns such as C:C or rows such as 1:1, as the range between the cells at the edges of
+// the sheet. chars is only moved past them when they are found.
+fn whole_lines(chars: &mut Peekable<Chars>) -> Option<[Token; 3]> {
+    let mut ahead = chars.clone();
+    let line = |ahead: &mut Peekable<Chars>| {
+        let mut s = String::new();
+        while let Some(ch) = ahead.next_if(|it| it.is_ascii_alphanumeric() || *it == '$') {
+            s.push(ch);
+        }
+        Line::parse(&s)
+    };
+    let skip_whitespace =
+        |ahead: &mut Peekable<Chars>| while ahead.next_if(|it| it.is_whitespace()).is_some() {};
+
+    let a = line(&mut ahead)?;
+    skip_whitespace(&mut ahead);
+    ahead.next_if_eq(&':')?;
+    skip_whitespace(&mut ahead);
+    let b = line(&mut ahead)?;
+    // Not the start of a name such as C:C_total
+    if ahead.peek().is_some_and(|it| *it == '_' || *it == '.') {
+        return None;
+    }
+    let range = Line::range(a, b)?;
+
+    *chars = ahead;
+    Some([
+        Token::Identifier(range.start.to_string()),
+        Token::Operator(':'),
+        Token::Identifier(range.end.to_string()),
+    ])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,10 +249,13 @@ mod tests {
             Token::Operator('%'),
             Token::Operator('&'),
             Token::Operator('|'),
+            Token::Operator('='),
+            Token::Operator('<'),
+            Token::Operator('>'),
         ]);
         exp.reverse();
 
-        assert_eq!(Tokenizer::new("+-*/^!%&|").unwrap().tokens, exp);
+        assert_eq!(Tokenizer::new("+-*/^!%&|=<>").unwrap().tokens, exp);
     }
 
     #[test]
@@ -213,7 +266,7 @@ mod tests {
             Token::Literal(Literal::String("world".into())),
         ];
         expected.reverse();
-        let t = Tokenizer::new(&raw).unwrap();
+        let t = Tokenizer::new(raw).unwrap();
         assert_eq!(t.tokens, expected);
     }
 
@@ -225,7 +278,7 @@ mod tests {
             Token::Literal(Literal::Number(4.56)),
         ];
         expected.reverse();
-        let t = Tokenizer::new(&raw).unwrap();
+        let t = Tokenizer::new(raw).unwrap();
         assert_eq!(t.tokens, expected);
     }
 
@@ -237,7 +290,7 @@ mod tests {
             Token::Literal(Literal::Boolean(true)),
         ];
         expected.reverse();
-        let t = Tokenizer::new(&raw).unwrap();
+        let t = Tokenizer::new(raw).unwrap();
         assert_eq!(t.tokens, expected);
     }
 
@@ -249,7 +302,21 @@ mod tests {
             Token::Identifier("test".to_string()),
         ];
         expected.reverse();
-        let t = Tokenizer::new(&raw).unwrap();
+        let t = Tokenizer::new(raw).unwrap();
+        assert_eq!(t.tokens, expected);
+    }
+
+    #[test]
+    fn test_token_dotted_identifier() {
+        let raw = "STDEV.S(A1)";
+        let mut expected: Vec<Token> = vec![
+            Token::Identifier("STDEV.S".to_string()),
+            Token::OpenParen,
+            Token::Identifier("A1".to_string()),
+            Token::CloseParen,
+        ];
+        expected.reverse();
+        let t = Tokenizer::new(raw).unwrap();
         assert_eq!(t.tokens, expected);
     }
 
@@ -269,7 +336,39 @@ mod tests {
             Token::CloseParen,
         ];
         expected.reverse();
-        let t = Tokenizer::new(&raw).unwrap();
+        let t = Tokenizer::new(raw).unwrap();
         assert_eq!(t.tokens, expected);
     }
+
+    #[test]
+    fn test_token_references() {
+        let t = Tokenizer::new("$A$1:B$2 + #REF!").unwrap();
+        assert_eq!(t.tokens[4], Token::Identifier("$A$1".to_string()));
+        assert_eq!(t.tokens[2], Token::Identifier("B$2".to_string()));
+        assert!(matches!(&t.tokens[0], Token::Err(e) if e.code == LeadErrCode::Ref));
+    }
+
+    #[test]
+    fn test_token_whole_lines() {
+        let identifiers = |raw: &str| -> Vec<String> {
+            Tokenizer::new(raw)
+                .unwrap()
+                .tokens
+                .into_iter()
+                .rev()
+                .filter_map(|it| match it {
+                    Token::Identifier(id) => Some(id),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(identifiers("SUM(C:C)"), ["SUM", "C1", "C1048576"]);
+        assert_eq!(identifiers("$B : $D"), ["B1", "D1048576"]);
+        assert_eq!(identifiers("2:1"), ["A1", "XFD2"]);
+        assert_eq!(identifiers("A1:B2"), ["A1", "B2"]);
+        // A column and a row, or a row number outside the sheet, are not ranges
+        assert_eq!(identifiers("A:1"), ["A"]);
+        assert_eq!(Tokenizer::new("1:1048577").unwrap().len(), 3);
+    }
 }
@@ -318,6 +318,18 @@ class Grid {
 				});
 				break;
 			}
+			case 'cycles': {
+				toast.error('Circular reference', {
+					description: msg.raw
+				});
+				break;
+			}
+			case 'iterations': {
+				toast.info('Iterative calculation', {
+					description: msg.raw
+				});
+				break;
+			}
 			case 'set': {
 				if (msg.cell === undefined) {
 					console.error('Expected cell ref for SET msg from server.');
@@ -334,6 +346,7 @@ class Grid {
 				this.data.cells[pos.key()] = {
 					raw: msg.raw ?? '',
 					eval: msg.eval,
+					stale: msg.stale ?? false,
 					pos: pos,
 					temp_raw: x?.temp_raw ?? '',
 					temp_eval: x?.temp_eval ?? undefined
@@ -1,12 +1,30 @@
 import type { Position } from "./grid.svelte.ts";
 
 interface LeadMsg {
-	msg_type: 'set' | 'get' | 'error' | 'bulk' | 'eval';
+	msg_type:
+		| 'set'
+		| 'clear'
+		| 'insert'
+		| 'delete'
+		| 'get'
+		| 'error'
+		| 'bulk'
+		| 'eval'
+		| 'functions'
+		| 'recalc'
+		| 'cycles'
+		| 'iterations';
 	cell?: CellRef;
 	raw?: string;
 	eval?: Eval;
 	eval_config?: EvalConfig;
 	bulk_msgs?: Array<LeadMsg>;
+	functions?: Array<FunctionInfo>;
+	seed?: number;
+	// The cell's value is out of date, calculation being manual
+	stale?: boolean;
+	// Each cell reading the next and the last reading the first
+	cycles?: Array<Array<CellRef>>;
 }
 
 interface CellRef {
@@ -14,9 +32,14 @@ interface CellRef {
 	col: number;
 }
 
-type LiteralType = 'Number' | 'Boolean' | 'String';
+type LiteralType = 'Number' | 'Boolean' | 'String' | 'DateTime';
 type LiteralValue = number | string | boolean;
-type EvalRange = Array<Eval>;
+// Row-major values of a rows x cols block
+interface EvalRange {
+	rows: number;
+	cols: number;
+	values: Array<Eval>;
+}
 
 interface Literal {
 	type: LiteralType;
@@ -29,21 +52,46 @@ interface EvalCellRef {
 }
 
 interface LeadErr {
-	code: 'DivZero' | 'TypeErr' | 'Syntax' | 'Server' | 'Unsupported';
+	code:
+		| 'DivZero'
+		| 'TypeErr'
+		| 'Syntax'
+		| 'Server'
+		| 'Unsupported'
+		| 'Invalid'
+		| 'Ref'
+		| 'Spill'
+		| 'NotAvailable';
 	desc: string;
 	title: string;
 }
 
+type ArgKind = 'Number' | 'Text' | 'Date' | 'Range' | 'Criteria' | 'Any';
+
+interface FunctionInfo {
+	name: string;
+	arity: { min: number; max: number | null };
+	arg_kinds: Array<ArgKind>;
+	volatile: boolean;
+	description: string;
+}
+
 interface EvalConfig {
 	do_propagation: boolean;
 	force_propagation: boolean;
+	// Evaluates circular references up to this many times, 0 to leave them as errors. Kept by
+	// the connection until changed.
+	max_iterations?: number;
+	tolerance?: number;
+	// Levels with at least this many cells to recalculate are evaluated on worker threads
+	parallel_threshold?: number;
 }
 
 // Tagged union
 type Eval =
 	| { literal: Literal }
 	| { cellref: EvalCellRef }
-	| { range: Range }
+	| { range: EvalRange }
 	| { err: LeadErr }
 	| 'unset';
 
@@ -53,6 +101,7 @@ interface CellT {
 	pos: Position;
 	temp_eval?: Eval;
 	eval?: Eval;
+	stale?: boolean;
 }
 
-export type { Eval, LeadMsg, LeadErr, Literal, CellRef, LiteralValue, CellT };
+export type { Eval, LeadMsg, LeadErr, Literal, CellRef, LiteralValue, CellT, FunctionInfo };
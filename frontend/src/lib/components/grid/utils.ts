@@ -40,11 +40,25 @@ export function refToStr(row: number, col: number): string {
 	return colToStr(col) + (row + 1).toString();
 }
 
+// Serial dates count days from 1899-12-30, the fraction being the time of day (UTC)
+const SERIAL_EPOCH_MS = Date.UTC(1899, 11, 30);
+
+export function formatSerialDate(serial: number): string {
+	const iso = new Date(SERIAL_EPOCH_MS + Math.round(serial * 86400) * 1000).toISOString();
+	const date = iso.slice(0, 10);
+	const time = iso.slice(11, 19);
+
+	if (serial < 1) return time;
+	if (Number.isInteger(serial)) return date;
+	return `${date} ${time}`;
+}
+
 export function getEvalLiteral(value: Eval | undefined): LiteralValue {
 	if (value === undefined) return '';
 	if (value === 'unset') return '';
 	if ('literal' in value) {
 		if (value.literal.value == null) return 'NaN';
+		if (value.literal.type === 'DateTime') return formatSerialDate(value.literal.value as number);
 		return value.literal.value;
 	}
 	if ('cellref' in value) return getEvalLiteral(value.cellref.eval);